@@ -0,0 +1,52 @@
+//! Converts the embedded crosshair to raw BGRA ahead of time, so starting
+//! up doesn't pay for decoding a PNG. With the `capi` feature, also checks
+//! that `include/glimpse.h` still matches the C ABI in `src/capi.rs`.
+
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=assets/crosshair.png");
+    let image = image::open("assets/crosshair.png").expect("Failed to decode assets/crosshair.png").to_rgba8();
+    let (width, height) = image.dimensions();
+    let mut pixels = image.into_raw();
+    // RGBA -> BGRA (Argb8888 in little endian)
+    for chunk in pixels.chunks_exact_mut(4) {
+        chunk.swap(0, 2);
+    }
+    let out = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(Path::new(&out).join("crosshair.bgra"), pixels).unwrap();
+    std::fs::write(Path::new(&out).join("crosshair_size.rs"), format!("({width}, {height})")).unwrap();
+
+    #[cfg(feature = "capi")]
+    header(Path::new(&out));
+}
+
+/// Generates the C header into `OUT_DIR` and fails the build if the
+/// checked-in copy differs, so the two can't drift apart.
+#[cfg(feature = "capi")]
+fn header(out: &Path) {
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    println!("cargo:rerun-if-changed=include/glimpse.h");
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        header: Some("/* Generated by cbindgen from src/capi.rs; do not edit. */".into()),
+        include_guard: Some("GLIMPSE_H".into()),
+        cpp_compat: true,
+        no_includes: true,
+        sys_includes: vec!["stddef.h".into(), "stdint.h".into()],
+        // size = sizeof(glimpse_options)
+        usize_is_size_t: true,
+        ..Default::default()
+    };
+    let generated = out.join("glimpse.h");
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src("src/capi.rs")
+        .generate()
+        .expect("Failed to generate the C header")
+        .write_to_file(&generated);
+    let current = std::fs::read(&generated).unwrap();
+    if std::fs::read("include/glimpse.h").ok().as_deref() != Some(&current[..]) {
+        panic!("include/glimpse.h is out of date; replace it with {}", generated.display());
+    }
+}
@@ -0,0 +1,77 @@
+/* Generated by cbindgen from src/capi.rs; do not edit. */
+
+#ifndef GLIMPSE_H
+#define GLIMPSE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * The region was selected.
+ */
+#define GLIMPSE_OK 0
+
+/**
+ * The user cancelled the selection.
+ */
+#define GLIMPSE_CANCELLED 1
+
+/**
+ * A pointer or the options' `size` was invalid.
+ */
+#define GLIMPSE_ERROR_ARGUMENT -1
+
+/**
+ * The selection failed, e.g. without a compositor; the reason is printed
+ * to stderr.
+ */
+#define GLIMPSE_ERROR_FAILED -2
+
+/**
+ * A region in logical coordinates, as `Rect`.
+ */
+typedef struct glimpse_region {
+  int32_t x;
+  int32_t y;
+  int32_t width;
+  int32_t height;
+} glimpse_region;
+
+/**
+ * How a selection starts. Set `size` to `sizeof(glimpse_options)`; fields
+ * added later past the given size keep their defaults.
+ */
+typedef struct glimpse_options {
+  size_t size;
+  /**
+   * Region to start with selected and editable, or NULL.
+   */
+  const struct glimpse_region *initial;
+  /**
+   * Configuration file instead of the usual one, or NULL.
+   */
+  const char *config_path;
+} glimpse_options;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Shows the overlay until a region is confirmed and stores it in
+ * `region`. `options` may be NULL for the defaults. Returns `GLIMPSE_OK`,
+ * `GLIMPSE_CANCELLED`, or a negative `GLIMPSE_ERROR_*` value. Blocks the
+ * calling thread.
+ *
+ * # Safety
+ *
+ * `options` must be NULL or point to a `glimpse_options` readable for its
+ * `size`, with valid or NULL pointers in it; `region` must be writable.
+ */
+int glimpse_select_region(const struct glimpse_options *options, struct glimpse_region *region);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* GLIMPSE_H */
@@ -0,0 +1,229 @@
+//! Statistics computed from captured images.
+
+use std::fmt;
+
+use image::RgbaImage;
+
+use crate::contrast::hex;
+
+// Pixels sampled at most for the palette; larger captures are strided
+const PALETTE_SAMPLES: usize = 250_000;
+
+/// A dominant color and the share of the image it stands for.
+#[derive(Debug, Clone, Copy)]
+pub struct Swatch {
+    pub color: [u8; 3],
+    pub share: f64, // 0.0 to 1.0
+}
+
+impl Swatch {
+    pub fn hex(&self) -> String {
+        hex(self.color)
+    }
+}
+
+/// Reduces the image to at most `count` dominant colors by repeatedly
+/// splitting the widest color box (a median cut variant), most common first.
+/// Transparent pixels (gaps between outputs) are ignored.
+pub fn palette(image: &RgbaImage, count: usize) -> Vec<Swatch> {
+    let stride = (image.pixels().len() / PALETTE_SAMPLES).max(1);
+    let pixels: Vec<[u8; 3]> = image.pixels()
+        .step_by(stride)
+        .filter(|p| p[3] != 0)
+        .map(|p| [p[0], p[1], p[2]])
+        .collect();
+    let total = pixels.len();
+    if total == 0 || count == 0 {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < count {
+        // Split the box with the widest channel range, weighted by its size
+        let Some((index, channel, _)) = boxes.iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let (channel, range) = widest_channel(b);
+                (i, channel, range as usize * b.len())
+            })
+            .filter(|&(_, _, score)| score > 0)
+            .max_by_key(|&(_, _, score)| score)
+        else {
+            break;
+        };
+        // Cutting at the middle of the range rather than at the median
+        // keeps flat UI colors from being averaged with their neighbors
+        let colors = boxes.swap_remove(index);
+        let (min, max) = channel_range(&colors, channel);
+        let middle = ((min as u16 + max as u16) / 2) as u8;
+        let (lower, upper) = colors.into_iter().partition(|c| c[channel] <= middle);
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    let mut swatches: Vec<Swatch> = boxes.iter()
+        .map(|colors| {
+            let mut sum = [0u64; 3];
+            for color in colors {
+                for (s, &c) in sum.iter_mut().zip(color) {
+                    *s += c as u64;
+                }
+            }
+            let n = colors.len() as u64;
+            Swatch {
+                color: sum.map(|s| ((s + n / 2) / n) as u8),
+                share: colors.len() as f64 / total as f64,
+            }
+        })
+        .collect();
+    swatches.sort_by(|a, b| b.share.total_cmp(&a.share));
+    swatches
+}
+
+/// The channel with the largest spread, and that spread.
+fn widest_channel(colors: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = channel_range(colors, channel);
+            (channel, max - min)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap()
+}
+
+fn channel_range(colors: &[[u8; 3]], channel: usize) -> (u8, u8) {
+    colors.iter().fold((u8::MAX, 0), |(min, max), c| (min.min(c[channel]), max.max(c[channel])))
+}
+
+/// Per-channel value counts of the non-transparent pixels.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    pub red: [u64; 256],
+    pub green: [u64; 256],
+    pub blue: [u64; 256],
+    pub luminance: [u64; 256], // Rec. 709 luma
+}
+
+impl Histogram {
+    pub fn new(image: &RgbaImage) -> Self {
+        let mut histogram = Histogram { red: [0; 256], green: [0; 256], blue: [0; 256], luminance: [0; 256] };
+        for pixel in image.pixels().filter(|p| p[3] != 0) {
+            let [r, g, b, _] = pixel.0;
+            histogram.red[r as usize] += 1;
+            histogram.green[g as usize] += 1;
+            histogram.blue[b as usize] += 1;
+            let luma = 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
+            histogram.luminance[luma.round() as usize] += 1;
+        }
+        histogram
+    }
+
+    pub fn to_json(&self) -> String {
+        let array = |counts: &[u64; 256]| counts.iter().map(u64::to_string).collect::<Vec<_>>().join(",");
+        format!(
+            "{{\"red\":[{}],\"green\":[{}],\"blue\":[{}],\"luminance\":[{}]}}",
+            array(&self.red), array(&self.green), array(&self.blue), array(&self.luminance)
+        )
+    }
+}
+
+// Histogram plot size and distance from the image corner, in pixels
+const PLOT_WIDTH: u32 = 256;
+const PLOT_HEIGHT: u32 = 100;
+const PLOT_MARGIN: u32 = 8;
+
+/// Draws the RGB histogram into the bottom-left corner of `image`, on a
+/// dark box; skipped when the image is too small to hold it.
+pub fn draw_histogram(image: &mut RgbaImage, histogram: &Histogram) {
+    let (width, height) = image.dimensions();
+    if width < PLOT_WIDTH + 2 * PLOT_MARGIN || height < PLOT_HEIGHT + 2 * PLOT_MARGIN {
+        log::warn!("Capture too small for the histogram plot");
+        return;
+    }
+    let left = PLOT_MARGIN;
+    let top = height - PLOT_MARGIN - PLOT_HEIGHT;
+    let channels = [&histogram.red, &histogram.green, &histogram.blue];
+    let max = channels.iter().flat_map(|c| c.iter()).copied().max().unwrap_or(0).max(1);
+    for x in 0..PLOT_WIDTH {
+        // Bar heights per channel; overlapping bars add up towards white
+        let bars = channels.map(|counts| (counts[x as usize] * PLOT_HEIGHT as u64).div_ceil(max) as u32);
+        for y in 0..PLOT_HEIGHT {
+            let level = PLOT_HEIGHT - y;
+            let lit = bars.map(|bar| if bar >= level { 0xFF } else { 0x00 });
+            let pixel = image.get_pixel_mut(left + x, top + y);
+            // 75% opaque box so the plot stays readable on any content
+            for (channel, &value) in lit.iter().enumerate() {
+                pixel[channel] = ((pixel[channel] as u32 + 3 * value as u32) / 4) as u8;
+            }
+            pixel[3] = 0xFF;
+        }
+    }
+}
+
+/// How much changed between two captures of the same region.
+#[derive(Debug, Clone, Copy)]
+pub struct Difference {
+    pub changed: u64,
+    pub total: u64,
+    pub bounds: Option<(u32, u32, u32, u32)>, // Changed area as (min_x, min_y, max_x, max_y), inclusive
+    pub max_delta: u8,                        // Largest change of any channel
+}
+
+impl Difference {
+    /// Compares two captures of the same size.
+    pub fn new(before: &RgbaImage, after: &RgbaImage) -> Self {
+        let mut difference = Difference { changed: 0, total: 0, bounds: None, max_delta: 0 };
+        for (x, y, pixel) in after.enumerate_pixels() {
+            difference.total += 1;
+            let delta = channel_delta(before.get_pixel(x, y).0, pixel.0);
+            if delta == 0 {
+                continue;
+            }
+            difference.changed += 1;
+            difference.max_delta = difference.max_delta.max(delta);
+            difference.bounds = Some(match difference.bounds {
+                Some((min_x, min_y, max_x, max_y)) => (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)),
+                None => (x, y, x, y),
+            });
+        }
+        difference
+    }
+
+    /// Percentage of the pixels that changed.
+    pub fn percent(&self) -> f64 {
+        self.changed as f64 * 100.0 / self.total.max(1) as f64
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} pixels changed ({:.2}%)", self.changed, self.total, self.percent())?;
+        if let Some((min_x, min_y, max_x, max_y)) = self.bounds {
+            write!(f, " within {min_x},{min_y} {}x{}", max_x - min_x + 1, max_y - min_y + 1)?;
+        }
+        write!(f, ", largest channel change {}", self.max_delta)
+    }
+}
+
+/// Largest difference between two pixels in any channel.
+fn channel_delta(a: [u8; 4], b: [u8; 4]) -> u8 {
+    (0..4).map(|c| a[c].abs_diff(b[c])).max().unwrap_or(0)
+}
+
+/// Shows `after` faded to light gray, with every pixel that differs from
+/// `before` in solid red.
+pub fn difference_image(before: &RgbaImage, after: &RgbaImage) -> RgbaImage {
+    let mut image = after.clone();
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if channel_delta(before.get_pixel(x, y).0, pixel.0) != 0 {
+            pixel.0 = [0xFF, 0x00, 0x00, 0xFF];
+            continue;
+        }
+        let luma = (0.2126 * pixel[0] as f64 + 0.7152 * pixel[1] as f64 + 0.0722 * pixel[2] as f64) as u32;
+        // A quarter of the way from white, so red stands out everywhere
+        let faded = (255 - (255 - luma) / 4) as u8;
+        pixel.0 = [faded, faded, faded, 0xFF];
+    }
+    image
+}
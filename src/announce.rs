@@ -0,0 +1,59 @@
+//! Spoken feedback for `--announce`, so keyboard selections can be made
+//! without seeing them.
+//!
+//! Glimpse has no accessibility tree for a screen reader to walk, so
+//! announcements go to speech-dispatcher, which screen readers such as Orca
+//! speak through, or as desktop notifications, which they read out.
+
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use crate::selection::Rect;
+
+/// Commands tried in order, run with the announcement as `$1`.
+/// speech-dispatcher drops a message still being spoken when the next one
+/// arrives, and the hint makes notification daemons replace the last one,
+/// so key repeat doesn't pile announcements up.
+const ANNOUNCERS: &[(&str, &str)] = &[
+    ("spd-say", "spd-say --application-name Glimpse \"$1\""),
+    ("notify-send", "notify-send --app-name Glimpse --expire-time 2000 --hint string:x-canonical-private-synchronous:glimpse \"$1\""),
+];
+
+/// First known announcer found in `$PATH`, looked up once.
+fn detect() -> Option<&'static str> {
+    static COMMAND: OnceLock<Option<&'static str>> = OnceLock::new();
+    *COMMAND.get_or_init(|| {
+        let path = std::env::var_os("PATH")?;
+        let found = ANNOUNCERS.iter()
+            .find(|(binary, _)| std::env::split_paths(&path).any(|dir| dir.join(binary).is_file()))
+            .map(|&(_, command)| command);
+        if found.is_none() {
+            log::warn!("Nothing to announce with; install speech-dispatcher (spd-say) or libnotify (notify-send)");
+        }
+        found
+    })
+}
+
+/// Announces `text` without waiting for it to be spoken.
+pub fn announce(text: &str) {
+    let Some(command) = detect() else { return };
+    log::debug!("Announcing '{text}'");
+    // Backgrounded by the shell so it is never waited for
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{command} &"))
+        .arg("sh")
+        .arg(text)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    if let Err(err) = status {
+        log::warn!("Failed to announce '{text}': {err}");
+    }
+}
+
+/// How a selection is read out, e.g. "selection 800 by 600 at 100, 200".
+pub fn describe(rect: Rect) -> String {
+    format!("selection {} by {} at {}, {}", rect.width, rect.height, rect.x, rect.y)
+}
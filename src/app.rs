@@ -0,0 +1,1409 @@
+//! Application state shared by all Wayland dispatchers.
+
+use wayland_client::{Proxy, QueueHandle};
+use wayland_client::protocol::{
+    wl_compositor, wl_subcompositor, wl_shm, wl_surface, wl_seat, wl_pointer,
+    wl_keyboard, wl_output
+};
+use wayland_protocols::wp::cursor_shape::v1::client::{
+    wp_cursor_shape_device_v1::WpCursorShapeDeviceV1, wp_cursor_shape_manager_v1::WpCursorShapeManagerV1
+};
+use wayland_protocols::wp::pointer_constraints::zv1::client::{
+    zwp_confined_pointer_v1::ZwpConfinedPointerV1, zwp_pointer_constraints_v1::{Lifetime, ZwpPointerConstraintsV1}
+};
+use wayland_protocols::wp::pointer_constraints::zv1::client::zwp_locked_pointer_v1::ZwpLockedPointerV1;
+use wayland_protocols::wp::relative_pointer::zv1::client::{
+    zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1, zwp_relative_pointer_v1::ZwpRelativePointerV1
+};
+use wayland_protocols::wp::pointer_gestures::zv1::client::{
+    zwp_pointer_gesture_pinch_v1::ZwpPointerGesturePinchV1, zwp_pointer_gesture_swipe_v1::ZwpPointerGestureSwipeV1,
+    zwp_pointer_gestures_v1::ZwpPointerGesturesV1
+};
+use wayland_protocols::wp::idle_inhibit::zv1::client::{
+    zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1
+};
+use wayland_protocols::wp::single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1;
+use wayland_protocols::wp::viewporter::client::wp_viewporter::WpViewporter;
+use wayland_protocols::xdg::xdg_output::zv1::client::zxdg_output_manager_v1::ZxdgOutputManagerV1;
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1::ZwlrLayerShellV1;
+use wayland_protocols::ext::image_capture_source::v1::client::ext_output_image_capture_source_manager_v1::ExtOutputImageCaptureSourceManagerV1;
+use wayland_protocols::ext::image_copy_capture::v1::client::ext_image_copy_capture_manager_v1::ExtImageCopyCaptureManagerV1;
+use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1;
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1, zwlr_data_control_offer_v1::ZwlrDataControlOfferV1
+};
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::{
+    ZwlrLayerSurfaceV1, Anchor as WlrAnchor, KeyboardInteractivity
+};
+
+use crate::announce;
+use crate::capture::CaptureFrame;
+use crate::clipboard::ClipboardSource;
+use crate::config::{ButtonAction, Command, Config, GeometryFormat, BTN_RIGHT};
+use crate::graphics::buffer::ShmBuffer;
+use crate::graphics::cursor::Cursor;
+use crate::graphics::dim_layer::DimLayer;
+use std::time::Instant;
+
+use image::RgbaImage;
+
+use crate::graphics::drawing::{draw_frame, Dim, Fade, Label, LabelStyle, Scene, Spotlight, FLASH, INVERTED_ALPHA};
+use crate::graphics::text::{TextRenderer, TEXT_SIZE};
+use crate::hud::{Anchor, Layout};
+use crate::process::Position;
+use crate::keys::{self, KeyAction};
+use crate::capture::Snapshot;
+use crate::contrast::{self, Contrast};
+use crate::measure::Measurement;
+use crate::history;
+use crate::output::{self, Output};
+use crate::preview::{Choice, Preview};
+use crate::recording::{InputEvent, Recorder};
+use crate::script::Script;
+use crate::windows::{self, Window};
+use crate::selection::{Handle, Rect};
+use crate::snap::{self, Snap};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectionState {
+    Idle,
+    Selecting { start: (i32, i32) }, // Global logical coordinates
+    Editing { rect: Rect },          // Drawn, adjustable from the keyboard until confirmed
+}
+
+/// The clickable buttons on the overlay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Accept, // Next to a selection being edited
+    Cancel,
+    Mode(Command), // On the toolbar: select, window or output
+    Copy,          // On the toolbar, toggling what happens to the selection
+    Save,
+}
+
+/// How a selection was confirmed, as recorded in the history.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Drag,
+    Click,  // Two-click mode
+    Output, // The whole hovered output
+    Window, // The hovered window
+    Edit,   // Adjusted from the keyboard
+}
+
+impl Method {
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Drag => "drag",
+            Method::Click => "click",
+            Method::Output => "output",
+            Method::Window => "window",
+            Method::Edit => "edit",
+        }
+    }
+}
+
+// Text height of the labels naming each output in output mode
+const OUTPUT_LABEL_SIZE: f32 = TEXT_SIZE * 2.5;
+
+// Text height of the countdown before a delayed capture
+const COUNTDOWN_SIZE: f32 = TEXT_SIZE * 6.0;
+
+// Longest window label shown in window mode, in characters
+const WINDOW_LABEL_CHARS: usize = 80;
+
+// Space between buttons in a row
+const BUTTON_GAP: i32 = 6;
+
+// Where the toolbar goes when shown with its key rather than configured
+const DEFAULT_TOOLBAR: Anchor = Anchor::Screen(Position::TopRight);
+
+// Pixels moved per key press, and with Alt held
+const KEY_STEP: i32 = 10;
+const KEY_FINE_STEP: i32 = 1;
+
+/// A full-screen layer surface covering a single output.
+pub struct Overlay {
+    pub output: Option<wl_output::WlOutput>, // None lets the compositor pick
+    pub surface: wl_surface::WlSurface,
+    pub layer_surface: ZwlrLayerSurfaceV1,
+    pub idle_inhibitor: Option<ZwpIdleInhibitorV1>,
+    pub width: u32,
+    pub height: u32,
+    pub needs_redraw: bool,
+    pub full_redraw: bool, // The next frame repaints the whole surface
+    pub fade: Fade,
+    pub dim_alpha: u8, // Of the background outside the selection
+    pub preferred_scale: Option<i32>, // Sent by wl_surface v6 compositors
+    pub hidden: bool, // Showing nothing while peeking
+    pub size_requested: bool, // Asked for the output's size after an unusable configure
+    pub passes_input: bool, // Input goes to the windows underneath, while counting down
+
+    // Buffering
+    pub shm: wl_shm::WlShm,
+    pub buffers: Vec<ShmBuffer>, // Two, or three while the compositor holds both
+    pub prev_scene_rect: Option<(u32, u32, u32, u32)>, // (min_x, min_y, max_x, max_y)
+    pub background_cache: Option<Vec<u8>>, // Clean background
+    pub frozen: Option<Vec<u8>>, // The screen as it was, with --freeze
+    pub dim_layer: Option<DimLayer>, // Replaces the shm buffers when single-pixel buffers are available
+}
+
+impl Overlay {
+    fn new(output: Option<wl_output::WlOutput>, state: &AppState, qh: &QueueHandle<AppState>) -> Self {
+        let compositor = state.compositor.as_ref().expect("No wl_compositor global");
+        let layer_shell = state.layer_shell.as_ref().expect("No zwlr_layer_shell_v1 global");
+        let config = &state.config;
+
+        let surface = compositor.create_surface(qh, ());
+        let layer_surface = layer_shell.get_layer_surface(&surface, output.as_ref(), config.layer, config.namespace.clone(), qh, ());
+        layer_surface.set_size(0, 0);
+        layer_surface.set_anchor(WlrAnchor::Top | WlrAnchor::Bottom | WlrAnchor::Left | WlrAnchor::Right);
+        layer_surface.set_exclusive_zone(-1);
+        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+        // The input region is left at its default, the whole surface, so the
+        // overlay takes the pointer even where it is fully transparent
+        surface.commit();
+
+        // Frozen and spotlight backgrounds differ from pixel to pixel, so
+        // only a uniform one can be a single stretched pixel
+        let dim_layer = (!config.freeze && !matches!(config.dim, Dim::Spotlight { .. }))
+            .then(|| DimLayer::new(&surface, state, qh))
+            .flatten();
+
+        // Keep the screen from blanking or locking while the overlay is up
+        let idle_inhibitor = state.idle_inhibit_manager.as_ref()
+            .map(|manager| manager.create_inhibitor(&surface, qh, ()));
+
+        Self {
+            output,
+            surface,
+            layer_surface,
+            idle_inhibitor,
+            width: 0,
+            height: 0,
+            needs_redraw: true,
+            full_redraw: false,
+            // Without dimming there is nothing to fade in
+            fade: if config.animate && config.dim.alpha() > 0 && !config.freeze { Fade::Waiting } else { Fade::Done },
+            dim_alpha: config.dim.alpha(),
+            preferred_scale: None,
+            hidden: false,
+            size_requested: false,
+            passes_input: false,
+            shm: state.shm.clone().expect("No wl_shm global"),
+            buffers: Vec::new(),
+            prev_scene_rect: None,
+            background_cache: None,
+            frozen: None,
+            dim_layer,
+        }
+    }
+
+    /// Lets pointer and keyboard input through to the windows underneath.
+    fn pass_input(&mut self, compositor: &wl_compositor::WlCompositor, qh: &QueueHandle<AppState>) {
+        let region = compositor.create_region(qh, ());
+        self.surface.set_input_region(Some(&region));
+        region.destroy();
+        self.layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+        self.surface.commit();
+        self.passes_input = true;
+    }
+
+    pub fn destroy(self) {
+        for buffer in self.buffers {
+            buffer.destroy();
+        }
+        if let Some(layer) = self.dim_layer {
+            layer.destroy();
+        }
+        if let Some(inhibitor) = self.idle_inhibitor {
+            inhibitor.destroy();
+        }
+        self.layer_surface.destroy();
+        self.surface.destroy();
+    }
+}
+
+pub struct AppState {
+    pub config: Config,
+
+    // Globals
+    pub compositor: Option<wl_compositor::WlCompositor>,
+    pub subcompositor: Option<wl_subcompositor::WlSubcompositor>,
+    pub shm: Option<wl_shm::WlShm>,
+    pub layer_shell: Option<ZwlrLayerShellV1>,
+    pub seat: Option<wl_seat::WlSeat>,
+    pub xdg_output_manager: Option<ZxdgOutputManagerV1>,
+    pub cursor_shape_manager: Option<WpCursorShapeManagerV1>,
+    pub idle_inhibit_manager: Option<ZwpIdleInhibitManagerV1>,
+    pub screencopy_manager: Option<ZwlrScreencopyManagerV1>,
+    pub image_capture_source_manager: Option<ExtOutputImageCaptureSourceManagerV1>,
+    pub image_copy_capture_manager: Option<ExtImageCopyCaptureManagerV1>, // Preferred over screencopy
+    pub data_control_manager: Option<ZwlrDataControlManagerV1>,
+    pub viewporter: Option<WpViewporter>,
+    pub pointer_constraints: Option<ZwpPointerConstraintsV1>,
+    pub relative_pointer_manager: Option<ZwpRelativePointerManagerV1>,
+    pub pointer_gestures: Option<ZwpPointerGesturesV1>,
+    pub single_pixel_buffer_manager: Option<WpSinglePixelBufferManagerV1>,
+    pub outputs: Vec<Output>,
+
+    // Objects
+    pub overlays: Vec<Overlay>,
+    pub pointer: Option<wl_pointer::WlPointer>,
+    pub keyboard: Option<wl_keyboard::WlKeyboard>,
+    pub cursor: Option<Cursor>,
+    pub text: Option<TextRenderer>, // Loaded when something shows labels
+    pub script: Option<Script>,
+    pub snapshot: Option<Snapshot>,  // Screen contents from before the overlays were mapped
+    pub windows: Vec<Window>, // Visible windows, topmost first, in window mode
+    pub cursor_shape_device: Option<WpCursorShapeDeviceV1>,
+    pub pointer_surface: Option<wl_surface::WlSurface>, // Overlay surface the pointer is over
+    pub confined_pointer: Option<ZwpConfinedPointerV1>, // Held during a drag with --confine
+    pub relative_pointer: Option<ZwpRelativePointerV1>,
+    pub locked_pointer: Option<ZwpLockedPointerV1>, // Holds the real pointer still in precision mode
+    pub pinch: Option<ZwpPointerGesturePinchV1>,
+    pub swipe: Option<ZwpPointerGestureSwipeV1>,
+    pub captures: Vec<CaptureFrame>,
+    pub clipboard: Option<ClipboardSource>, // Offered until another client takes the selection
+    pub clipboard_offer: Option<ZwlrDataControlOfferV1>, // Current clipboard contents, when watched
+
+    // State
+    pub running: bool,
+    pub error: Option<String>, // Why the session ended early, returned once the loop stops
+    pub result: Option<Rect>, // Confirmed selection, set when the loop ends
+    pub flash: Option<Instant>, // When the confirmed selection started flashing
+    pub countdown: Option<Instant>, // When the confirmed selection is captured, with --delay
+    pub points: Option<((i32, i32), (i32, i32))>, // Confirmed points when the command picks points
+    pub selection_state: SelectionState,
+    pub edit_handle: Option<Handle>, // Edge or corner the arrows move while editing; None moves it all
+    pub modifiers: u8, // keys::MOD_* flags currently held
+    pub peek_key: Option<u32>, // Held down to hide the overlay
+    pub snap: Snap, // What the end of a drag snaps to, cycled with Tab
+    pub drag_offset: (i32, i32), // From the pointer to the corner it moves, after swapping the anchor
+    pub pointer_lost: bool,  // The seat dropped its pointer mid-session
+    pub keyboard_lost: bool, // The seat dropped its keyboard mid-session
+    pub current_pos: (i32, i32), // Global logical coordinates
+    pub precise_pos: Option<(f64, f64)>, // Position moved by slowed-down deltas while Alt is held in a drag
+    pub gesture: Option<(Rect, f64, (f64, f64))>, // Rectangle being edited when a pinch or swipe began, with its scale and offset since
+    pub scroll: (f64, f64), // Scrolling not yet applied to the edited rectangle, under a pixel
+    pub prev_pos: (i32, i32),
+    pub prev_selection_state: SelectionState,
+    pub spare_buffers: Vec<ShmBuffer>, // From closed overlays, for the next ones to reuse
+    pub recorder: Option<Recorder>, // With --record-input
+    pub preview: Option<Preview>, // The capture awaiting a choice, with --confirm-preview
+    pub captured: Option<RgbaImage>, // Capture saved from the preview, to be written
+    pub toolbar: bool, // Whether the mode toolbar is shown
+    capture_requested: bool, // Capturing was asked for other than by copying or saving
+}
+
+impl AppState {
+    pub fn new(config: Config) -> Self {
+        Self {
+            snap: config.snap,
+            toolbar: config.hud.toolbar.is_some(),
+            capture_requested: config.capture && !config.copy && !config.save,
+            config,
+            compositor: None,
+            subcompositor: None,
+            shm: None,
+            layer_shell: None,
+            seat: None,
+            xdg_output_manager: None,
+            cursor_shape_manager: None,
+            idle_inhibit_manager: None,
+            screencopy_manager: None,
+            image_capture_source_manager: None,
+            image_copy_capture_manager: None,
+            data_control_manager: None,
+            viewporter: None,
+            pointer_constraints: None,
+            relative_pointer_manager: None,
+            pointer_gestures: None,
+            single_pixel_buffer_manager: None,
+            outputs: Vec::new(),
+            overlays: Vec::new(),
+            pointer: None,
+            keyboard: None,
+            cursor: None,
+            text: None,
+            script: None,
+            snapshot: None,
+            windows: Vec::new(),
+            cursor_shape_device: None,
+            pointer_surface: None,
+            confined_pointer: None,
+            relative_pointer: None,
+            locked_pointer: None,
+            pinch: None,
+            swipe: None,
+            captures: Vec::new(),
+            clipboard: None,
+            clipboard_offer: None,
+            running: true,
+            error: None,
+            result: None,
+            flash: None,
+            countdown: None,
+            points: None,
+            selection_state: SelectionState::Idle,
+            edit_handle: None,
+            modifiers: 0,
+            peek_key: None,
+            drag_offset: (0, 0),
+            pointer_lost: false,
+            keyboard_lost: false,
+            current_pos: (0, 0),
+            precise_pos: None,
+            gesture: None,
+            scroll: (0.0, 0.0),
+            prev_pos: (0, 0),
+            prev_selection_state: SelectionState::Idle,
+            spare_buffers: Vec::new(),
+            recorder: None,
+            preview: None,
+            captured: None,
+        }
+    }
+
+    /// Whether the screen can be captured, with either protocol.
+    pub fn can_capture(&self) -> bool {
+        let ext = self.image_capture_source_manager.is_some() && self.image_copy_capture_manager.is_some();
+        ext || self.screencopy_manager.is_some()
+    }
+
+    /// Creates the xdg-output companion for every output that lacks one.
+    pub fn bind_xdg_outputs(&mut self, qh: &QueueHandle<Self>) {
+        let Some(manager) = &self.xdg_output_manager else { return };
+        for output in self.outputs.iter_mut().filter(|o| o.xdg_output.is_none()) {
+            output.xdg_output = Some(manager.get_xdg_output(&output.wl_output, qh, ()));
+        }
+    }
+
+    /// Maps one overlay surface per known output, or a single one on the
+    /// compositor's choice of output if none were advertised.
+    pub fn create_overlays(&mut self, qh: &QueueHandle<Self>) {
+        let mut outputs: Vec<_> = self.outputs.iter().map(|o| Some(o.wl_output.clone())).collect();
+        if outputs.is_empty() {
+            outputs.push(None);
+        }
+        for output in outputs {
+            self.open_overlay(output, qh);
+        }
+    }
+
+    /// Maps an overlay, handing it buffers left by closed ones.
+    fn open_overlay(&mut self, output: Option<wl_output::WlOutput>, qh: &QueueHandle<Self>) {
+        let mut overlay = Overlay::new(output, self, qh);
+        let spare = self.spare_buffers.len().saturating_sub(2);
+        overlay.buffers = self.spare_buffers.split_off(spare);
+        self.overlays.push(overlay);
+    }
+
+    /// Unmaps every overlay, keeping their buffers for the next ones, e.g.
+    /// for the next request over RPC.
+    pub fn close_overlays(&mut self) {
+        for mut overlay in self.overlays.drain(..) {
+            self.spare_buffers.append(&mut overlay.buffers);
+            overlay.destroy();
+        }
+    }
+
+    /// Maps an overlay onto an output that appeared after startup.
+    pub fn add_overlay(&mut self, wl_output: &wl_output::WlOutput, qh: &QueueHandle<Self>) {
+        if self.overlays.is_empty() {
+            return; // Not mapped yet; create_overlays will cover it
+        }
+        self.open_overlay(Some(wl_output.clone()), qh);
+    }
+
+    /// Top-left corner of an overlay in global logical coordinates.
+    fn overlay_origin(&self, overlay: &Overlay) -> (i32, i32) {
+        overlay.output.as_ref()
+            .and_then(|wl_output| self.outputs.iter().find(|o| &o.wl_output == wl_output))
+            .map_or((0, 0), |output| output.to_global((0, 0)))
+    }
+
+    /// The frozen screen under an overlay as BGRA pixels, with `--freeze`.
+    pub fn frozen_pixels(&self, overlay: &Overlay) -> Option<Vec<u8>> {
+        let snapshot = self.snapshot.as_ref().filter(|_| self.config.freeze)?;
+        let (x, y) = self.overlay_origin(overlay);
+        Some(snapshot.logical_bgra(Rect::new(x, y, overlay.width as i32, overlay.height as i32)))
+    }
+
+    /// Integer scale the compositor prefers for an overlay surface, or else
+    /// that of the output it is on.
+    pub fn surface_scale(&self, surface: &wl_surface::WlSurface) -> i32 {
+        let overlay = self.overlays.iter().find(|o| &o.surface == surface);
+        if let Some(scale) = overlay.and_then(|overlay| overlay.preferred_scale) {
+            return scale;
+        }
+        overlay
+            .and_then(|overlay| overlay.output.as_ref())
+            .and_then(|wl_output| self.outputs.iter().find(|o| &o.wl_output == wl_output))
+            .map_or(1, |output| output.scale)
+    }
+
+    /// Maps coordinates local to an overlay surface into global logical coordinates.
+    pub fn to_global(&self, surface: &wl_surface::WlSurface, local: (i32, i32)) -> (i32, i32) {
+        match self.overlays.iter().find(|o| &o.surface == surface) {
+            Some(overlay) => {
+                let (x, y) = self.overlay_origin(overlay);
+                (x + local.0, y + local.1)
+            }
+            None => local,
+        }
+    }
+
+    /// The current selection rectangle in global logical coordinates.
+    pub fn selection_rect(&self) -> Option<Rect> {
+        if self.config.command.picks_points() {
+            return None;
+        }
+        let rect = match self.selection_state {
+            SelectionState::Selecting { start } => Rect::from_corners(start, self.drag_end()),
+            SelectionState::Editing { rect } => rect,
+            SelectionState::Idle => return None,
+        };
+        Some(self.script.as_ref().map_or(rect, |script| script.selection_changed(rect)))
+    }
+
+    /// Where the moving corner of a drag is, after snapping.
+    pub fn drag_end(&self) -> (i32, i32) {
+        let pointer = (self.current_pos.0 + self.drag_offset.0, self.current_pos.1 + self.drag_offset.1);
+        match self.snap {
+            Snap::Off => pointer,
+            Snap::Grid => snap::to_grid(pointer, self.config.snap_grid),
+            Snap::Windows => snap::to_edges(pointer, self.windows.iter().map(|window| window.rect)),
+            Snap::Outputs => snap::to_edges(pointer, self.overlays.iter().map(|overlay| {
+                let (x, y) = self.overlay_origin(overlay);
+                Rect::new(x, y, overlay.width as i32, overlay.height as i32)
+            })),
+        }
+    }
+
+    /// Moves on to the next snapping target, skipping windows when they
+    /// can't be listed.
+    fn cycle_snap(&mut self, steps: i32) {
+        self.snap = self.snap.cycle(steps);
+        if self.snap == Snap::Windows && self.windows.is_empty() {
+            match windows::visible() {
+                Ok(visible) => self.windows = visible,
+                Err(err) => {
+                    log::warn!("Not snapping to windows: {err}");
+                    self.snap = self.snap.cycle(steps);
+                }
+            }
+        }
+        if self.text.is_none() {
+            self.text = TextRenderer::load(self.config.font.as_deref());
+        }
+        self.request_redraw();
+    }
+
+    /// Logical geometry of the output under the pointer.
+    pub fn hovered_output_rect(&self) -> Option<Rect> {
+        let surface = self.pointer_surface.as_ref()?;
+        let overlay = self.overlays.iter().find(|o| &o.surface == surface)?;
+        let (x, y) = self.overlay_origin(overlay);
+        Some(Rect::new(x, y, overlay.width as i32, overlay.height as i32))
+    }
+
+    /// Starts a drag at the pointer, keeping the pointer on its output
+    /// until the drag ends with `--confine`.
+    pub fn start_drag(&mut self, qh: &QueueHandle<Self>) {
+        self.drag_offset = (0, 0);
+        self.selection_state = SelectionState::Selecting { start: self.drag_end() };
+        self.request_redraw();
+        self.prev_pos = self.current_pos;
+        self.prev_selection_state = self.selection_state;
+        self.confine_pointer(qh);
+    }
+
+    /// Confines the pointer to the output under it during a drag, with
+    /// `--confine`.
+    pub fn confine_pointer(&mut self, qh: &QueueHandle<Self>) {
+        let dragging = matches!(self.selection_state, SelectionState::Selecting { .. });
+        if !self.config.confine || !dragging || self.confined_pointer.is_some() || self.locked_pointer.is_some() {
+            return;
+        }
+        if let (Some(constraints), Some(pointer), Some(surface)) = (&self.pointer_constraints, &self.pointer, &self.pointer_surface) {
+            // The whole surface, which covers exactly one output
+            self.confined_pointer = Some(constraints.confine_pointer(surface, pointer, None, Lifetime::Oneshot, qh, ()));
+        }
+    }
+
+    /// Lets the pointer leave the output again after a confined drag.
+    fn release_pointer(&mut self) {
+        if let Some(confined) = self.confined_pointer.take() {
+            confined.destroy();
+        }
+    }
+
+    /// Creates the relative pointer for precision mode, once both the
+    /// pointer and the manager are there.
+    pub fn bind_relative_pointer(&mut self, qh: &QueueHandle<Self>) {
+        if let (None, Some(manager), Some(pointer)) = (&self.relative_pointer, &self.relative_pointer_manager, &self.pointer) {
+            self.relative_pointer = Some(manager.get_relative_pointer(pointer, qh, ()));
+        }
+    }
+
+    /// Creates the pinch and swipe gestures for editing, once both the
+    /// pointer and the manager are there.
+    pub fn bind_gestures(&mut self, qh: &QueueHandle<Self>) {
+        if let (None, Some(gestures), Some(pointer)) = (&self.pinch, &self.pointer_gestures, &self.pointer) {
+            self.pinch = Some(gestures.get_pinch_gesture(pointer, qh, ()));
+            self.swipe = Some(gestures.get_swipe_gesture(pointer, qh, ()));
+        }
+    }
+
+    /// Starts a pinch or swipe over the rectangle being edited.
+    pub fn begin_gesture(&mut self) {
+        if let SelectionState::Editing { rect } = self.selection_state {
+            self.gesture = Some((rect, 1.0, (0.0, 0.0)));
+        }
+    }
+
+    /// Scales the rectangle from when the gesture began around its center
+    /// by the pinch's `scale`, and moves it by the deltas so far.
+    pub fn update_gesture(&mut self, scale: Option<f64>, (dx, dy): (f64, f64)) {
+        let Some((start, ref mut total, ref mut offset)) = self.gesture else { return };
+        *total = scale.unwrap_or(*total);
+        *offset = (offset.0 + dx, offset.1 + dy);
+        let width = ((start.width as f64 * *total).round() as i32).max(1);
+        let height = ((start.height as f64 * *total).round() as i32).max(1);
+        let rect = Rect::new(start.x + (start.width - width) / 2, start.y + (start.height - height) / 2, width, height);
+        let rect = rect.translate(offset.0.round() as i32, offset.1.round() as i32);
+        if self.selection_state != (SelectionState::Editing { rect }) {
+            self.selection_state = SelectionState::Editing { rect };
+            self.request_redraw();
+        }
+    }
+
+    /// Ends a pinch or swipe, putting the rectangle back if it was
+    /// cancelled.
+    pub fn end_gesture(&mut self, cancelled: bool) {
+        if let Some((start, ..)) = self.gesture.take().filter(|_| cancelled) {
+            self.selection_state = SelectionState::Editing { rect: start };
+            self.request_redraw();
+        }
+    }
+
+    /// Moves the rectangle being edited by scrolling, e.g. with two fingers
+    /// on a touchpad.
+    pub fn scroll_selection(&mut self, (dx, dy): (f64, f64)) {
+        let SelectionState::Editing { rect } = self.selection_state else { return };
+        let (x, y) = (self.scroll.0 + dx, self.scroll.1 + dy);
+        let (step_x, step_y) = (x.trunc(), y.trunc());
+        self.scroll = (x - step_x, y - step_y);
+        if step_x != 0.0 || step_y != 0.0 {
+            self.selection_state = SelectionState::Editing { rect: rect.translate(step_x as i32, step_y as i32) };
+            self.request_redraw();
+        }
+    }
+
+    /// Moves the end of a drag by a pointer delta slowed down to
+    /// `--precision` while Alt is held. The real pointer is locked in place
+    /// meanwhile, when the compositor allows it.
+    pub fn precise_motion(&mut self, (dx, dy): (f64, f64), qh: &QueueHandle<Self>) {
+        if self.modifiers & keys::MOD_ALT == 0 || !matches!(self.selection_state, SelectionState::Selecting { .. }) {
+            return;
+        }
+        let (x, y) = *self.precise_pos.get_or_insert((self.current_pos.0 as f64, self.current_pos.1 as f64));
+        if self.locked_pointer.is_none() {
+            // A surface takes one constraint at a time; confining resumes
+            // once Alt is let go
+            self.release_pointer();
+            if let (Some(constraints), Some(pointer), Some(surface)) = (&self.pointer_constraints, &self.pointer, &self.pointer_surface) {
+                self.locked_pointer = Some(constraints.lock_pointer(surface, pointer, None, Lifetime::Oneshot, qh, ()));
+            }
+        }
+        let position = (x + dx * self.config.precision, y + dy * self.config.precision);
+        self.precise_pos = Some(position);
+        self.current_pos = (position.0.round() as i32, position.1.round() as i32);
+        if self.current_pos != self.prev_pos {
+            self.request_redraw();
+        }
+        self.prev_pos = self.current_pos;
+    }
+
+    /// Leaves precision mode, asking for the real pointer to reappear where
+    /// the slowed-down one got to.
+    pub fn end_precise_motion(&mut self) {
+        if self.precise_pos.take().is_none() {
+            return;
+        }
+        let Some(locked) = self.locked_pointer.take() else { return };
+        if let Some(overlay) = self.pointer_surface.as_ref().and_then(|s| self.overlays.iter().find(|o| &o.surface == s)) {
+            let (x, y) = self.overlay_origin(overlay);
+            let (px, py) = self.current_pos;
+            locked.set_cursor_position_hint((px - x) as f64, (py - y) as f64);
+            overlay.surface.commit();
+        }
+        locked.destroy();
+    }
+
+    /// Ends the drag in progress. With `--adjust` the rectangle stays
+    /// editable, otherwise it is confirmed right away.
+    pub fn end_drag(&mut self) {
+        self.release_pointer();
+        self.end_precise_motion();
+        if self.config.command.picks_points() {
+            return self.finish_points();
+        }
+        let Some(rect) = self.selection_rect() else { return };
+        if self.config.adjust {
+            self.selection_state = SelectionState::Editing { rect };
+            self.request_redraw();
+        } else {
+            self.confirm(rect, self.pointer_method());
+        }
+    }
+
+    /// Confirms the selection in progress and exits.
+    pub fn finish_selection(&mut self) {
+        if self.config.command.picks_points() {
+            return self.finish_points();
+        }
+        // Both corners are global, so this is the union across outputs
+        let method = match self.selection_state {
+            SelectionState::Editing { .. } => Method::Edit,
+            _ => self.pointer_method(),
+        };
+        if let Some(rect) = self.selection_rect() {
+            self.confirm(rect, method);
+        }
+    }
+
+    /// The topmost window at a point in global logical coordinates.
+    pub fn window_at(&self, (x, y): (i32, i32)) -> Option<&Window> {
+        self.windows.iter().find(|window| window.rect.intersection(&Rect::new(x, y, 1, 1)).is_some())
+    }
+
+    /// The topmost window under the pointer.
+    pub fn hovered_window(&self) -> Option<&Window> {
+        self.window_at(self.current_pos)
+    }
+
+    /// Confirms the window under the pointer and exits.
+    pub fn select_hovered_window(&mut self) {
+        if let Some(rect) = self.hovered_window().map(|window| window.rect) {
+            self.confirm(rect, Method::Window);
+        }
+    }
+
+    /// Confirms the whole output under the pointer and exits.
+    pub fn select_hovered_output(&mut self) {
+        if let Some(rect) = self.hovered_output_rect() {
+            self.confirm(rect, Method::Output);
+        }
+    }
+
+    /// Confirms the two picked points and exits.
+    fn finish_points(&mut self) {
+        if let SelectionState::Selecting { start } = self.selection_state {
+            self.points = Some((start, self.current_pos));
+            self.running = false;
+        }
+    }
+
+    fn pointer_method(&self) -> Method {
+        if self.config.two_click { Method::Click } else { Method::Drag }
+    }
+
+    fn confirm(&mut self, rect: Rect, method: Method) {
+        if self.flash.is_some() || self.countdown.is_some() {
+            return; // Already confirmed
+        }
+        let rect = self.script.as_ref().map_or(rect, |script| script.confirm(rect, method.name()));
+        let rect = match output::bounds(&self.outputs) {
+            _ if self.config.allow_offscreen => rect,
+            Some(bounds) => rect.intersection(&bounds).unwrap_or_else(|| {
+                log::warn!("The selection lies outside every output; keeping it as is");
+                rect
+            }),
+            None => rect,
+        };
+        if self.config.history {
+            let output = output::containing(&self.outputs, &rect).and_then(|o| o.name.as_deref());
+            if let Err(err) = history::record(history::Entry::new(rect, output, method.name())) {
+                log::warn!("Failed to record the selection in the history: {err}");
+            }
+        }
+        self.result = Some(rect);
+        if self.config.announce {
+            announce::announce("selection confirmed");
+        }
+        if let Some(delay) = self.config.delay {
+            // Counted down over the selection, with input going to the
+            // windows underneath; draw_pending exits once it's over
+            self.countdown = Some(Instant::now() + delay);
+            if self.text.is_none() {
+                self.text = TextRenderer::load(self.config.font.as_deref());
+            }
+            self.request_redraw();
+        } else if self.config.animate {
+            // Flash the area as feedback; draw_pending exits once it's over
+            self.flash = Some(Instant::now());
+            self.request_redraw();
+        } else {
+            self.running = false;
+        }
+    }
+
+    /// Acts on a clicked overlay button.
+    fn click(&mut self, button: Button) {
+        match button {
+            Button::Accept => self.finish_selection(),
+            Button::Cancel => self.cancel(),
+            Button::Mode(command) => self.set_mode(command),
+            Button::Copy | Button::Save => {
+                if button == Button::Copy {
+                    self.config.copy = !self.config.copy;
+                } else {
+                    self.config.save = !self.config.save;
+                }
+                self.config.capture = self.capture_requested || self.config.copy || self.config.save;
+                self.request_redraw();
+            }
+        }
+    }
+
+    /// Switches between selecting a region, a window and an output from the
+    /// toolbar, dropping a selection in progress.
+    fn set_mode(&mut self, command: Command) {
+        if command == Command::Window && self.windows.is_empty() {
+            match windows::visible() {
+                Ok(visible) => self.windows = visible,
+                Err(err) => log::warn!("No windows to select: {err}"),
+            }
+        }
+        self.config.command = command;
+        self.selection_state = SelectionState::Idle;
+        self.edit_handle = None;
+        self.request_redraw();
+    }
+
+    /// Shows or hides the mode toolbar.
+    fn toggle_toolbar(&mut self) {
+        self.toolbar = !self.toolbar;
+        if self.text.is_none() {
+            self.text = TextRenderer::load(self.config.font.as_deref());
+        }
+        self.request_redraw();
+    }
+
+    pub fn cancel(&mut self) {
+        if self.flash.is_some() || self.countdown.is_some() {
+            return; // Too late, the selection is confirmed
+        }
+        self.result = None;
+        self.running = false;
+        if self.config.announce {
+            announce::announce("selection cancelled");
+        }
+    }
+
+    /// Handles pointer or keyboard input from the seat, recording it with
+    /// `--record-input`. Ignored while a recording is replayed.
+    pub fn input(&mut self, event: InputEvent, qh: &QueueHandle<Self>) {
+        if self.config.replay_input.is_some() {
+            return;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(event);
+        }
+        self.apply_input(event, qh);
+    }
+
+    /// Handles pointer or keyboard input, live or replayed.
+    pub fn apply_input(&mut self, event: InputEvent, qh: &QueueHandle<Self>) {
+        if self.preview.is_some() {
+            self.preview_input(event);
+            return;
+        }
+        match event {
+            // Precision mode moves by relative events instead
+            InputEvent::Motion(..) if self.precise_pos.is_some() => {}
+            InputEvent::Motion(x, y) => {
+                self.current_pos = (x, y);
+                // The highlight follows the window under the pointer
+                let window_rect = |point| self.window_at(point).map(|window| window.rect);
+                if self.config.command == Command::Window && window_rect(self.current_pos) != window_rect(self.prev_pos) {
+                    self.request_redraw();
+                }
+                // The spotlight and pointer readout follow the pointer
+                let follows = matches!(self.config.dim, Dim::Spotlight { .. }) || self.config.hud.coordinates.is_some();
+                // Otherwise only redraw on motion during selection
+                if (follows || matches!(self.selection_state, SelectionState::Selecting { .. })) &&
+                   (self.current_pos != self.prev_pos || self.selection_state != self.prev_selection_state) {
+                    self.request_redraw();
+                }
+                // Buttons light up under the pointer
+                if (self.config.hud.buttons.is_some() || self.toolbar) && self.button_at(self.current_pos) != self.button_at(self.prev_pos) {
+                    self.request_redraw();
+                }
+                self.prev_pos = self.current_pos;
+                self.prev_selection_state = self.selection_state;
+            }
+            InputEvent::Relative(dx, dy) => self.precise_motion((dx, dy), qh),
+            InputEvent::Button { button, pressed } => {
+                let clicked = if pressed { self.button_at(self.current_pos) } else { None };
+                match self.config.button_action(button) {
+                    Some(ButtonAction::Select) if clicked.is_some() => self.click(clicked.unwrap()),
+                    Some(ButtonAction::Select) if pressed && self.config.command == Command::Output => {
+                        self.select_hovered_output();
+                    }
+                    Some(ButtonAction::Select) if pressed && self.config.command == Command::Window => {
+                        self.select_hovered_window();
+                    }
+                    // A new drag also replaces a rectangle being adjusted
+                    Some(ButtonAction::Select) if pressed && !matches!(self.selection_state, SelectionState::Selecting { .. }) => {
+                        self.start_drag(qh);
+                        // A pick is done with its first click
+                        if self.config.command == Command::Pick {
+                            self.end_drag();
+                        }
+                    }
+                    // Dragging ends on release; two-click mode ends on the second
+                    // press. A release after clicking a button ends nothing.
+                    Some(ButtonAction::Select) if pressed == self.config.two_click && matches!(self.selection_state, SelectionState::Selecting { .. }) => {
+                        self.end_drag();
+                    }
+                    Some(ButtonAction::Confirm) if pressed => self.finish_selection(),
+                    Some(ButtonAction::Cancel) if pressed => self.cancel(),
+                    Some(ButtonAction::Output) if pressed => self.select_hovered_output(),
+                    _ => {}
+                }
+            }
+            InputEvent::Axis(dx, dy) => self.scroll_selection((dx, dy)),
+            InputEvent::Key { key, pressed: true } => match self.config.key_action(key, self.modifiers) {
+                Some((KeyAction::Peek, _)) => self.set_peek(Some(key)),
+                Some((action, fine)) => self.handle_key_action(action, fine),
+                None => {}
+            },
+            InputEvent::Key { key, pressed: false } if self.peek_key == Some(key) => self.set_peek(None),
+            InputEvent::Key { .. } => {}
+            InputEvent::Modifiers(modifiers) => {
+                self.modifiers = modifiers;
+                if self.modifiers & keys::MOD_ALT == 0 {
+                    self.end_precise_motion();
+                    self.confine_pointer(qh);
+                }
+            }
+        }
+    }
+
+    /// Handles input while the capture preview is shown: only its choices
+    /// and the confirm and cancel bindings do anything.
+    fn preview_input(&mut self, event: InputEvent) {
+        let choice = match event {
+            InputEvent::Motion(x, y) => {
+                self.current_pos = (x, y);
+                None
+            }
+            InputEvent::Button { button, pressed: true } => match self.config.button_action(button) {
+                Some(ButtonAction::Select) => self.preview.as_ref().and_then(|preview| preview.choice_at(self, self.current_pos)),
+                Some(ButtonAction::Confirm) => Some(Choice::Save),
+                Some(ButtonAction::Cancel) => Some(Choice::Cancel),
+                _ => None,
+            },
+            InputEvent::Key { key, pressed: true } => match self.config.key_action(key, self.modifiers) {
+                Some((KeyAction::Confirm, _)) => Some(Choice::Save),
+                Some((KeyAction::Retry, _)) => Some(Choice::Retry),
+                Some((KeyAction::Cancel, _)) => Some(Choice::Cancel),
+                _ => None,
+            },
+            InputEvent::Modifiers(modifiers) => {
+                self.modifiers = modifiers;
+                None
+            }
+            _ => None,
+        };
+        if let (Some(choice), Some(preview)) = (choice, &mut self.preview) {
+            preview.choice = Some(choice);
+            self.running = false;
+        }
+    }
+
+    /// Applies a bound key action. Moving or resizing while idle starts a
+    /// keyboard selection in the middle of the hovered output.
+    pub fn handle_key_action(&mut self, action: KeyAction, fine: bool) {
+        let step = if fine { KEY_FINE_STEP } else { KEY_STEP };
+        match action {
+            KeyAction::Cancel => self.cancel(),
+            // Held keys are handled by the keyboard dispatch, and retrying
+            // only applies to the capture preview
+            KeyAction::Peek | KeyAction::Retry => {}
+            KeyAction::Confirm => self.finish_selection(),
+            KeyAction::Toolbar => self.toggle_toolbar(),
+            // Points are only picked with the pointer
+            KeyAction::Move(..) | KeyAction::Resize(..) | KeyAction::CycleHandle(_) | KeyAction::Preset(_) | KeyAction::SwapAnchor
+                if self.config.command.picks_points() => {}
+            KeyAction::SwapAnchor => {
+                let SelectionState::Selecting { start } = self.selection_state else { return };
+                // The pointer can't be moved, so the corner it drags keeps
+                // its distance from it instead
+                let end = self.drag_end();
+                self.drag_offset = (start.0 - self.current_pos.0, start.1 - self.current_pos.1);
+                self.selection_state = SelectionState::Selecting { start: end };
+                self.request_redraw();
+            }
+            // Leave a mouse drag in progress alone
+            KeyAction::Preset(_) if matches!(self.selection_state, SelectionState::Selecting { .. }) => {}
+            KeyAction::Preset(index) => {
+                let Some(output) = self.edit_output_rect() else { return };
+                self.set_selection(self.config.presets[index].rect(output));
+            }
+            KeyAction::CycleHandle(steps) => match self.selection_state {
+                SelectionState::Editing { .. } => {
+                    self.edit_handle = Handle::cycle(self.edit_handle, steps);
+                    self.request_redraw();
+                }
+                SelectionState::Selecting { .. } => self.cycle_snap(steps),
+                SelectionState::Idle => {}
+            },
+            KeyAction::Move(dx, dy) | KeyAction::Resize(dx, dy) => {
+                let Some(mut rect) = self.editable_rect() else { return };
+                if let (KeyAction::Move(..), Some(handle)) = (action, self.edit_handle) {
+                    rect = handle.drag(rect, dx * step, dy * step);
+                } else if let KeyAction::Move(..) = action {
+                    rect = rect.translate(dx * step, dy * step);
+                } else {
+                    rect.width = (rect.width + dx * step).max(1);
+                    rect.height = (rect.height + dy * step).max(1);
+                }
+                self.selection_state = SelectionState::Editing { rect };
+                self.announce_selection(rect);
+                self.request_redraw();
+            }
+        }
+    }
+
+    /// The selection to move or resize: the one being edited, or a new one
+    /// in the middle of the hovered output. `None` during a mouse drag,
+    /// which is left alone.
+    pub fn editable_rect(&self) -> Option<Rect> {
+        match self.selection_state {
+            SelectionState::Editing { rect } => Some(rect),
+            SelectionState::Idle => self.default_edit_rect(),
+            SelectionState::Selecting { .. } => None,
+        }
+    }
+
+    /// Replaces the selection with `rect`, to be edited or confirmed.
+    pub fn set_selection(&mut self, rect: Rect) {
+        self.selection_state = SelectionState::Editing { rect };
+        self.edit_handle = None;
+        self.announce_selection(rect);
+        self.request_redraw();
+    }
+
+    /// Reads out a selection made with the keyboard, with `--announce`.
+    fn announce_selection(&self, rect: Rect) {
+        if self.config.announce {
+            announce::announce(&announce::describe(rect));
+        }
+    }
+
+    /// Hides the overlay while `key` is held, or shows it again.
+    pub fn set_peek(&mut self, key: Option<u32>) {
+        if self.peek_key != key {
+            self.peek_key = key;
+            self.request_redraw();
+        }
+    }
+
+    /// Drops the pointer after the seat lost it. A drag in progress is kept
+    /// for the keyboard to adjust and confirm.
+    pub fn remove_pointer(&mut self) {
+        let Some(pointer) = self.pointer.take() else { return };
+        self.release_pointer();
+        self.end_precise_motion();
+        if let Some(relative) = self.relative_pointer.take() {
+            relative.destroy();
+        }
+        if let Some(pinch) = self.pinch.take() {
+            pinch.destroy();
+        }
+        if let Some(swipe) = self.swipe.take() {
+            swipe.destroy();
+        }
+        self.gesture = None;
+        if let Some(device) = self.cursor_shape_device.take() {
+            device.destroy();
+        }
+        if pointer.version() >= 3 {
+            pointer.release();
+        }
+        self.pointer_surface = None;
+        self.pointer_lost = true;
+        if let SelectionState::Selecting { .. } = self.selection_state {
+            self.selection_state = match self.selection_rect() {
+                Some(rect) if !self.config.command.picks_points() => SelectionState::Editing { rect },
+                _ => SelectionState::Idle,
+            };
+        }
+        self.show_input_notice();
+    }
+
+    /// Drops the keyboard after the seat lost it. Unless a button already
+    /// cancels, the right one takes over from Escape.
+    pub fn remove_keyboard(&mut self) {
+        let Some(keyboard) = self.keyboard.take() else { return };
+        if keyboard.version() >= 3 {
+            keyboard.release();
+        }
+        self.modifiers = 0;
+        self.set_peek(None);
+        self.keyboard_lost = true;
+        let bindings = &mut self.config.button_bindings;
+        if !bindings.iter().any(|&(_, action)| action == ButtonAction::Cancel) && !bindings.iter().any(|&(b, _)| b == BTN_RIGHT) {
+            bindings.push((BTN_RIGHT, ButtonAction::Cancel));
+        }
+        self.show_input_notice();
+    }
+
+    /// Loads the font for the notice about a lost device, if nothing else
+    /// needed it, and redraws.
+    fn show_input_notice(&mut self) {
+        if self.text.is_none() {
+            self.text = TextRenderer::load(self.config.font.as_deref());
+        }
+        self.request_redraw();
+    }
+
+    /// What is left to work with after losing the pointer or keyboard.
+    fn input_notice(&self) -> Option<&'static str> {
+        match (self.pointer.is_none() && self.pointer_lost, self.keyboard.is_none() && self.keyboard_lost) {
+            (false, false) => None,
+            (true, true) => Some("Pointer and keyboard disconnected · waiting for one to come back"),
+            (true, false) if self.config.command.picks_points() => Some("Pointer disconnected · Esc to cancel"),
+            (true, false) => Some("Pointer disconnected · Arrows to adjust · Enter to confirm · Esc to cancel"),
+            (false, true) if self.config.button_action(BTN_RIGHT) == Some(ButtonAction::Cancel) => {
+                Some("Keyboard disconnected · Right-click to cancel")
+            }
+            (false, true) => Some("Keyboard disconnected"),
+        }
+    }
+
+    /// The hovered output, or the first one, for keyboard selections.
+    fn edit_output_rect(&self) -> Option<Rect> {
+        self.hovered_output_rect().or_else(|| {
+            let overlay = self.overlays.first()?;
+            let (x, y) = self.overlay_origin(overlay);
+            Some(Rect::new(x, y, overlay.width as i32, overlay.height as i32))
+        })
+    }
+
+    /// A rectangle covering the central quarter of the hovered (or first) output.
+    fn default_edit_rect(&self) -> Option<Rect> {
+        let output = self.edit_output_rect()?;
+        Some(Rect::new(output.x + output.width / 4, output.y + output.height / 4, output.width / 2, output.height / 2))
+    }
+
+    /// Schedules a redraw on every overlay; those the selection does not touch
+    /// end up with an empty dirty region and skip drawing.
+    pub fn request_redraw(&mut self) {
+        for overlay in &mut self.overlays {
+            overlay.needs_redraw = true;
+        }
+    }
+
+    /// What the overlays show, in global logical coordinates.
+    fn scene(&self) -> Scene {
+        if self.peek_key.is_some() {
+            return Scene { hidden: true, ..Scene::default() };
+        }
+        if let (Some(started), Some(rect)) = (self.flash, self.result) {
+            let progress = (started.elapsed().as_secs_f32() / FLASH.as_secs_f32()).min(1.0);
+            let alpha = (0x80 as f32 * (1.0 - progress)) as u8;
+            return Scene {
+                selection: Some(rect),
+                flash: Some(alpha),
+                corner_radius: self.config.corner_radius,
+                shadow: self.config.shadow,
+                ..Scene::default()
+            };
+        }
+        if let (Some(due), Some(rect)) = (self.countdown, self.result) {
+            let seconds = due.saturating_duration_since(Instant::now()).as_secs_f32().ceil().max(1.0);
+            let mut scene = Scene {
+                selection: Some(rect),
+                corner_radius: self.config.corner_radius,
+                shadow: self.config.shadow,
+                animated: true,
+                ..Scene::default()
+            };
+            if let Some(text) = &self.text {
+                let mut layout = Layout::new(Some(rect), self.current_pos, self.pointer_screen());
+                let mask = text.render(&seconds.to_string(), COUNTDOWN_SIZE);
+                scene.labels.extend(layout.place(Anchor::Inside(Position::Center), mask));
+            }
+            return scene;
+        }
+        if let Some(preview) = &self.preview {
+            let (thumbnail, buttons) = preview.layout(self);
+            return Scene {
+                pictures: vec![thumbnail],
+                labels: buttons.into_iter().map(|(label, _)| label).collect(),
+                ..Scene::default()
+            };
+        }
+        let hovered = match self.config.command {
+            Command::Output => self.hovered_output_rect(),
+            Command::Window => self.hovered_window().map(|window| window.rect),
+            _ => None,
+        };
+        let spotlight = match self.config.dim {
+            Dim::Spotlight { radius } => Some(Spotlight { center: self.current_pos, radius }),
+            Dim::Uniform | Dim::Off | Dim::Invert => None,
+        };
+        let mut scene = Scene {
+            selection: self.selection_rect().or(hovered),
+            corner_radius: self.config.corner_radius,
+            // A shadow would darken the part left clear
+            shadow: self.config.shadow.filter(|_| self.config.dim != Dim::Invert),
+            spotlight,
+            inverted: self.config.dim == Dim::Invert,
+            guides: self.config.guides,
+            focus: match (self.selection_state, self.edit_handle) {
+                (SelectionState::Editing { rect }, Some(handle)) => Some(handle.position(rect)),
+                _ => None,
+            },
+            ..Scene::default()
+        };
+        let pointer = self.current_pos;
+        let screen = self.pointer_screen();
+        let mut layout = Layout::new(scene.selection, pointer, screen);
+        let hud = self.config.hud;
+        // First, so that button_at finds them where they are drawn
+        scene.labels.extend(self.buttons(&mut layout).into_iter().map(|(label, _)| label));
+        if let Some(text) = &self.text {
+            if let (Some(rect), Some(anchor)) = (scene.selection, hud.size) {
+                let mask = text.render(&format!("{} × {}", rect.width, rect.height), TEXT_SIZE);
+                scene.labels.extend(layout.place(anchor, mask).map(|label| self.contrasting(label, rect)));
+            }
+            if let Some(anchor) = hud.coordinates {
+                let readout = match self.snapshot.as_ref().and_then(|snapshot| snapshot.sample(pointer)) {
+                    Some(color) => format!("{}, {}  {}", pointer.0, pointer.1, contrast::hex(color)),
+                    None => format!("{}, {}", pointer.0, pointer.1),
+                };
+                let mask = text.render(&readout, TEXT_SIZE);
+                scene.labels.extend(layout.place(anchor, mask));
+            }
+            if let Some(anchor) = hud.hints {
+                let mask = text.render(self.hints(), TEXT_SIZE);
+                scene.labels.extend(layout.place(anchor, mask));
+            }
+            if let Some(notice) = self.input_notice() {
+                let mask = text.render(notice, TEXT_SIZE);
+                scene.labels.extend(layout.place(Anchor::Screen(Position::Center), mask));
+            }
+        }
+        if self.config.command == Command::Output {
+            scene.labels.extend(self.output_labels());
+        }
+        if let (Some(window), Some(text)) = (self.hovered_window(), &self.text) {
+            // Above the window, or inside it when that would be off screen
+            let mask = text.render(&truncate(&window.label(), WINDOW_LABEL_CHARS), TEXT_SIZE);
+            let label = Label { position: (0, 0), text: mask, style: LabelStyle::DARK };
+            let above = window.rect.y - label.rect().height - 6;
+            let anchor = if screen.is_some_and(|screen| above >= screen.y) {
+                Anchor::Outside(Position::TopLeft)
+            } else {
+                Anchor::Inside(Position::TopLeft)
+            };
+            scene.labels.extend(layout.place(anchor, label.text));
+        }
+        let script_text = scene.selection.zip(self.script.as_ref())
+            .and_then(|(rect, script)| script.hud_text(rect));
+        if let (Some(script_text), Some(text)) = (script_text, &self.text) {
+            // Under the selection's bottom-left corner
+            let mask = text.render(&script_text, TEXT_SIZE);
+            scene.labels.extend(layout.place(Anchor::Outside(Position::BottomLeft), mask));
+        }
+        let SelectionState::Selecting { start } = self.selection_state else { return scene };
+        let end = self.current_pos;
+        if let (Snap::Grid | Snap::Windows | Snap::Outputs, Some(anchor), Some(text)) = (self.snap, hud.readout, &self.text) {
+            let mask = text.render(&format!("Snap: {}", self.snap.name()), TEXT_SIZE);
+            scene.labels.extend(layout.place(anchor, mask));
+        }
+        let label = match self.config.command {
+            Command::Measure => {
+                scene.lines.push((start, end));
+                Some(Measurement::new(start, end, &self.outputs).label())
+            }
+            Command::Contrast => self.snapshot.as_ref()
+                .and_then(|snapshot| Some(Contrast::new(snapshot.sample(start)?, snapshot.sample(end)?)))
+                .map(|contrast| contrast.label()),
+            _ => None,
+        };
+        if let (Some(label), Some(anchor), Some(text)) = (label, hud.readout, &self.text) {
+            scene.labels.extend(layout.place(anchor, text.render(&label, TEXT_SIZE)));
+        }
+        scene
+    }
+
+    /// The output under the pointer, for labels in a screen corner.
+    fn pointer_screen(&self) -> Option<Rect> {
+        let (x, y) = self.current_pos;
+        output::containing(&self.outputs, &Rect::new(x, y, 1, 1)).map(|output| {
+            let (x, y, width, height) = output.logical_geometry();
+            Rect::new(x, y, width, height)
+        })
+    }
+
+    /// The toolbar, when shown, and the Accept and Cancel buttons of a
+    /// selection being edited.
+    fn buttons(&self, layout: &mut Layout) -> Vec<(Label, Button)> {
+        let mut buttons = Vec::new();
+        if self.toolbar && matches!(self.config.command, Command::Select | Command::Window | Command::Output) {
+            let command = self.config.command;
+            let mut items = vec![
+                ("Region", Button::Mode(Command::Select), command == Command::Select),
+                ("Window", Button::Mode(Command::Window), command == Command::Window),
+                ("Output", Button::Mode(Command::Output), command == Command::Output),
+            ];
+            // A geometry template can't be combined with captures
+            if self.config.geometry_format == GeometryFormat::Plain {
+                items.push(("Copy", Button::Copy, self.config.copy));
+                items.push(("Save", Button::Save, self.config.save));
+            }
+            let anchor = self.config.hud.toolbar.unwrap_or(DEFAULT_TOOLBAR);
+            buttons.extend(self.button_row(layout, anchor, &items));
+        }
+        if let (Some(anchor), SelectionState::Editing { .. }) = (self.config.hud.buttons, self.selection_state) {
+            buttons.extend(self.button_row(layout, anchor, &[("Accept", Button::Accept, false), ("Cancel", Button::Cancel, false)]));
+        }
+        buttons
+    }
+
+    /// A row of buttons at `anchor`, each with its name, what it does and
+    /// whether it is switched on. The one under the pointer is lit up.
+    fn button_row(&self, layout: &mut Layout, anchor: Anchor, items: &[(&str, Button, bool)]) -> Vec<(Label, Button)> {
+        let Some(text) = &self.text else { return Vec::new() };
+        let mut buttons: Vec<_> = items.iter()
+            .map(|&(name, button, _)| (Label { position: (0, 0), text: text.render(name, TEXT_SIZE), style: LabelStyle::DARK }, button))
+            .collect();
+        let width = buttons.iter().map(|(label, _)| label.rect().width + BUTTON_GAP).sum::<i32>() - BUTTON_GAP;
+        let height = buttons.iter().map(|(label, _)| label.rect().height).max().unwrap_or(0);
+        let Some((mut x, y)) = layout.reserve(anchor, Rect::new(0, 0, width, height)) else { return Vec::new() };
+        for ((label, _), &(_, _, active)) in buttons.iter_mut().zip(items) {
+            label.position = (x, y);
+            let rect = label.rect();
+            let (px, py) = self.current_pos;
+            let hovered = rect.intersection(&Rect::new(px, py, 1, 1)).is_some();
+            let style = match (active, hovered) {
+                (true, _) => LabelStyle::LIGHT,
+                (false, true) => LabelStyle::HOVERED,
+                (false, false) => LabelStyle::DARK,
+            };
+            label.style = style.rounded(rect.height / 2);
+            x += rect.width + BUTTON_GAP;
+        }
+        buttons
+    }
+
+    /// The button at `point`, if any.
+    fn button_at(&self, (x, y): (i32, i32)) -> Option<Button> {
+        let mut layout = Layout::new(self.selection_rect(), self.current_pos, self.pointer_screen());
+        self.buttons(&mut layout).into_iter()
+            .find(|(label, _)| label.rect().intersection(&Rect::new(x, y, 1, 1)).is_some())
+            .map(|(_, button)| button)
+    }
+
+    /// Rounds `label` and colors it to stand out from the content under it.
+    fn contrasting(&self, label: Label, selection: Rect) -> Label {
+        let area = label.rect();
+        // The content shows through whichever dim layer covers the label
+        let inside = selection.intersection(&area) == Some(area);
+        let dim = match (inside, self.config.dim) {
+            (true, Dim::Invert) => INVERTED_ALPHA,
+            (true, _) => 0,
+            (false, dim) => dim.alpha(),
+        };
+        let visible = 1.0 - dim as f64 / 255.0;
+        let light = self.snapshot.as_ref()
+            .and_then(|snapshot| snapshot.mean_luminance(area))
+            .is_some_and(|luminance| luminance * visible > 0.18);
+        let style = if light { LabelStyle::DARK } else { LabelStyle::LIGHT };
+        Label { style: style.rounded(area.height / 2), ..label }
+    }
+
+    /// A large label in the middle of each output naming it, for telling
+    /// them apart before picking one.
+    fn output_labels(&self) -> Vec<Label> {
+        let Some(text) = &self.text else { return Vec::new() };
+        self.outputs.iter().enumerate()
+            .map(|(index, output)| {
+                let (x, y, width, height) = output.logical_geometry();
+                let name = output.name.clone().unwrap_or_else(|| format!("Output {}", index + 1));
+                let (pixels_wide, pixels_high) = output.transformed_size();
+                let scale = format!("{:.2}", output.fractional_scale());
+                let scale = scale.trim_end_matches('0').trim_end_matches('.');
+                let mask = text.render(&format!("{name} · {pixels_wide}×{pixels_high} · {scale}×"), OUTPUT_LABEL_SIZE);
+                let label = Label { position: (0, 0), text: mask, style: LabelStyle::DARK.rounded(8) };
+                let size = label.rect();
+                let position = (x + (width - size.width) / 2, y + (height - size.height) / 2);
+                Label { position, ..label }
+            })
+            .collect()
+    }
+
+    /// How to use the current mode, for the hints label.
+    fn hints(&self) -> &'static str {
+        match self.config.command {
+            Command::Measure => "Drag to measure · Esc to cancel",
+            Command::Contrast => "Drag between two colors · Esc to cancel",
+            Command::Pick => "Click a pixel · Esc to cancel",
+            Command::Output => "Click an output · Esc to cancel",
+            Command::Window => "Click a window · Esc to cancel",
+            _ if matches!(self.selection_state, SelectionState::Editing { .. }) => {
+                "Arrows to move · Shift+Arrows to resize · Tab to pick an edge · Enter to confirm · Esc to cancel"
+            }
+            _ => "Drag to select · Esc to cancel",
+        }
+    }
+
+    /// Draws every overlay that has a pending redraw.
+    pub fn draw_pending(&mut self, qh: &QueueHandle<Self>) {
+        if self.flash.is_some_and(|started| started.elapsed() >= FLASH) {
+            self.flash = None;
+            self.running = false;
+            return;
+        }
+        if self.countdown.is_some_and(|due| Instant::now() >= due) {
+            self.countdown = None;
+            self.running = false;
+            return;
+        }
+        if let (Some(_), Some(compositor)) = (self.countdown, &self.compositor) {
+            for overlay in self.overlays.iter_mut().filter(|o| !o.passes_input) {
+                overlay.pass_input(compositor, qh);
+            }
+        }
+        if !self.overlays.iter().any(|o| o.needs_redraw) {
+            return;
+        }
+        let scene = self.scene();
+        for idx in 0..self.overlays.len() {
+            if !self.overlays[idx].needs_redraw {
+                continue;
+            }
+            let (x, y) = self.overlay_origin(&self.overlays[idx]);
+            let overlay = &mut self.overlays[idx];
+            // Cleared first, as drawing sets it again when no buffer is free
+            overlay.needs_redraw = false;
+            draw_frame(overlay, &scene.translate(-x, -y), qh);
+        }
+    }
+}
+
+/// Shortens `text` to at most `max` characters, ending it with an ellipsis
+/// when cut.
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max.saturating_sub(1)) {
+        Some((end, _)) if text.chars().count() > max => format!("{}…", &text[..end]),
+        _ => text.to_string(),
+    }
+}
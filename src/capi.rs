@@ -0,0 +1,105 @@
+//! The C ABI, with the `capi` feature: `glimpse_select_region` for programs
+//! linking `libglimpse.so`. `include/glimpse.h` declares it, and build.rs
+//! checks that the header still matches this file.
+
+#![allow(non_camel_case_types)]
+
+use std::ffi::{c_char, c_int, CStr, OsStr};
+use std::mem::{offset_of, size_of};
+use std::os::unix::ffi::OsStrExt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr::addr_of;
+
+use crate::select::{self, Options};
+use crate::selection::Rect;
+
+/// The region was selected.
+pub const GLIMPSE_OK: c_int = 0;
+/// The user cancelled the selection.
+pub const GLIMPSE_CANCELLED: c_int = 1;
+/// A pointer or the options' `size` was invalid.
+pub const GLIMPSE_ERROR_ARGUMENT: c_int = -1;
+/// The selection failed, e.g. without a compositor; the reason is printed
+/// to stderr.
+pub const GLIMPSE_ERROR_FAILED: c_int = -2;
+
+/// A region in logical coordinates, as `Rect`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct glimpse_region {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// How a selection starts. Set `size` to `sizeof(glimpse_options)`; fields
+/// added later past the given size keep their defaults.
+#[repr(C)]
+pub struct glimpse_options {
+    pub size: usize,
+    /// Region to start with selected and editable, or NULL.
+    pub initial: *const glimpse_region,
+    /// Configuration file instead of the usual one, or NULL.
+    pub config_path: *const c_char,
+}
+
+/// Shows the overlay until a region is confirmed and stores it in
+/// `region`. `options` may be NULL for the defaults. Returns `GLIMPSE_OK`,
+/// `GLIMPSE_CANCELLED`, or a negative `GLIMPSE_ERROR_*` value. Blocks the
+/// calling thread.
+///
+/// # Safety
+///
+/// `options` must be NULL or point to a `glimpse_options` readable for its
+/// `size`, with valid or NULL pointers in it; `region` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn glimpse_select_region(options: *const glimpse_options, region: *mut glimpse_region) -> c_int {
+    if region.is_null() {
+        return GLIMPSE_ERROR_ARGUMENT;
+    }
+    let Some(options) = read_options(options) else {
+        return GLIMPSE_ERROR_ARGUMENT;
+    };
+    // Unwinding into C is undefined, so a panic is one more failure
+    match panic::catch_unwind(AssertUnwindSafe(|| select::select_region(&options))) {
+        Ok(Ok(Some(rect))) => {
+            region.write(glimpse_region { x: rect.x, y: rect.y, width: rect.width, height: rect.height });
+            GLIMPSE_OK
+        }
+        Ok(Ok(None)) => GLIMPSE_CANCELLED,
+        Ok(Err(err)) => {
+            eprintln!("glimpse: {err}");
+            GLIMPSE_ERROR_FAILED
+        }
+        Err(_) => GLIMPSE_ERROR_FAILED,
+    }
+}
+
+/// The options as the library takes them, or `None` if they can't be read.
+unsafe fn read_options(options: *const glimpse_options) -> Option<Options> {
+    let mut result = Options::default();
+    if options.is_null() {
+        return Some(result);
+    }
+    // Older callers pass a smaller struct, so fields are read one by one and
+    // only within its size
+    let size = addr_of!((*options).size).read();
+    if size < size_of::<usize>() {
+        return None;
+    }
+    let has = |offset: usize, field: usize| size >= offset + field;
+    if has(offset_of!(glimpse_options, initial), size_of::<*const glimpse_region>()) {
+        if let Some(initial) = addr_of!((*options).initial).read().as_ref() {
+            result.initial = Some(Rect { x: initial.x, y: initial.y, width: initial.width, height: initial.height });
+        }
+    }
+    if has(offset_of!(glimpse_options, config_path), size_of::<*const c_char>()) {
+        let path = addr_of!((*options).config_path).read();
+        if !path.is_null() {
+            result.config = Some(PathBuf::from(OsStr::from_bytes(CStr::from_ptr(path).to_bytes())));
+        }
+    }
+    Some(result)
+}
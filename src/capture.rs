@@ -0,0 +1,516 @@
+//! Capturing the confirmed selection with ext-image-copy-capture or, where
+//! the compositor lacks it, wlr-screencopy.
+//!
+//! Every output the selection touches is captured separately, then the
+//! frames are brought into logical orientation and stitched into a single
+//! image at the highest scale among them.
+//!
+//! Frames in 10-bit formats are read into 16-bit images with
+//! `--deep-color`, and reduced to 8 bits otherwise.
+
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+
+use image::{imageops, ImageBuffer, Pixel, Rgba, RgbaImage};
+use wayland_client::{EventQueue, QueueHandle};
+use wayland_client::protocol::{wl_buffer, wl_output::Transform, wl_shm, wl_shm_pool};
+use wayland_protocols::ext::image_capture_source::v1::client::ext_image_capture_source_v1::ExtImageCaptureSourceV1;
+use wayland_protocols::ext::image_copy_capture::v1::client::{
+    ext_image_copy_capture_frame_v1::ExtImageCopyCaptureFrameV1, ext_image_copy_capture_manager_v1::Options,
+    ext_image_copy_capture_session_v1::ExtImageCopyCaptureSessionV1
+};
+use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1;
+
+use crate::app::AppState;
+use crate::compositor;
+use crate::config::Command;
+use crate::contrast;
+use crate::selection::Rect;
+
+/// A capture with 16 bits per channel.
+pub type DeepImage = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+const NO_CAPTURE: &str = "Compositor lacks both ext_image_copy_capture_manager_v1 and zwlr_screencopy_manager_v1";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameState {
+    Pending,
+    Ready,
+    Failed,
+}
+
+/// Buffer parameters offered by the compositor for a frame.
+#[derive(Debug, Clone, Copy)]
+pub struct BufferInfo {
+    pub format: wl_shm::Format,
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32,
+}
+
+/// The protocol a frame is captured with.
+pub enum Backend {
+    // Copies just the region
+    Wlr(ZwlrScreencopyFrameV1),
+    // Copies the whole output, cut down to the region afterwards
+    Ext {
+        index: usize, // Of the capture, as the frame's user data
+        source: ExtImageCaptureSourceV1,
+        session: ExtImageCopyCaptureSessionV1,
+        frame: Option<ExtImageCopyCaptureFrameV1>,
+        output: Rect, // Global logical geometry of the output
+        size: (u32, u32), // Buffer size the session asks for
+        format: Option<wl_shm::Format>,
+    },
+}
+
+pub struct CaptureFrame {
+    pub backend: Backend,
+    pub region: Rect, // Global logical region this frame covers
+    pub transform: Transform,
+    pub info: Option<BufferInfo>,
+    pub y_invert: bool,
+    pub state: FrameState,
+    shm_pool: Option<wl_shm_pool::WlShmPool>,
+    buffer: Option<wl_buffer::WlBuffer>,
+    mmap: Option<memmap2::MmapMut>,
+}
+
+impl CaptureFrame {
+    /// Allocates a shm buffer matching the offered parameters and asks the
+    /// compositor to copy the frame into it.
+    pub fn start_copy(&mut self, shm: &wl_shm::WlShm, qh: &QueueHandle<AppState>) {
+        let Some(info) = self.info else {
+            log::error!("Compositor offered no supported shm format for capture");
+            self.state = FrameState::Failed;
+            return;
+        };
+        if let Err(err) = self.allocate(shm, info, qh) {
+            log::error!("Failed to allocate capture buffer: {err}");
+            self.state = FrameState::Failed;
+            return;
+        }
+        let buffer = self.buffer.as_ref().unwrap();
+        match &mut self.backend {
+            Backend::Wlr(frame) => frame.copy(buffer),
+            Backend::Ext { index, session, frame, .. } => {
+                let new = session.create_frame(qh, *index);
+                new.attach_buffer(buffer);
+                new.damage_buffer(0, 0, info.width as i32, info.height as i32);
+                new.capture();
+                *frame = Some(new);
+            }
+        }
+    }
+
+    /// Takes the constraints an ext-image-copy-capture session settled on,
+    /// once it sends `done`, and starts copying.
+    pub fn session_done(&mut self, shm: &wl_shm::WlShm, qh: &QueueHandle<AppState>) {
+        let Backend::Ext { size: (width, height), format, .. } = self.backend else { return };
+        if self.buffer.is_some() {
+            return; // Constraints changed after the copy started; it fails or completes on its own
+        }
+        self.info = format.map(|format| BufferInfo { format, width, height, stride: width * 4 });
+        self.start_copy(shm, qh);
+    }
+
+    fn allocate(&mut self, shm: &wl_shm::WlShm, info: BufferInfo, qh: &QueueHandle<AppState>) -> std::io::Result<()> {
+        let size = info.stride * info.height;
+        let file = tempfile::tempfile()?;
+        file.set_len(size as u64)?;
+        let fd = unsafe { BorrowedFd::borrow_raw(file.as_raw_fd()) };
+        let pool = shm.create_pool(fd, size as i32, qh, ());
+        let buffer = pool.create_buffer(0, info.width as i32, info.height as i32, info.stride as i32, info.format, qh, ());
+        let mmap = unsafe { memmap2::MmapMut::map_mut(&file)? };
+        self.shm_pool = Some(pool);
+        self.buffer = Some(buffer);
+        self.mmap = Some(mmap);
+        Ok(())
+    }
+
+    /// Converts the copied frame to RGBA in logical orientation.
+    fn to_image(&self) -> Option<RgbaImage> {
+        self.decode(|format, px| Rgba(rgba8(format, px)))
+    }
+
+    /// Converts the copied frame to 16-bit RGBA in logical orientation.
+    fn to_deep_image(&self) -> Option<DeepImage> {
+        self.decode(|format, px| Rgba(rgba16(format, px)))
+    }
+
+    /// Converts every pixel of the copied frame with `pixel`, then brings
+    /// the image into logical orientation and cuts out the region.
+    fn decode<P: Pixel + 'static>(&self, pixel: impl Fn(wl_shm::Format, &[u8]) -> P) -> Option<ImageBuffer<P, Vec<P::Subpixel>>> {
+        let info = self.info?;
+        let data = self.mmap.as_ref()?;
+        let mut image = ImageBuffer::new(info.width, info.height);
+        for (y, row) in data.chunks_exact(info.stride as usize).take(info.height as usize).enumerate() {
+            for (x, px) in row.chunks_exact(4).take(info.width as usize).enumerate() {
+                image.put_pixel(x as u32, y as u32, pixel(info.format, px));
+            }
+        }
+        if self.y_invert {
+            imageops::flip_vertical_in_place(&mut image);
+        }
+        let image = untransform(image, self.transform);
+        let Backend::Ext { output, .. } = self.backend else { return Some(image) };
+        // Cut the region out of the whole output, at the frame's own scale
+        let scale_x = image.width() as f64 / output.width.max(1) as f64;
+        let scale_y = image.height() as f64 / output.height.max(1) as f64;
+        let x = ((self.region.x - output.x) as f64 * scale_x).round() as u32;
+        let y = ((self.region.y - output.y) as f64 * scale_y).round() as u32;
+        let width = (self.region.width as f64 * scale_x).round() as u32;
+        let height = (self.region.height as f64 * scale_y).round() as u32;
+        Some(imageops::crop_imm(&image, x, y, width, height).to_image())
+    }
+
+    fn destroy(self) {
+        match self.backend {
+            Backend::Wlr(frame) => frame.destroy(),
+            Backend::Ext { source, session, frame, .. } => {
+                if let Some(frame) = frame {
+                    frame.destroy();
+                }
+                session.destroy();
+                source.destroy();
+            }
+        }
+        if let Some(buffer) = self.buffer {
+            buffer.destroy();
+        }
+        if let Some(pool) = self.shm_pool {
+            pool.destroy();
+        }
+    }
+}
+
+/// Whether a shm format can be converted by [`CaptureFrame`].
+pub fn is_supported_format(format: wl_shm::Format) -> bool {
+    matches!(
+        format,
+        wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888 | wl_shm::Format::Abgr8888 | wl_shm::Format::Xbgr8888
+    ) || is_deep_format(format)
+}
+
+/// Whether a shm format has more than 8 bits per color channel.
+fn is_deep_format(format: wl_shm::Format) -> bool {
+    matches!(
+        format,
+        wl_shm::Format::Argb2101010 | wl_shm::Format::Xrgb2101010 | wl_shm::Format::Abgr2101010 | wl_shm::Format::Xbgr2101010
+    )
+}
+
+/// Whether to take an `offered` shm format over the one picked so far:
+/// the first supported one, unless a later one matches the wanted depth.
+pub fn prefer_format(picked: Option<wl_shm::Format>, offered: wl_shm::Format, deep: bool) -> bool {
+    match picked {
+        _ if !is_supported_format(offered) => false,
+        None => true,
+        Some(picked) => is_deep_format(picked) != deep && is_deep_format(offered) == deep,
+    }
+}
+
+/// One pixel as 8-bit RGBA.
+fn rgba8(format: wl_shm::Format, px: &[u8]) -> [u8; 4] {
+    // Little endian: Xrgb/Argb are stored as BGRA, Xbgr/Abgr as RGBA
+    match format {
+        wl_shm::Format::Argb8888 => [px[2], px[1], px[0], px[3]],
+        wl_shm::Format::Xrgb8888 => [px[2], px[1], px[0], 0xFF],
+        wl_shm::Format::Abgr8888 => [px[0], px[1], px[2], px[3]],
+        format if is_deep_format(format) => rgba16(format, px).map(|value| (value >> 8) as u8),
+        _ => [px[0], px[1], px[2], 0xFF],
+    }
+}
+
+/// One pixel as 16-bit RGBA, with narrower channels widened to fill the range.
+fn rgba16(format: wl_shm::Format, px: &[u8]) -> [u16; 4] {
+    let word = u32::from_le_bytes([px[0], px[1], px[2], px[3]]);
+    let ten = |shift: u32| {
+        let value = ((word >> shift) & 0x3FF) as u16;
+        value << 6 | value >> 4
+    };
+    let alpha = (word >> 30) as u16 * 0x5555;
+    match format {
+        wl_shm::Format::Argb2101010 => [ten(20), ten(10), ten(0), alpha],
+        wl_shm::Format::Xrgb2101010 => [ten(20), ten(10), ten(0), 0xFFFF],
+        wl_shm::Format::Abgr2101010 => [ten(0), ten(10), ten(20), alpha],
+        wl_shm::Format::Xbgr2101010 => [ten(0), ten(10), ten(20), 0xFFFF],
+        _ => rgba8(format, px).map(|value| value as u16 * 257),
+    }
+}
+
+/// Undoes an output transform: captured frames come in the output's
+/// hardware orientation, while the selection is in logical orientation.
+fn untransform<P: Pixel + 'static>(image: ImageBuffer<P, Vec<P::Subpixel>>, transform: Transform) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    match transform {
+        Transform::_90 => imageops::rotate90(&image),
+        Transform::_180 => imageops::rotate180(&image),
+        Transform::_270 => imageops::rotate270(&image),
+        Transform::Flipped => imageops::flip_horizontal(&image),
+        Transform::Flipped90 => imageops::flip_horizontal(&imageops::rotate90(&image)),
+        Transform::Flipped180 => imageops::flip_horizontal(&imageops::rotate180(&image)),
+        Transform::Flipped270 => imageops::flip_horizontal(&imageops::rotate270(&image)),
+        _ => image,
+    }
+}
+
+/// Hides the overlays and captures `rect` (global logical coordinates).
+pub fn capture_region(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    rect: Rect,
+) -> Result<RgbaImage, Box<dyn std::error::Error>> {
+    hide_overlays(state, event_queue)?;
+    // A frozen screen is captured as it was shown
+    if let Some(snapshot) = state.snapshot.as_ref().filter(|_| state.config.freeze) {
+        return Ok(snapshot.crop(rect));
+    }
+    let scale = capture_frames(state, event_queue, rect)?;
+    Ok(stitch(state, rect, scale, CaptureFrame::to_image))
+}
+
+/// Like [`capture_region`], keeping 16 bits per channel from frames that
+/// have more than 8.
+pub fn capture_region_deep(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    rect: Rect,
+) -> Result<DeepImage, Box<dyn std::error::Error>> {
+    hide_overlays(state, event_queue)?;
+    let scale = capture_frames(state, event_queue, rect)?;
+    Ok(stitch(state, rect, scale, CaptureFrame::to_deep_image))
+}
+
+fn hide_overlays(state: &mut AppState, event_queue: &mut EventQueue<AppState>) -> Result<(), Box<dyn std::error::Error>> {
+    if !state.can_capture() {
+        return Err(NO_CAPTURE.into());
+    }
+    // Unmap the overlays so they are not part of the capture. Both protocols
+    // copy the next rendered frame, which no longer contains them.
+    state.close_overlays();
+    event_queue.roundtrip(state)?;
+    Ok(())
+}
+
+/// Copies every output `rect` touches into `state.captures` and returns
+/// the highest scale among them.
+fn capture_frames(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    rect: Rect,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let qh = event_queue.handle();
+    let mut scale = 1;
+    for output in &state.outputs {
+        let (x, y, width, height) = output.logical_geometry();
+        let Some(region) = rect.intersection(&Rect::new(x, y, width, height)) else { continue };
+        let index = state.captures.len();
+        let backend = match (&state.image_capture_source_manager, &state.image_copy_capture_manager, &state.screencopy_manager) {
+            (Some(sources), Some(manager), _) => {
+                let source = sources.create_source(&output.wl_output, &qh, ());
+                let options = if state.config.capture_cursor { Options::PaintCursors } else { Options::empty() };
+                let session = manager.create_session(&source, options, &qh, index);
+                Backend::Ext {
+                    index,
+                    source,
+                    session,
+                    frame: None,
+                    output: Rect::new(x, y, width, height),
+                    size: (0, 0),
+                    format: None,
+                }
+            }
+            (_, _, Some(manager)) => {
+                let local = region.translate(-x, -y);
+                let overlay_cursor = state.config.capture_cursor as i32;
+                Backend::Wlr(manager.capture_output_region(
+                    overlay_cursor, &output.wl_output, local.x, local.y, local.width, local.height, &qh, index,
+                ))
+            }
+            _ => return Err(NO_CAPTURE.into()),
+        };
+        scale = scale.max(output.scale);
+        state.captures.push(CaptureFrame {
+            backend,
+            region,
+            transform: output.transform,
+            info: None,
+            y_invert: false,
+            state: FrameState::Pending,
+            shm_pool: None,
+            buffer: None,
+            mmap: None,
+        });
+    }
+    if state.captures.is_empty() {
+        return Err("Selection does not cover any output".into());
+    }
+
+    while state.captures.iter().any(|c| c.state == FrameState::Pending) {
+        event_queue.blocking_dispatch(state)?;
+    }
+    if state.captures.iter().any(|c| c.state == FrameState::Failed) {
+        return Err("Screen capture failed".into());
+    }
+    Ok(scale)
+}
+
+/// Converts the copied frames with `to_image` and puts them together at
+/// `scale`.
+fn stitch<P: Pixel + 'static>(
+    state: &mut AppState,
+    rect: Rect,
+    scale: i32,
+    to_image: impl Fn(&CaptureFrame) -> Option<ImageBuffer<P, Vec<P::Subpixel>>>,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let width = (rect.width * scale) as u32;
+    let height = (rect.height * scale) as u32;
+    let mut canvas = ImageBuffer::new(width, height);
+    for capture in state.captures.drain(..) {
+        let region = capture.region;
+        if let Some(image) = to_image(&capture) {
+            // Outputs with a lower scale get upscaled to the common one
+            let (w, h) = ((region.width * scale) as u32, (region.height * scale) as u32);
+            let image = if image.dimensions() != (w, h) {
+                imageops::resize(&image, w, h, imageops::FilterType::Triangle)
+            } else {
+                image
+            };
+            let x = ((region.x - rect.x) * scale) as i64;
+            let y = ((region.y - rect.y) * scale) as i64;
+            imageops::replace(&mut canvas, &image, x, y);
+        }
+        capture.destroy();
+    }
+    canvas
+}
+
+/// Describes a capture as PNG text keywords and values: when and where it
+/// was taken, so screenshots stay traceable after they are shared.
+pub fn metadata(state: &AppState, rect: Rect) -> Vec<(&'static str, String)> {
+    let Rect { x, y, width, height } = rect;
+    let mut metadata = vec![
+        ("Software", "Glimpse".to_string()),
+        ("Creation Time", chrono::Local::now().to_rfc3339()),
+        ("Geometry", format!("{x},{y} {width}x{height}")),
+    ];
+    let outputs: Vec<&str> = state.outputs.iter()
+        .filter(|output| {
+            let (x, y, width, height) = output.logical_geometry();
+            rect.intersection(&Rect::new(x, y, width, height)).is_some()
+        })
+        .filter_map(|output| output.name.as_deref())
+        .collect();
+    if !outputs.is_empty() {
+        metadata.push(("Output", outputs.join(",")));
+    }
+    if let Some(compositor) = compositor::name() {
+        metadata.push(("Compositor", compositor));
+    }
+    metadata
+}
+
+/// Every output captured before the overlays are mapped, for reading the
+/// true colors under the dimmed overlay.
+pub struct Snapshot {
+    image: RgbaImage,
+    bounds: Rect, // Global logical area the image covers
+    scale: i32,
+}
+
+impl Snapshot {
+    /// Color of the pixel at a point in global logical coordinates.
+    pub fn sample(&self, (x, y): (i32, i32)) -> Option<[u8; 3]> {
+        if x < self.bounds.x || y < self.bounds.y || x >= self.bounds.right() || y >= self.bounds.bottom() {
+            return None;
+        }
+        let px = ((x - self.bounds.x) * self.scale) as u32;
+        let py = ((y - self.bounds.y) * self.scale) as u32;
+        let [r, g, b, _] = self.image.get_pixel_checked(px, py)?.0;
+        Some([r, g, b])
+    }
+
+    /// The part of the snapshot under `rect`, at the snapshot's scale. Areas
+    /// off every output are black.
+    pub fn crop(&self, rect: Rect) -> RgbaImage {
+        let scale = self.scale;
+        let mut image = RgbaImage::from_pixel(
+            (rect.width * scale) as u32, (rect.height * scale) as u32, image::Rgba([0, 0, 0, 0xFF]),
+        );
+        let x = ((self.bounds.x - rect.x) * scale) as i64;
+        let y = ((self.bounds.y - rect.y) * scale) as i64;
+        imageops::replace(&mut image, &self.image, x, y);
+        image
+    }
+
+    /// The part of the snapshot under `rect` at logical resolution, as opaque
+    /// BGRA pixels for an overlay buffer.
+    pub fn logical_bgra(&self, rect: Rect) -> Vec<u8> {
+        let image = self.crop(rect);
+        let (width, height) = (rect.width as u32, rect.height as u32);
+        let image = if image.dimensions() != (width, height) {
+            imageops::resize(&image, width, height, imageops::FilterType::Triangle)
+        } else {
+            image
+        };
+        let mut data = image.into_raw();
+        for pixel in data.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+            pixel[3] = 0xFF;
+        }
+        data
+    }
+
+    /// Average relative luminance of the part of `rect` on screen, sampled
+    /// every few pixels.
+    pub fn mean_luminance(&self, rect: Rect) -> Option<f64> {
+        let samples: Vec<f64> = (rect.y..rect.bottom()).step_by(3)
+            .flat_map(|y| (rect.x..rect.right()).step_by(3).map(move |x| (x, y)))
+            .filter_map(|point| self.sample(point))
+            .map(contrast::luminance)
+            .collect();
+        (!samples.is_empty()).then(|| samples.iter().sum::<f64>() / samples.len() as f64)
+    }
+}
+
+/// Captures all outputs. Must run before the overlays are created, since
+/// capturing destroys them.
+pub fn capture_snapshot(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+) -> Result<Snapshot, Box<dyn std::error::Error>> {
+    let bounds = state.outputs.iter()
+        .map(|output| {
+            let (x, y, width, height) = output.logical_geometry();
+            Rect::new(x, y, width, height)
+        })
+        .reduce(|a, b| {
+            Rect::from_corners((a.x.min(b.x), a.y.min(b.y)), (a.right().max(b.right()), a.bottom().max(b.bottom())))
+        })
+        .ok_or("No outputs to capture")?;
+    let scale = state.outputs.iter().map(|output| output.scale).max().unwrap_or(1).max(1);
+    let image = capture_region(state, event_queue, bounds)?;
+    Ok(Snapshot { image, bounds, scale })
+}
+
+/// Takes the snapshot the overlays will read from, if anything needs one.
+/// Must run before the overlays are created, like [`capture_snapshot`].
+pub fn prepare_snapshot(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if state.config.command == Command::Contrast || state.config.freeze {
+        // Colors are read from the screen as it was, not through the dim
+        // overlay, and a frozen screen is shown and captured from it
+        state.snapshot = Some(capture_snapshot(state, event_queue)?);
+    } else if (state.config.hud.size.is_some() || state.config.hud.coordinates.is_some())
+        && state.can_capture()
+    {
+        // The size badge picks its colors to stand out from the content under
+        // it, and the pointer readout shows the color under the pointer
+        match capture_snapshot(state, event_queue) {
+            Ok(snapshot) => state.snapshot = Some(snapshot),
+            Err(err) => log::warn!("Failed to capture the screen for the HUD: {err}"),
+        }
+    }
+    Ok(())
+}
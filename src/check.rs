@@ -0,0 +1,107 @@
+//! `glimpse check-config`: reports every problem in the configuration file
+//! with its line, including settings that would otherwise only fail when a
+//! capture is taken, such as an unreadable watermark.
+
+use std::io::Write;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::config_file;
+use crate::paths;
+use crate::script;
+use crate::sounds;
+
+/// Words a hook may start with that are not programs.
+const SHELL_WORDS: &[&str] = &["if", "for", "while", "until", "case", "exec", "cd", "test", "!"];
+
+/// Checks the configuration file and the options given with the command,
+/// printing each problem. Fails if there was any.
+pub fn run(out: &mut dyn Write, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut problems = Vec::new();
+    match &config.config_path {
+        Some(path) => check_file(out, path, &mut problems)?,
+        None => writeln!(out, "Cannot determine the configuration directory; is $HOME set?")?,
+    }
+    if let Err(err) = script::load(config.script.as_deref()) {
+        problems.push(format!("script: {err}"));
+    }
+    for problem in &problems {
+        writeln!(out, "{problem}")?;
+    }
+    match problems.len() {
+        0 => Ok(()),
+        1 => Err("found 1 problem".into()),
+        n => Err(format!("found {n} problems").into()),
+    }
+}
+
+/// Applies every line of `path` to a scratch configuration, adding a
+/// problem for each one that fails instead of stopping at the first.
+fn check_file(out: &mut dyn Write, path: &Path, problems: &mut Vec<String>) -> std::io::Result<()> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && paths::config_file().as_deref() == Some(path) => {
+            return writeln!(out, "{}: not found, using the defaults", path.display());
+        }
+        Err(err) => {
+            problems.push(format!("{}: {err}", path.display()));
+            return Ok(());
+        }
+    };
+    let entries = match config_file::parse(&contents) {
+        Ok(entries) => entries,
+        Err(err) => {
+            problems.push(format!("{}: {err}", path.display()));
+            return Ok(());
+        }
+    };
+    let mut scratch = Config::default();
+    for entry in &entries {
+        // Every compositor's overrides are checked, not just the running one's
+        let entry = entry.for_compositor().map_or_else(|| entry.clone(), |(_, entry)| entry);
+        if let Err(err) = scratch.apply_entry(&entry).and_then(|()| check_entry(&entry)) {
+            problems.push(format!("{}: line {}: {err}", path.display(), entry.line));
+        }
+    }
+    if scratch.watermark.is_some_and(|w| w.image.as_os_str().is_empty()) {
+        problems.push(format!("{}: [watermark] needs an image", path.display()));
+    }
+    if problems.is_empty() {
+        writeln!(out, "{}: OK", path.display())?;
+    }
+    Ok(())
+}
+
+/// Checks what a setting refers to, which is otherwise only looked at
+/// when it is used.
+fn check_entry(entry: &config_file::Entry) -> Result<(), String> {
+    let value = entry.value.as_str();
+    match (entry.section.as_str(), entry.key.as_str()) {
+        ("watermark", "image") => {
+            let image = paths::expand_home(value);
+            image::open(&image).map(drop).map_err(|err| format!("unusable watermark {}: {err}", image.display()))
+        }
+        ("sounds", "confirm" | "cancel" | "save") => match sounds::parse_sound(value) {
+            Some(sound) if !sound.is_file() => Err(format!("no sound file at {}", sound.display())),
+            _ => Ok(()),
+        },
+        ("hooks", _) | ("sounds", "player") => check_program(value),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the program a shell command starts with can be found. Only
+/// simple commands are looked at; anything else is left to the shell.
+fn check_program(command: &str) -> Result<(), String> {
+    let Some(program) = command.split_whitespace().next() else { return Ok(()) };
+    let simple = program.chars().all(|c| c.is_ascii_alphanumeric() || "-_.+/~".contains(c));
+    if !simple || SHELL_WORDS.contains(&program) {
+        return Ok(());
+    }
+    let found = if program.contains('/') {
+        paths::expand_home(program).is_file()
+    } else {
+        std::env::var_os("PATH").is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+    };
+    if found { Ok(()) } else { Err(format!("'{program}' was not found")) }
+}
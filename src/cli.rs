@@ -0,0 +1,455 @@
+//! The `glimpse` command: parsing its arguments and running it to the end.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+use wayland_client::{Connection, EventQueue};
+
+use crate::analysis;
+use crate::app::{AppState, SelectionState};
+use crate::capture;
+use crate::check;
+use crate::clipboard;
+use crate::config::{Command, Config, HistogramOutput};
+use crate::contrast::Contrast;
+use crate::daemon;
+use crate::driver;
+use crate::editor;
+use crate::encode;
+use crate::geometry;
+use crate::graphics::text::TextRenderer;
+use crate::history;
+use crate::hooks;
+use crate::measure::Measurement;
+use crate::notify;
+use crate::output;
+use crate::paths;
+use crate::preview::{self, Choice};
+use crate::process;
+use crate::record;
+use crate::recording::{self, Recorder};
+use crate::rpc;
+use crate::s3;
+use crate::script::{self, Script};
+use crate::selection::Rect;
+use crate::sinks;
+use crate::snap::Snap;
+use crate::sounds;
+use crate::trace;
+use crate::upload;
+use crate::windows;
+
+/// Runs the `glimpse` command with the process's arguments.
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    trace::init();
+    let config = Config::from_args()?;
+    let _trace = config.trace_wayland.as_deref().map(trace::start).transpose()?;
+    // Opened up front so a bad descriptor fails before the overlay appears
+    let mut output = sinks::result_output(config.output_fd)?;
+    if let Command::History { json, limit, pick } = config.command {
+        return history::show(&mut output, json, limit, pick);
+    }
+    if config.command == Command::CheckConfig {
+        return check::run(&mut output, &config);
+    }
+    if let Command::Client(method) = config.command {
+        return daemon::request(&mut output, &config, method);
+    }
+    let script = script::load(config.script.as_deref())?;
+    let windows = match config.command {
+        Command::Window | Command::Windows { .. } => windows::visible()?,
+        _ if config.snap == Snap::Windows => windows::visible().unwrap_or_else(|err| {
+            log::warn!("Not snapping to windows: {err}");
+            Vec::new()
+        }),
+        _ => Vec::new(),
+    };
+    let conn = Connection::connect_to_env()?;
+    let mut event_queue = conn.new_event_queue();
+    let qh = event_queue.handle();
+
+    let display = conn.display();
+    display.get_registry(&qh, ());
+
+    let mut state = AppState::new(config);
+    state.script = script;
+    state.windows = windows;
+
+    event_queue.roundtrip(&mut state)?;
+    // Second roundtrip collects output geometry and xdg-output details
+    event_queue.roundtrip(&mut state)?;
+
+    if let Command::Outputs { json } = state.config.command {
+        output::list(&mut output, &state.outputs, json)?;
+        return Ok(());
+    }
+    if let Command::Windows { json } = state.config.command {
+        windows::list(&mut output, &state.windows, &state.outputs, json)?;
+        return Ok(());
+    }
+    state.shm.as_ref().expect("No wl_shm global");
+    // A custom cursor is set up on the first pointer enter
+    if !state.config.custom_cursor && state.cursor_shape_manager.is_none() {
+        log::warn!("Compositor lacks wp_cursor_shape_v1; keeping the current cursor");
+    }
+    if state.config.command == Command::Replay {
+        // The newest history entry, handled as if it was just selected
+        let entry = history::load()?.pop().ok_or("The history is empty; confirm a selection first")?;
+        return deliver(&mut state, &mut event_queue, output, entry.rect);
+    }
+    if state.config.command == Command::Daemon {
+        // Loaded once for every request to come
+        state.text = TextRenderer::load(state.config.font.as_deref());
+        return daemon::serve(&mut state, &mut event_queue);
+    }
+    if state.config.rpc {
+        // Overlays are mapped per request
+        return rpc::serve(&mut state, &mut event_queue, &mut output);
+    }
+    capture::prepare_snapshot(&mut state, &mut event_queue)?;
+    // Read up front so a bad recording fails before the overlay shows
+    let replay = state.config.replay_input.as_deref().map(recording::load).transpose()?;
+    state.recorder = state.config.record_input.as_deref().map(Recorder::create).transpose()?;
+    let initial = match state.config.initial_geometry {
+        Some(rect) => Some(rect),
+        // Reuse a region shared earlier, e.g. pasted from Glimpse's own output
+        None if state.config.clipboard_geometry && !state.config.command.picks_points() => {
+            clipboard::read_text(&mut state, &mut event_queue)?.and_then(|text| Rect::parse(&text))
+        }
+        None => None,
+    };
+    if let Some(rect) = initial {
+        state.selection_state = SelectionState::Editing { rect };
+    }
+    state.create_overlays(&qh);
+
+    let shows_labels = state.config.command.picks_points()
+        || matches!(state.config.command, Command::Output | Command::Window)
+        || state.config.hud.shows_labels();
+    if shows_labels || state.script.as_ref().is_some_and(Script::has_hud_text) {
+        state.text = TextRenderer::load(state.config.font.as_deref());
+        if state.text.is_none() {
+            log::warn!("No usable font found; results are only printed");
+        }
+    }
+
+    event_queue.roundtrip(&mut state)?;
+
+    // Guarantee initial draw
+    state.draw_pending(&qh);
+
+    if state.config.command == Command::Measure {
+        eprintln!("Click two points to measure. Press ESC to cancel.");
+    } else if state.config.command == Command::Contrast {
+        eprintln!("Click a foreground pixel, then a background pixel. Press ESC to cancel.");
+    } else {
+        eprintln!("Click and drag to select a region. Press ESC to cancel.");
+    }
+    if state.config.driver {
+        driver::run(&mut state, &mut event_queue)?;
+    } else if let Some(events) = replay {
+        recording::replay(&mut state, &mut event_queue, events)?;
+    } else {
+        while state.running {
+            // Block for events, redraw only when needed
+            event_queue.blocking_dispatch(&mut state)?;
+            state.draw_pending(&qh);
+        }
+    }
+    if let Some(err) = state.error.take() {
+        return Err(err.into());
+    }
+
+    if state.config.command.picks_points() {
+        let Some((from, to)) = state.points else {
+            eprintln!("Selection cancelled.");
+            return Ok(());
+        };
+        if state.config.command == Command::Measure {
+            writeln!(output, "{}", Measurement::new(from, to, &state.outputs))?;
+        } else {
+            let snapshot = state.snapshot.as_ref().unwrap();
+            let (Some(foreground), Some(background)) = (snapshot.sample(from), snapshot.sample(to)) else {
+                return Err("Picked a point outside of every output".into());
+            };
+            writeln!(output, "{}", Contrast::new(foreground, background))?;
+        }
+        return Ok(());
+    }
+
+    let Some(mut rect) = state.result else {
+        return cancelled(&state);
+    };
+    while state.config.confirm_preview {
+        let image = capture::capture_region(&mut state, &mut event_queue, rect)?;
+        match preview::show(&mut state, &mut event_queue, &image, rect)? {
+            Choice::Save => {
+                state.captured = Some(image);
+                break;
+            }
+            Choice::Retry => match preview::reselect(&mut state, &mut event_queue, rect)? {
+                Some(selected) => rect = selected,
+                None => return cancelled(&state),
+            },
+            Choice::Cancel => return cancelled(&state),
+        }
+    }
+    deliver(&mut state, &mut event_queue, output, rect)
+}
+
+/// Reports a cancelled selection.
+fn cancelled(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
+    eprintln!("Selection cancelled.");
+    sounds::play(&state.config.sounds, &state.config.sounds.cancel);
+    hooks::run(&state.config.hooks.on_cancel, None, None);
+    Ok(())
+}
+
+/// Prints, captures, saves or copies the confirmed `rect`, as configured.
+fn deliver(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    mut output: Box<dyn Write>,
+    rect: Rect,
+) -> Result<(), Box<dyn std::error::Error>> {
+    sounds::play(&state.config.sounds, &state.config.sounds.confirm);
+    if !state.config.capture {
+        let line = geometry::format(&state.config.geometry_format, rect, &state.outputs, state.config.units)?;
+        writeln!(output, "{line}")?;
+        output.flush()?;
+        hooks::run(&state.config.hooks.on_success, Some(rect), None);
+        if state.config.notify {
+            notify::delivered(&state.config, rect);
+        }
+        return Ok(());
+    }
+
+    if let Command::Watch { every } = state.config.command {
+        return watch(state, event_queue, &mut output, rect, every);
+    }
+    if let Command::Record { fps, duration } = state.config.command {
+        let name = record::video_name(Path::new(&sinks::expand_filename(&state.config.filename)?));
+        let dir = paths::screenshots_dir().ok_or("Cannot determine the pictures directory; is $HOME set?")?;
+        let path = dir.join(name);
+        record::record(state, event_queue, &path, rect, fps, duration)?;
+        writeln!(output, "{}", path.display())?;
+        output.flush()?;
+        hooks::run(&state.config.hooks.on_save, Some(rect), Some(&path));
+        hooks::run(&state.config.hooks.on_success, Some(rect), None);
+        if state.config.notify {
+            notify::delivered(&state.config, rect);
+        }
+        return Ok(());
+    }
+    if let Command::WatchChanges { every, threshold } = state.config.command {
+        return watch_changes(state, event_queue, &mut output, rect, every, threshold);
+    }
+
+    let name = PathBuf::from(sinks::expand_filename(&state.config.filename)?);
+    let started = Instant::now();
+    let mut encoded = Vec::new();
+    let mut saved = Vec::new();
+    let uploads = state.config.upload.is_some() || state.config.s3.is_some();
+    for shot in 0..state.config.burst {
+        // Shots are timed from the first one so slow captures don't add drift
+        let due = started + state.config.burst_interval * shot;
+        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        encoded = capture_and_encode(state, event_queue, &mut output, rect)?;
+        if state.config.save {
+            let name = if state.config.burst > 1 { sinks::numbered(&name, shot + 1) } else { name.clone() };
+            saved = save_all(state, &mut output, &encoded, &name, rect)?;
+        } else if let (false, false, Some(encoded)) = (state.config.copy, uploads, encoded.first()) {
+            output.write_all(&encoded.data)?;
+        }
+    }
+    let mut url = None;
+    if let Some(encoded) = encoded.first().filter(|_| uploads) {
+        let path = name.with_extension(encoded.format.extension());
+        let mime_type = encoded.format.mime_type();
+        let link = match (&state.config.upload, &state.config.s3) {
+            (Some(upload), _) => upload::send(upload, &encoded.data, &path.file_name().unwrap_or_default().to_string_lossy(), mime_type)?,
+            // Directories in the file name template become key prefixes
+            (None, Some(bucket)) => s3::send(bucket, &encoded.data, &path.to_string_lossy(), mime_type)?,
+            (None, None) => unreachable!(),
+        };
+        writeln!(output, "{link}")?;
+        url = Some(link).filter(|_| state.config.copy_url);
+    }
+    output.flush()?;
+    // Close the descriptor before the clipboard server forks, or the caller
+    // would wait for it as long as the copy stays on the clipboard
+    drop(output);
+    hooks::run(&state.config.hooks.on_success, Some(rect), None);
+    if state.config.notify {
+        notify::delivered(&state.config, rect);
+    }
+    if state.config.copy {
+        let geometry = geometry::format(&state.config.geometry_format, rect, &state.outputs, state.config.units)?;
+        let offers = clipboard::offers(&state.config.offered_types(), &encoded, saved.first().map(PathBuf::as_path), &geometry);
+        clipboard::copy(state, event_queue, offers)?;
+        clipboard::serve_in_background(state, event_queue)?;
+    } else if let Some(url) = url {
+        clipboard::copy(state, event_queue, vec![("text/plain;charset=utf-8".into(), url.into_bytes())])?;
+        clipboard::serve_in_background(state, event_queue)?;
+    }
+    Ok(())
+}
+
+/// Captures `rect`, prints the requested statistics, applies the configured
+/// post-processing and encodes the result into every requested format.
+fn capture_and_encode(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    output: &mut dyn Write,
+    rect: Rect,
+) -> Result<Vec<encode::Encoded>, Box<dyn std::error::Error>> {
+    if state.config.deep_color {
+        // Checked to come without analysis or post-processing, which work on 8 bits
+        let image = capture::capture_region_deep(state, event_queue, rect)?;
+        let metadata = capture::metadata(state, rect);
+        return encode::encode_all(&DynamicImage::ImageRgba16(image), &state.config.encoded_formats(), &metadata, state.config.optimize);
+    }
+    let mut image = match (state.captured.take(), state.config.command) {
+        // Already captured and approved in the preview
+        (Some(image), _) => image,
+        (None, Command::Diff { every }) => diff(state, event_queue, output, rect, every)?,
+        (None, _) => capture::capture_region(state, event_queue, rect)?,
+    };
+    // Statistics describe the screen itself, before any post-processing
+    if let Some(count) = state.config.palette {
+        for swatch in analysis::palette(&image, count) {
+            writeln!(output, "{} {:.1}%", swatch.hex(), swatch.share * 100.0)?;
+        }
+    }
+    let histogram = state.config.histogram.map(|output| (output, analysis::Histogram::new(&image)));
+    if let Some((HistogramOutput::Json, histogram)) = &histogram {
+        writeln!(output, "{}", histogram.to_json())?;
+    }
+    if !state.config.writes_image() {
+        return Ok(Vec::new());
+    }
+    if let Some(resample) = state.config.resample {
+        image = process::resample(image, resample, state.config.filter);
+    }
+    image = process::upscale(image, state.config.upscale);
+    if let Some((HistogramOutput::Plot, histogram)) = &histogram {
+        analysis::draw_histogram(&mut image, histogram);
+    }
+    if let Some(watermark) = &state.config.watermark {
+        process::apply_watermark(&mut image, watermark)?;
+    }
+    if state.config.edit {
+        image = editor::edit(&image, state.config.editor.as_deref())?;
+    }
+    let metadata = capture::metadata(state, rect);
+    encode::encode_all(&DynamicImage::ImageRgba8(image), &state.config.encoded_formats(), &metadata, state.config.optimize)
+}
+
+/// Writes one file per format, sharing `name` with the format's extension,
+/// and returns their paths. Formats only encoded for the clipboard are skipped.
+fn save_all(
+    state: &AppState,
+    output: &mut dyn Write,
+    encoded: &[encode::Encoded],
+    name: &Path,
+    rect: Rect,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut paths = Vec::new();
+    for encoded in encoded.iter().filter(|encoded| state.config.formats.contains(&encoded.format)) {
+        let path = sinks::save_to_screenshots(&encoded.data, &name.with_extension(encoded.format.extension()))?;
+        writeln!(output, "{}", path.display())?;
+        sounds::play(&state.config.sounds, &state.config.sounds.save);
+        hooks::run(&state.config.hooks.on_save, Some(rect), Some(&path));
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Captures `rect` every `every` until interrupted, reporting each time
+/// more than `threshold` percent of its pixels changed since the last
+/// capture.
+fn watch_changes(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    output: &mut dyn Write,
+    rect: Rect,
+    every: Duration,
+    threshold: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let started = Instant::now();
+    let mut last = capture::capture_region(state, event_queue, rect)?;
+    for shot in 1.. {
+        let due = started + every * shot;
+        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        let image = capture::capture_region(state, event_queue, rect)?;
+        if image.dimensions() != last.dimensions() {
+            // After a scale change, comparing starts over at the new size
+            log::warn!("The region changed size between captures; comparing from the next one");
+            last = image;
+            continue;
+        }
+        let difference = analysis::Difference::new(&last, &image);
+        last = image;
+        if difference.percent() <= threshold {
+            continue;
+        }
+        writeln!(output, "{difference}")?;
+        output.flush()?;
+        let changed = format!("{:.2}", difference.percent());
+        hooks::run_with(&state.config.hooks.on_change, Some(rect), &[("GLIMPSE_CHANGED", changed.into())]);
+    }
+    Ok(())
+}
+
+/// Captures `rect` twice, after Enter is pressed or `every` has passed,
+/// and returns an image of what changed, printing statistics about it.
+fn diff(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    output: &mut dyn Write,
+    rect: Rect,
+    every: Option<Duration>,
+) -> Result<image::RgbaImage, Box<dyn std::error::Error>> {
+    let before = capture::capture_region(state, event_queue, rect)?;
+    match every {
+        Some(every) => std::thread::sleep(every),
+        None => {
+            eprintln!("Captured. Press Enter to capture again and compare.");
+            std::io::stdin().read_line(&mut String::new())?;
+        }
+    }
+    let after = capture::capture_region(state, event_queue, rect)?;
+    if before.dimensions() != after.dimensions() {
+        return Err("The region changed size between captures, e.g. after a scale change".into());
+    }
+    let difference = analysis::Difference::new(&before, &after);
+    // Statistics only share the output with a saved or copied image
+    if state.config.save || state.config.copy {
+        writeln!(output, "{difference}")?;
+    } else {
+        eprintln!("{difference}");
+    }
+    Ok(analysis::difference_image(&before, &after))
+}
+
+/// Captures `rect` every `every` into timestamped files until interrupted.
+fn watch(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    output: &mut dyn Write,
+    rect: Rect,
+    every: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let started = Instant::now();
+    for shot in 0.. {
+        let due = started + every * shot;
+        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        let encoded = capture_and_encode(state, event_queue, output, rect)?;
+        let name = PathBuf::from(sinks::expand_filename(&state.config.filename)?);
+        save_all(state, output, &encoded, &name, rect)?;
+        output.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,197 @@
+//! Reading the clipboard and offering captures on it with wlr-data-control.
+//!
+//! Wayland clipboards are served by the client that set them, so after the
+//! selection is set the process forks: the parent exits as usual while the
+//! child keeps answering paste requests until another client takes over.
+//!
+//! A capture can be offered as several types at once, set with
+//! `clipboard_types`, since applications paste different ones: images,
+//! a `text/uri-list` naming the saved file, or the geometry as text.
+
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, AsRawFd};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use wayland_client::{EventQueue, Proxy};
+
+use crate::app::AppState;
+use crate::encode::{self, Encoded, Format};
+
+// Text types accepted when reading, most specific first
+const TEXT_MIME_TYPES: &[&str] = &["text/plain;charset=utf-8", "text/plain", "UTF8_STRING", "STRING", "TEXT"];
+// Longest text read from the clipboard, and how long its owner may take
+const READ_LIMIT: usize = 4096;
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// What a capture is offered as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClipboardType {
+    Image(Format),
+    UriList, // The saved file, so only with --save
+    Geometry,
+}
+
+/// Parses a comma-separated list such as `png,uri-list,geometry`.
+pub fn parse_types(spec: &str) -> Result<Vec<ClipboardType>, String> {
+    let mut types = Vec::new();
+    for name in spec.split(',').map(str::trim) {
+        let kind = match name {
+            "uri-list" => ClipboardType::UriList,
+            "geometry" => ClipboardType::Geometry,
+            "png" | "webp" | "jpeg" | "jpg" => ClipboardType::Image(encode::parse_formats(name)?[0]),
+            _ => return Err(format!("unknown clipboard type '{name}', expected png, webp, jpeg, uri-list or geometry")),
+        };
+        if !types.contains(&kind) {
+            types.push(kind);
+        }
+    }
+    Ok(types)
+}
+
+/// The MIME types and data to offer for `types`, in order. Images come from
+/// `encoded`, and a URI list from `saved` when there is a saved file.
+pub fn offers(types: &[ClipboardType], encoded: &[Encoded], saved: Option<&Path>, geometry: &str) -> Vec<(String, Vec<u8>)> {
+    let mut offers = Vec::new();
+    for kind in types {
+        match kind {
+            ClipboardType::Image(format) => {
+                if let Some(encoded) = encoded.iter().find(|encoded| encoded.format == *format) {
+                    offers.push((format.mime_type().to_string(), encoded.data.clone()));
+                }
+            }
+            ClipboardType::UriList => match saved {
+                Some(path) => offers.push(("text/uri-list".into(), format!("{}\r\n", file_uri(path)).into_bytes())),
+                None => log::warn!("Not offering text/uri-list without a saved file"),
+            },
+            ClipboardType::Geometry => {
+                offers.extend(TEXT_MIME_TYPES.iter().map(|mime| (mime.to_string(), geometry.as_bytes().to_vec())));
+            }
+        }
+    }
+    offers
+}
+
+/// A `file://` URI for an absolute path.
+fn file_uri(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    let encoded: String = path.as_os_str().as_bytes().iter().map(|&byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => (byte as char).to_string(),
+        _ => format!("%{byte:02X}"),
+    }).collect();
+    format!("file://{encoded}")
+}
+
+/// Data currently offered on the clipboard, by MIME type.
+pub struct ClipboardSource {
+    pub offers: Vec<(String, Vec<u8>)>,
+}
+
+impl ClipboardSource {
+    /// Writes the data for a paste request; failures only affect that paste.
+    pub fn send(&self, mime_type: &str, fd: std::os::fd::OwnedFd) {
+        let Some((_, data)) = self.offers.iter().find(|(offered, _)| offered == mime_type) else { return };
+        if let Err(err) = std::fs::File::from(fd).write_all(data) {
+            log::warn!("Failed to send clipboard data: {err}");
+        }
+    }
+}
+
+/// Reads the clipboard as text, if it holds short text. Gives up after a
+/// brief timeout so an unresponsive clipboard owner cannot stall startup.
+pub fn read_text(state: &mut AppState, event_queue: &mut EventQueue<AppState>) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let qh = event_queue.handle();
+    let (Some(manager), Some(seat)) = (&state.data_control_manager, &state.seat) else { return Ok(None) };
+    let device = manager.get_data_device(seat, &qh, ());
+    // The current selection is announced as soon as the device is created
+    event_queue.roundtrip(state)?;
+    device.destroy();
+    let Some(offer) = state.clipboard_offer.take() else { return Ok(None) };
+
+    let mime_type = offer.data::<Mutex<Vec<String>>>().and_then(|types| {
+        let types = types.lock().unwrap();
+        TEXT_MIME_TYPES.iter().find(|&&mime| types.iter().any(|t| t == mime)).copied()
+    });
+    let Some(mime_type) = mime_type else {
+        offer.destroy();
+        return Ok(None);
+    };
+    let (mut reader, writer) = std::io::pipe()?;
+    offer.receive(mime_type.to_string(), writer.as_fd());
+    drop(writer);
+    event_queue.flush()?;
+    offer.destroy();
+
+    let deadline = Instant::now() + READ_TIMEOUT;
+    let mut text = Vec::new();
+    let mut chunk = [0; 512];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let mut poll_fd = libc::pollfd { fd: reader.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        if remaining.is_zero() || unsafe { libc::poll(&mut poll_fd, 1, remaining.as_millis() as i32) } <= 0 {
+            return Ok(None);
+        }
+        match reader.read(&mut chunk)? {
+            0 => break,
+            n => text.extend_from_slice(&chunk[..n]),
+        }
+        if text.len() > READ_LIMIT {
+            return Ok(None);
+        }
+    }
+    Ok(String::from_utf8(text).ok())
+}
+
+/// Sets the clipboard selection to `offers`, pairs of a MIME type and data.
+pub fn copy(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    offers: Vec<(String, Vec<u8>)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if offers.is_empty() {
+        return Err("Nothing to offer on the clipboard; check clipboard_types".into());
+    }
+    let qh = event_queue.handle();
+    let manager = state.data_control_manager.as_ref().ok_or("Compositor lacks zwlr_data_control_manager_v1")?;
+    let seat = state.seat.as_ref().ok_or("No wl_seat to copy to")?;
+
+    let device = manager.get_data_device(seat, &qh, ());
+    let source = manager.create_data_source(&qh, ());
+    for (mime_type, _) in &offers {
+        source.offer(mime_type.clone());
+    }
+    device.set_selection(Some(&source));
+    state.clipboard = Some(ClipboardSource { offers });
+    event_queue.roundtrip(state)?;
+    Ok(())
+}
+
+/// Forks a child that serves the clipboard until it is replaced; returns in
+/// the parent only.
+pub fn serve_in_background(state: &mut AppState, event_queue: &mut EventQueue<AppState>) -> Result<(), Box<dyn std::error::Error>> {
+    std::io::stdout().flush()?;
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error().into()),
+        0 => {
+            // Let callers reading our output see EOF, and leave their session
+            detach_stdio();
+            unsafe { libc::setsid() };
+            while state.clipboard.is_some() {
+                if event_queue.blocking_dispatch(state).is_err() {
+                    break;
+                }
+            }
+            std::process::exit(0);
+        }
+        _ => Ok(()),
+    }
+}
+
+fn detach_stdio() {
+    let Ok(null) = std::fs::OpenOptions::new().read(true).write(true).open("/dev/null") else { return };
+    let null = std::os::fd::AsRawFd::as_raw_fd(&null);
+    for fd in 0..=2 {
+        unsafe { libc::dup2(null, fd) };
+    }
+}
@@ -0,0 +1,18 @@
+//! Identifying the running compositor from its environment variables.
+
+/// Name of the running compositor, e.g. `sway` or `Hyprland`.
+pub fn name() -> Option<String> {
+    let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+    // Compositor sockets are more reliable than XDG_CURRENT_DESKTOP,
+    // which is often set by the session rather than the compositor
+    if var("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        return Some("Hyprland".into());
+    }
+    if var("SWAYSOCK").is_some() {
+        return Some("sway".into());
+    }
+    if var("NIRI_SOCKET").is_some() {
+        return Some("niri".into());
+    }
+    var("XDG_CURRENT_DESKTOP").and_then(|desktops| desktops.split(':').next().map(str::to_string))
+}
@@ -0,0 +1,1300 @@
+//! Command line options, the configuration file and built-in defaults.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1::Layer;
+
+use image::imageops::FilterType;
+
+use crate::clipboard::{self, ClipboardType};
+use crate::compositor;
+use crate::config_file::{self, Entry};
+use crate::encode::{self, Format, Optimize};
+use crate::geometry::{self, Units};
+use crate::graphics::cursor;
+use crate::graphics::drawing::{Dim, Shadow};
+use crate::hooks::Hooks;
+use crate::hud::{self, Anchor, Hud};
+use crate::keys::{self, KeyAction, KeyCombo, MOD_ALT};
+use crate::paths;
+use crate::pipeline::{self, Stage};
+use crate::process::{self, Position, Resample, Watermark};
+use crate::record;
+use crate::s3::{self, S3};
+use crate::selection::{Preset, Rect};
+use crate::sinks;
+use crate::snap::Snap;
+use crate::sounds::{self, Sounds};
+use crate::upload::Upload;
+
+// Linux input event codes for mouse buttons
+pub const BTN_LEFT: u32 = 0x110;
+pub const BTN_RIGHT: u32 = 0x111;
+pub const BTN_MIDDLE: u32 = 0x112;
+pub const BTN_SIDE: u32 = 0x113;
+pub const BTN_EXTRA: u32 = 0x114;
+
+// Radius of --blur when none is given, in pixels
+const DEFAULT_BLUR_RADIUS: u32 = 12;
+
+// Pointer speed while Alt is held during a drag, unless --precision is given
+const DEFAULT_PRECISION: f64 = 0.25;
+
+// Spacing of the grid Tab can snap a drag to, unless --snap-grid is given
+const DEFAULT_SNAP_GRID: i32 = 16;
+
+// How often watch-changes captures the region by default
+const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Radius of the spotlight dim style, in pixels
+const DEFAULT_SPOTLIGHT_RADIUS: i32 = 200;
+
+const USAGE: &str = "\
+Usage: glimpse [command] [options]
+
+Commands:
+  select                      Select a region (default)
+  watch --every DURATION      Select a region once, then capture it every
+                              DURATION (e.g. 500ms, 5s, 1m) into timestamped
+                              files until interrupted
+  record --native [--fps N] [--duration DURATION]
+                              Select a region and record it to a .webm (VP9)
+                              or, with an .mp4 --filename, H.264 video until
+                              DURATION passes, Ctrl+C or SIGUSR1 (e.g. from a
+                              compositor hotkey); needs ffmpeg and the record
+                              feature. --fps defaults to 30
+  diff [--every DURATION]     Select a region, capture it, capture it again
+                              after Enter is pressed (or after DURATION) and
+                              output an image of the changed pixels, printing
+                              how many changed and where
+  watch-changes [--every DURATION] [--threshold PERCENT]
+                              Select a region, then capture it every DURATION
+                              (default: 1s) and print a line, and run the
+                              on_change hook or --on-change CMD, whenever more
+                              than PERCENT of its pixels (default: 0) changed
+                              since the last capture
+  measure                     Click two points to show and print the distance
+                              between them, in pixels and millimeters
+  contrast                    Click a foreground and a background pixel to show
+                              and print their WCAG contrast ratio
+  window                      Click a window to select it; the hovered one is
+                              labeled with its app id and title (Hyprland
+                              and sway)
+  output                      Click an output to select all of it; each one is
+                              labeled with its name, resolution and scale
+  outputs [--json]            List the outputs with their logical geometry,
+                              scale, transform and refresh rate
+  windows [--json]            List the visible windows, topmost first, with
+                              their app id, title, geometry, output and
+                              whether they have focus (Hyprland and sway)
+  history [--json] [-n N]     List the most recent confirmed selections
+  history --pick [N]          Print the geometry of the Nth most recent
+                              selection (default: 1), e.g. for --initial
+  replay                      Print or capture the most recent selection
+                              again, as given by the other options, without
+                              showing anything
+  daemon                      Stay connected with the overlays' resources
+                              ready, answering clients on a socket in
+                              $XDG_RUNTIME_DIR so selections show up at once
+  run NAME                    Run the pipeline NAME from the [pipelines]
+                              section of the configuration file, e.g.
+                              select -> edit -> save -> copy -> notify
+  client [METHOD]             Ask the daemon to select (default), capture or
+                              pick, printing the result like Glimpse does;
+                              takes --initial, --format, --save, --filename
+  check-config                Report every problem in the configuration file,
+                              by line, including missing watermark images,
+                              sounds and hook programs
+
+Options:
+  -h, --help                  Show this help and exit
+  --config PATH               Read settings from PATH instead of
+                              $XDG_CONFIG_HOME/glimpse/config
+  --bind-button BUTTON=ACTION Bind a mouse button to an action (repeatable)
+                              BUTTON: left, right, middle, side, extra or an
+                                      input event code such as 0x110
+                              ACTION: select, cancel, confirm, output, none
+                              Default: left=select, middle=output
+  --bind-key KEY=ACTION       Bind a key to an action (repeatable)
+                              KEY: a name such as l, shift+l, L, ctrl+left,
+                                   escape, return, space, tab
+                              ACTION: cancel, confirm, move-left, move-right,
+                                      move-up, move-down, grow-width,
+                                      shrink-width, grow-height, shrink-height,
+                                      next-handle, previous-handle,
+                                      preset-1 to preset-9, peek,
+                                      swap-anchor, retry, toolbar, none
+                              Default: arrows/hjkl move, shift or ctrl with
+                              them resizes; hold alt for 1px steps; tab and
+                              shift+tab pick an edge or corner for the
+                              arrows to move on its own; 1-9 select the
+                              [presets] from the config file; holding x
+                              hides the overlay to peek underneath; o
+                              swaps the fixed and the dragged corner; t
+                              shows the mode toolbar
+  --initial X,Y WxH           Start with this rectangle selected and editable,
+                              to be confirmed with Enter or redrawn
+                              (alias: --initial-geometry). Otherwise a geometry
+                              on the clipboard is used, unless
+                              --no-clipboard-geometry is given
+  --two-click                 Click once for each corner instead of dragging
+  --adjust                    Keep the selection editable after drawing it;
+                              confirm with Enter
+  --allow-offscreen           Keep parts of the selection outside every
+                              output, for tools taking virtual desktop
+                              coordinates, instead of cutting them off
+  --precision FACTOR          Pointer speed while Alt is held during a drag,
+                              for placing a corner exactly (default: 0.25;
+                              needs relative pointer events)
+  --snap-grid PX              Spacing of the grid a drag snaps to (default:
+                              16); tab cycles the snapping while dragging
+                              between none, grid, windows and outputs
+  --snap TARGET               What a drag snaps to until tab is pressed: off
+                              (default), grid, windows or outputs
+  --confine                   Keep the pointer on the output a drag started
+                              on, so a corner near its edge can't overshoot
+                              onto the next one (needs pointer constraints)
+  --capture                   Capture the selection and write it as PNG to stdout
+  --save                      Capture the selection into
+                              $XDG_PICTURES_DIR/Screenshots and print the path
+  --copy                      Capture the selection to the clipboard; with
+                              --save, do both
+  --clipboard-types TYPES     Comma-separated types --copy offers: png, webp,
+                              jpeg, uri-list (the saved file) and geometry
+                              (as text); default: the first format
+  --palette N                 Capture the selection and print its N dominant
+                              colors as hex values
+  --histogram FORMAT          Capture the selection and compute its RGB and
+                              luminance histograms: json prints them, plot
+                              draws them into the capture's corner
+  --edit [CMD]                Open the capture in an image editor and keep the
+                              edited result; CMD may use %f for the file
+                              (default: satty, swappy or gimp, if installed)
+  --confirm-preview           Show a thumbnail of the capture with Save (Enter),
+                              Retry (R) and Cancel (Esc) buttons before it is
+                              written, to redo a bad grab
+  --upload [TARGET]           POST the capture to TARGET, a URL or a preset
+                              (0x0, catbox), or to url under [upload], and
+                              print the URL it answers with; needs the upload
+                              feature
+  --s3 [BUCKET]               Store the capture in BUCKET, or bucket under
+                              [s3], on S3 or a compatible service and print a
+                              link to it; needs the s3 feature
+  --copy-url                  Put the link from --upload or --s3 on the
+                              clipboard
+  --notify                    Send a desktop notification with notify-send
+                              once the result is delivered
+  --cursor                    Include the pointer in captures
+  --deep-color                Capture 10-bit outputs at full depth, when the
+                              compositor offers it, and save 16-bit PNG
+  --resize WxH                Resample captures to exactly WxH pixels
+  --scale FACTOR              Resample captures by FACTOR, e.g. 0.5
+  --filter FILTER             Resampling filter: nearest, bilinear or lanczos
+                              (default)
+  --upscale N                 Enlarge captures N times with hard pixel edges,
+                              e.g. for pixel art
+  -f, --format FORMATS        Comma-separated capture formats: png (default),
+                              webp, jpeg; several formats need --save. Without a
+                              capture, env prints the geometry as GLIMPSE_X,
+                              GLIMPSE_Y, GLIMPSE_W, GLIMPSE_H and GLIMPSE_OUTPUT
+                              assignments for eval, and a template such as
+                              '%x,%y %wx%h' fills in %x, %y, %w, %h and %o
+                              (output); %wmm, %hmm, %win and %hin give the
+                              size in millimeters or inches
+  --optimize [zopfli]         Shrink PNG output losslessly before it is saved,
+                              copied or uploaded, trying more filters and
+                              stronger compression; zopfli is smaller still
+                              but much slower. Needs the optimize feature
+  --units UNITS               Also print the size in mm or in, using the
+                              output's physical dimensions
+  --burst N                   Capture the selection N times, numbering the files;
+                              needs --save
+  --interval MS               Time between --burst captures (default: 500)
+  --delay DURATION            Count down over the selection for DURATION, e.g.
+                              3s, before capturing it; the windows underneath
+                              take input meanwhile
+  --output-fd N               Write the result (geometry, image data, paths or
+                              statistics) to the inherited file descriptor N
+                              instead of stdout
+  --trace-wayland FILE        Record every Wayland request and event, with
+                              object ids and arguments, to FILE
+  --script PATH               Lua script customizing the selection (default:
+                              $XDG_CONFIG_HOME/glimpse/init.lua, if present);
+                              needs a build with the lua feature
+  --rpc                       Serve newline-delimited JSON-RPC requests (select,
+                              capture, pick, cancel) on stdin until it closes,
+                              answering on stdout
+  --driver                    Take commands on stdin while the overlay is
+                              shown: move DX DY, resize DW DH, set X,Y WxH,
+                              confirm and cancel, e.g. for automated tests
+  --record-input FILE         Save the pointer and keyboard input of the session
+                              to FILE, with timestamps
+  --replay-input FILE         Play back input saved with --record-input instead
+                              of taking live input
+  --filename TEMPLATE         strftime-style file name for --save; the
+                              extension follows the format, {app} is the
+                              focused window's app id and directories such as
+                              %Y/%m/ are created as needed
+                              (default: Screenshot_%Y-%m-%d_%H-%M-%S.png)
+  --layer LAYER               Layer shell layer: overlay (default), top, bottom
+                              or background
+  --namespace NAME            Layer surface namespace for compositor rules
+                              (default: rust-layer)
+  --no-custom-cursor          Use the cursor theme's crosshair instead of the
+                              bundled one (needs wp_cursor_shape_v1)
+  --cursor-size N             Show the crosshair N logical pixels across
+                              (default: $XCURSOR_SIZE, else 64)
+  --badge [CORNER]            Show the selection's size next to CORNER:
+                              top-left, top-right, bottom-left, bottom-right
+                              (default) or center
+  --buttons [CORNER]          Show Accept and Cancel buttons next to CORNER of
+                              a selection being edited, as for --badge, for
+                              confirming or cancelling with a click
+  --font FAMILY               Font for labels, resolved through fontconfig
+                              (default: sans-serif)
+  --corner-radius N           Round the selection's corners with radius N
+  --dim STYLE                 How the screen around the selection is dimmed:
+                              uniform (default) or spotlight, which keeps the
+                              area around the pointer clear, or none
+  --no-dim                    Leave the screen undimmed and only draw the
+                              selection and labels (same as --dim none)
+  --invert                    Darken the selection and leave the rest clear,
+                              to preview what a capture of everything else
+                              or a blackout region covers (same as
+                              --dim invert)
+  --inspect                   Show the pointer's coordinates and the color
+                              under it, even before selecting
+  --guides                    Extend the selection's edges to the screen's
+                              edges with faint lines, for checking alignment
+  --freeze                    Show the screen as it was when Glimpse started and
+                              capture from that, so content can't change or
+                              scroll away while selecting
+  --blur [RADIUS]             Blur the frozen screen around the selection
+                              instead of dimming it (default radius: 12);
+                              needs --freeze
+  --reduce-motion             Show the dim overlay at once instead of fading
+                              it in, and exit without flashing the confirmed
+                              selection (also --no-animation)
+  --announce                  Read out keyboard selections, and whether they
+                              were confirmed or cancelled, through
+                              speech-dispatcher or notifications
+";
+
+/// What Glimpse does, chosen by the first argument.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Select,
+    Watch { every: Duration }, // Capture the selection periodically
+    Record {                   // Record the selection to a video file
+        fps: u32,
+        duration: Option<Duration>, // None records until stopped
+    },
+    Diff { every: Option<Duration> }, // Capture the selection twice and show what changed
+    WatchChanges {                    // Report whenever the selection's content changes
+        every: Duration,
+        threshold: f64, // Percentage of pixels that may change unreported
+    },
+    Measure,                   // Print the distance between two clicked points
+    Contrast,                  // Print the contrast ratio of two clicked pixels
+    Pick,                      // Report one clicked pixel; only requested over --rpc
+    Output,                    // Select a whole output with a click
+    Window,                    // Select a window with a click
+    Outputs { json: bool },    // List the outputs and exit
+    Windows { json: bool },    // List the visible windows and exit
+    Replay,                    // Handle the newest history entry without showing anything
+    CheckConfig,               // Report problems in the configuration file and exit
+    Daemon,                    // Answer requests on a socket until killed
+    Client(ClientMethod),      // Send one request to the daemon
+    History {                  // List recorded selections, or print one of them
+        json: bool,
+        limit: Option<usize>,
+        pick: Option<usize>,
+    },
+}
+
+/// What `glimpse client` asks the daemon for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientMethod {
+    Select,
+    Capture,
+    Pick,
+}
+
+impl ClientMethod {
+    /// The RPC method.
+    pub fn name(self) -> &'static str {
+        match self {
+            ClientMethod::Select => "select",
+            ClientMethod::Capture => "capture",
+            ClientMethod::Pick => "pick",
+        }
+    }
+}
+
+impl Command {
+    /// Whether the command picks points instead of a rectangle.
+    pub fn picks_points(self) -> bool {
+        matches!(self, Command::Measure | Command::Contrast | Command::Pick)
+    }
+}
+
+/// How the selected geometry is printed when nothing is captured.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeometryFormat {
+    Plain,            // X,Y,WxH
+    Env,              // Shell variable assignments, for eval
+    Template(String), // With %x, %y, %w, %h, %o placeholders
+}
+
+/// Where `--histogram` sends the histograms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistogramOutput {
+    Json, // Printed to stdout
+    Plot, // Drawn into the capture
+}
+
+/// What a mouse button does when pressed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ButtonAction {
+    Select,  // Press starts a drag, release ends it (or a second press with --two-click)
+    Cancel,  // Exit without a selection
+    Confirm, // Finish the selection in progress
+    Output,  // Select the whole output under the pointer
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub command: Command,
+    pub button_bindings: Vec<(u32, ButtonAction)>,
+    pub key_bindings: Vec<(KeyCombo, KeyAction)>,
+    pub presets: [Preset; 9], // Regions for keys 1 to 9
+    pub custom_cursor: bool,
+    pub cursor_size: Option<u32>, // Logical size of the crosshair, from --cursor-size
+    pub animate: bool,
+    pub announce: bool, // Read keyboard selections out
+    pub corner_radius: i32,
+    pub hud: Hud,
+    pub font: Option<String>, // Fontconfig family for labels; None uses sans-serif
+    pub shadow: Option<Shadow>,
+    pub dim: Dim,
+    pub freeze: bool,
+    pub guides: bool,
+    pub blur: Option<u32>, // Radius for blurring the frozen screen instead of dimming it
+    pub two_click: bool,
+    pub confine: bool, // Confine the pointer to the output under it while dragging
+    pub precision: f64, // Pointer speed while Alt is held during a drag
+    pub snap_grid: i32,
+    pub snap: Snap, // What a drag snaps to at first
+    pub initial_geometry: Option<Rect>,
+    pub clipboard_geometry: bool, // Prefill from a geometry on the clipboard
+    pub adjust: bool,
+    pub allow_offscreen: bool, // Don't clamp the result to the outputs
+    pub capture: bool,
+    pub save: bool,
+    pub copy: bool,
+    pub clipboard_types: Vec<ClipboardType>, // Offered by --copy; empty for the first format
+    pub capture_cursor: bool,
+    pub deep_color: bool, // Keep 10-bit frames at 16 bits per channel
+    pub edit: bool,
+    pub editor: Option<String>, // None picks an installed editor
+    pub confirm_preview: bool, // Show the capture and wait for save, retry or cancel
+    pub notify: bool, // Send a desktop notification once delivered
+    pub palette: Option<usize>,
+    pub histogram: Option<HistogramOutput>,
+    pub resample: Option<Resample>,
+    pub filter: FilterType,
+    pub upscale: u32,
+    pub watermark: Option<Watermark>,
+    pub hooks: Hooks,
+    pub pipelines: Vec<(String, Vec<Stage>)>, // From [pipelines], for `run`
+    pub sounds: Sounds,
+    pub upload: Option<Upload>, // Where captures are posted, with --upload
+    pub upload_settings: Upload, // From [upload], for --upload without a target
+    pub s3: Option<S3>,         // Where captures are stored, with --s3
+    pub s3_settings: S3,        // From [s3]
+    pub copy_url: bool,         // Put the link from --upload or --s3 on the clipboard
+    pub script: Option<PathBuf>,
+    pub formats: Vec<Format>,
+    pub optimize: Option<Optimize>, // Shrink PNG output before it is written
+    pub geometry_format: GeometryFormat,
+    pub units: Option<Units>,
+    pub output_fd: Option<i32>,
+    pub trace_wayland: Option<PathBuf>, // File for a protocol trace
+    pub rpc: bool,
+    pub history: bool, // Record confirmed selections; only the glimpse command itself does
+    pub driver: bool, // Take selection commands on stdin
+    pub record_input: Option<PathBuf>, // File to record pointer and keyboard input to
+    pub replay_input: Option<PathBuf>, // Recording to play back instead of live input
+    pub filename: String,
+    pub burst: u32,
+    pub burst_interval: Duration,
+    pub delay: Option<Duration>, // Counted down on the overlay before capturing
+    pub layer: Layer,
+    pub namespace: String,
+    pub config_path: Option<PathBuf>, // The configuration file, read or not
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            command: Command::Select,
+            button_bindings: vec![
+                (BTN_LEFT, ButtonAction::Select),
+                (BTN_MIDDLE, ButtonAction::Output),
+            ],
+            key_bindings: keys::default_key_bindings(),
+            presets: Preset::defaults(),
+            custom_cursor: true,
+            cursor_size: None,
+            animate: true,
+            announce: false,
+            corner_radius: 0,
+            hud: Hud::default(),
+            font: None,
+            shadow: Some(Shadow::default()),
+            dim: Dim::Uniform,
+            freeze: false,
+            guides: false,
+            blur: None,
+            two_click: false,
+            confine: false,
+            precision: DEFAULT_PRECISION,
+            snap_grid: DEFAULT_SNAP_GRID,
+            snap: Snap::Off,
+            initial_geometry: None,
+            clipboard_geometry: true,
+            adjust: false,
+            allow_offscreen: false,
+            capture: false,
+            save: false,
+            copy: false,
+            clipboard_types: Vec::new(),
+            capture_cursor: false,
+            deep_color: false,
+            edit: false,
+            confirm_preview: false,
+            notify: false,
+            editor: None,
+            palette: None,
+            histogram: None,
+            resample: None,
+            filter: FilterType::Lanczos3,
+            upscale: 1,
+            watermark: None,
+            hooks: Hooks::default(),
+            pipelines: Vec::new(),
+            sounds: Sounds::default(),
+            upload: None,
+            upload_settings: Upload::default(),
+            s3: None,
+            s3_settings: S3::default(),
+            copy_url: false,
+            script: None,
+            formats: vec![Format::Png],
+            optimize: None,
+            geometry_format: GeometryFormat::Plain,
+            units: None,
+            output_fd: None,
+            trace_wayland: None,
+            rpc: false,
+            history: false,
+            driver: false,
+            record_input: None,
+            replay_input: None,
+            filename: "Screenshot_%Y-%m-%d_%H-%M-%S.png".into(),
+            burst: 1,
+            burst_interval: Duration::from_millis(500),
+            delay: None,
+            layer: Layer::Overlay,
+            namespace: "rust-layer".into(),
+            config_path: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration file, then applies the process arguments on
+    /// top of it. Prints usage and exits on `--help`.
+    pub fn from_args() -> Result<Self, String> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let mut config = Config::default();
+        let explicit = args.iter().position(|arg| arg == "--config").and_then(|i| args.get(i + 1));
+        config.config_path = explicit.map(PathBuf::from).or_else(paths::config_file);
+        // check-config reads the file on its own, reporting every bad line
+        let checking = args.first().is_some_and(|arg| arg == "check-config");
+        if let (Some(path), false) = (config.config_path.clone(), checking) {
+            config.load_file(&path, explicit.is_some())?;
+        }
+        config.history = true;
+        config.parse(args)?;
+        if config.formats.len() > 1 && !config.save {
+            return Err("writing several formats needs --save".into());
+        }
+        if config.deep_color {
+            if !config.formats.contains(&Format::Png) {
+                return Err("--deep-color keeps the extra bits only in png output".into());
+            }
+            let processed = config.resample.is_some() || config.upscale > 1 || config.watermark.is_some() || config.edit;
+            let analyzed = config.palette.is_some() || config.histogram.is_some();
+            if processed || analyzed || config.confirm_preview || config.freeze
+                || matches!(config.command, Command::Diff { .. } | Command::WatchChanges { .. })
+            {
+                return Err("--deep-color saves the capture as taken and can't be combined with --resize, --scale, --upscale, \
+                    a watermark, --edit, --palette, --histogram, --confirm-preview, --freeze, diff or watch-changes".into());
+            }
+        }
+        if config.optimize.is_some() {
+            if !cfg!(feature = "optimize") {
+                return Err("--optimize needs Glimpse built with the optimize feature".into());
+            }
+            if !config.formats.contains(&Format::Png) {
+                return Err("--optimize only shrinks PNG output".into());
+            }
+        }
+        if config.blur.is_some() && !config.freeze {
+            return Err("--blur needs --freeze".into());
+        }
+        if config.freeze && matches!(config.command, Command::Watch { .. }) {
+            return Err("watch captures the live screen and can't be combined with --freeze".into());
+        }
+        if config.freeze && matches!(config.command, Command::Diff { .. } | Command::WatchChanges { .. }) {
+            return Err("diff and watch-changes capture the live screen and can't be combined with --freeze".into());
+        }
+        if config.freeze && config.command == Command::Replay {
+            return Err("replay captures the live screen and can't be combined with --freeze".into());
+        }
+        if config.freeze && config.rpc {
+            return Err("--rpc captures the live screen for each request and can't be combined with --freeze".into());
+        }
+        if config.freeze && config.command == Command::Daemon {
+            return Err("daemon captures the live screen for each request and can't be combined with --freeze".into());
+        }
+        if let Command::Record { .. } = config.command {
+            if !cfg!(feature = "record") {
+                return Err("record --native needs Glimpse built with the record feature".into());
+            }
+            let single = config.copy || config.upload.is_some() || config.s3.is_some() || config.burst > 1;
+            if single || config.confirm_preview || config.deep_color || config.rpc {
+                return Err("record writes a video file and can't be combined with --copy, --upload, --s3, --burst, \
+                    --confirm-preview, --deep-color or --rpc".into());
+            }
+            if config.freeze {
+                return Err("record captures the live screen and can't be combined with --freeze".into());
+            }
+        }
+        if config.burst > 1 && !config.save {
+            return Err("--burst needs --save".into());
+        }
+        if config.geometry_format != GeometryFormat::Plain && config.capture {
+            return Err("--format env or a template prints the geometry and can't be combined with captures".into());
+        }
+        if config.driver && (config.rpc || config.command.picks_points()) {
+            return Err("--driver can't be combined with --rpc, measure or contrast".into());
+        }
+        let recorded = config.record_input.is_some() || config.replay_input.is_some();
+        if recorded && (config.rpc || config.driver || config.command == Command::Daemon) {
+            return Err("--record-input and --replay-input can't be combined with --rpc, --driver or daemon".into());
+        }
+        if config.confirm_preview {
+            if !config.capture || config.command != Command::Select || config.burst > 1 {
+                return Err("--confirm-preview needs a single capture of a selection, with --capture, --save or --copy".into());
+            }
+            if config.rpc || config.driver || config.replay_input.is_some() {
+                return Err("--confirm-preview can't be combined with --rpc, --driver or --replay-input".into());
+            }
+        }
+        if config.delay.is_some() {
+            if !config.capture {
+                return Err("--delay needs a capture, with --capture, --save or --copy".into());
+            }
+            if config.freeze || config.rpc || config.command == Command::Replay {
+                return Err("--delay counts down over a live selection and can't be combined with --freeze, --rpc or replay".into());
+            }
+        }
+        if let Some(upload) = &config.upload {
+            if !cfg!(feature = "upload") {
+                return Err("uploading needs Glimpse built with the upload feature".into());
+            }
+            if upload.url.is_empty() {
+                return Err("--upload needs a host: a URL, a preset such as 0x0, or url under [upload]".into());
+            }
+        }
+        if let Some(s3) = &config.s3 {
+            if !cfg!(feature = "s3") {
+                return Err("S3 uploads need Glimpse built with the s3 feature".into());
+            }
+            if s3.bucket.is_empty() {
+                return Err("--s3 needs a bucket: --s3 BUCKET, or bucket under [s3]".into());
+            }
+            if config.upload.is_some() {
+                return Err("--s3 and --upload can't be combined".into());
+            }
+        }
+        if config.upload.is_some() || config.s3.is_some() {
+            if config.burst > 1 || config.rpc || matches!(config.command, Command::Watch { .. } | Command::WatchChanges { .. }) {
+                return Err("--upload and --s3 send a single capture and can't be combined with --burst, --rpc, watch or watch-changes".into());
+            }
+            if config.copy_url && config.copy {
+                return Err("--copy-url and --copy can't both have the clipboard".into());
+            }
+        }
+        if config.rpc && config.command != Command::Select {
+            return Err("--rpc takes no command; send requests instead".into());
+        }
+        Ok(config)
+    }
+
+    /// Settings from the configuration file alone, at `path` or the usual
+    /// place, for selections started by other programs through the library.
+    pub fn from_file(path: Option<&Path>) -> Result<Self, String> {
+        let mut config = Config {
+            config_path: path.map(Path::to_path_buf).or_else(paths::config_file),
+            ..Config::default()
+        };
+        if let Some(file) = config.config_path.clone() {
+            config.load_file(&file, path.is_some())?;
+        }
+        Ok(config)
+    }
+
+    /// Applies the settings in `path`. A missing file is only an error when
+    /// it was asked for explicitly.
+    fn load_file(&mut self, path: &Path, required: bool) -> Result<(), String> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => return Ok(()),
+            Err(err) => return Err(format!("{}: {err}", path.display())),
+        };
+        let entries = config_file::parse(&contents).map_err(|err| format!("{}: {err}", path.display()))?;
+        // The running compositor's overrides go last, whatever their place
+        let compositor = compositor::name();
+        let (overrides, general): (Vec<_>, Vec<_>) = entries.iter().partition(|entry| entry.for_compositor().is_some());
+        let overrides = overrides.iter().filter_map(|entry| entry.for_compositor())
+            .filter(|(name, _)| compositor.as_deref().is_some_and(|c| c.eq_ignore_ascii_case(name)))
+            .map(|(_, entry)| entry);
+        for entry in general.into_iter().cloned().chain(overrides) {
+            self.apply_entry(&entry)
+                .map_err(|err| format!("{}: line {}: {err}", path.display(), entry.line))?;
+        }
+        if self.watermark.as_ref().is_some_and(|w| w.image.as_os_str().is_empty()) {
+            return Err(format!("{}: [watermark] needs an image", path.display()));
+        }
+        Ok(())
+    }
+
+    /// Applies one setting from the configuration file.
+    pub fn apply_entry(&mut self, entry: &Entry) -> Result<(), String> {
+        let value = entry.value.as_str();
+        match (entry.section.as_str(), entry.key.as_str()) {
+            ("watermark", key) => {
+                let watermark = self.watermark.get_or_insert_with(Watermark::default);
+                match key {
+                    "image" => watermark.image = paths::expand_home(value),
+                    "position" => watermark.position = process::parse_position(value)?,
+                    "opacity" => {
+                        watermark.opacity = value.parse().ok().filter(|o| (0.0..=1.0).contains(o))
+                            .ok_or_else(|| format!("invalid opacity '{value}', expected 0.0 to 1.0"))?;
+                    }
+                    "margin" => {
+                        watermark.margin = value.parse().map_err(|_| format!("invalid margin '{value}'"))?;
+                    }
+                    _ => return Err(format!("unknown setting '{key}' in [watermark]")),
+                }
+            }
+            ("hud", key) => {
+                let anchor = hud::parse_anchor(value)?;
+                match key {
+                    "size" => self.hud.size = anchor,
+                    "coordinates" => self.hud.coordinates = anchor,
+                    "hints" => self.hud.hints = anchor,
+                    "readout" => self.hud.readout = anchor,
+                    "buttons" => self.hud.buttons = parse_buttons_anchor(anchor)?,
+                    "toolbar" => self.hud.toolbar = parse_buttons_anchor(anchor)?,
+                    _ => return Err(format!("unknown setting '{key}' in [hud]")),
+                }
+            }
+            ("theme", key) => match key {
+                "reduce_motion" => {
+                    let reduce: bool = value.parse()
+                        .map_err(|_| format!("invalid reduce_motion '{value}', expected true or false"))?;
+                    self.animate = !reduce;
+                }
+                "shadow_size" => {
+                    let size: i32 = value.parse().ok().filter(|s| (0..=100).contains(s))
+                        .ok_or_else(|| format!("invalid shadow size '{value}', expected 0 to 100"))?;
+                    let opacity = self.shadow.unwrap_or_default().opacity;
+                    // A size of 0 turns the shadow off
+                    self.shadow = (size > 0).then_some(Shadow { size, opacity });
+                }
+                "font" => self.font = Some(value.to_string()),
+                "dim" => self.dim = parse_dim(value, self.dim)?,
+                "spotlight_radius" => {
+                    let radius = value.parse().ok().filter(|r| (10..=2000).contains(r))
+                        .ok_or_else(|| format!("invalid spotlight radius '{value}', expected 10 to 2000"))?;
+                    self.dim = Dim::Spotlight { radius };
+                }
+                "shadow_opacity" => {
+                    let opacity = value.parse().ok().filter(|o| (0.0..=1.0).contains(o))
+                        .ok_or_else(|| format!("invalid opacity '{value}', expected 0.0 to 1.0"))?;
+                    if let Some(shadow) = &mut self.shadow {
+                        shadow.opacity = opacity;
+                    }
+                }
+                _ => return Err(format!("unknown setting '{key}' in [theme]")),
+            },
+            ("presets", key) => {
+                let index = match key.parse() {
+                    Ok(number @ 1..=9) => number - 1,
+                    _ => return Err(format!("unknown preset key '{key}', expected 1 to 9")),
+                };
+                self.presets[index] = Preset::parse(value)?;
+            }
+            ("pipelines", name) => {
+                let stages = pipeline::parse(value)?;
+                self.pipelines.retain(|(other, _)| other != name);
+                self.pipelines.push((name.to_string(), stages));
+            }
+            ("hooks", key) => {
+                let hook = match key {
+                    "on_success" => &mut self.hooks.on_success,
+                    "on_cancel" => &mut self.hooks.on_cancel,
+                    "on_save" => &mut self.hooks.on_save,
+                    "on_change" => &mut self.hooks.on_change,
+                    _ => return Err(format!("unknown hook '{key}'")),
+                };
+                *hook = Some(value.to_string()).filter(|command| !command.is_empty());
+            }
+            ("upload", key) => {
+                let upload = &mut self.upload_settings;
+                match key {
+                    "url" => upload.set_target(value)?,
+                    "field" => upload.field = value.to_string(),
+                    "form" => upload.add_field(value)?,
+                    "header" => upload.add_header(value)?,
+                    "url_key" => upload.url_key = Some(value.to_string()),
+                    "copy_url" => self.copy_url = parse_copy_url(value)?,
+                    _ => return Err(format!("unknown setting '{key}' in [upload]")),
+                }
+            }
+            ("s3", key) => {
+                let s3 = &mut self.s3_settings;
+                let url = |value: &str| match value.starts_with("https://") || value.starts_with("http://") {
+                    true => Ok(value.trim_end_matches('/').to_string()),
+                    false => Err(format!("invalid {key} '{value}', expected an http(s) URL")),
+                };
+                match key {
+                    "bucket" => s3.bucket = value.to_string(),
+                    "endpoint" => s3.endpoint = Some(url(value)?),
+                    "region" => s3.region = Some(value.to_string()),
+                    "path_style" => {
+                        s3.path_style = Some(value.parse().map_err(|_| format!("invalid path_style '{value}', expected true or false"))?);
+                    }
+                    "prefix" => s3.prefix = value.to_string(),
+                    "public_url" => s3.public_url = Some(url(value)?),
+                    "expires" => {
+                        s3.expires = value.parse().ok().filter(|seconds| (1..=s3::MAX_EXPIRES).contains(seconds))
+                            .ok_or_else(|| format!("invalid expires '{value}', expected 1 to {} seconds", s3::MAX_EXPIRES))?;
+                    }
+                    "access_key" => s3.access_key = Some(value.to_string()),
+                    "secret_key" => s3.secret_key = Some(value.to_string()),
+                    "session_token" => s3.session_token = Some(value.to_string()),
+                    "copy_url" => self.copy_url = parse_copy_url(value)?,
+                    _ => return Err(format!("unknown setting '{key}' in [s3]")),
+                }
+            }
+            ("sounds", key) => match key {
+                "player" => self.sounds.player = Some(value.to_string()).filter(|command| !command.is_empty()),
+                "confirm" => self.sounds.confirm = sounds::parse_sound(value),
+                "cancel" => self.sounds.cancel = sounds::parse_sound(value),
+                "save" => self.sounds.save = sounds::parse_sound(value),
+                _ => return Err(format!("unknown setting '{key}' in [sounds]")),
+            },
+            ("", "layer") => self.layer = parse_layer(value)?,
+            ("", "namespace") => self.namespace = value.to_string(),
+            ("", "snap") => self.snap = Snap::parse(value)?,
+            ("", "snap_grid") => self.snap_grid = parse_snap_grid(value)?,
+            ("", "clipboard_types") => self.clipboard_types = clipboard::parse_types(value)?,
+            ("", key) => return Err(format!("unknown setting '{key}'")),
+            (section, _) => return Err(format!("unknown section [{section}]")),
+        }
+        Ok(())
+    }
+
+    fn parse(&mut self, args: impl IntoIterator<Item = String>) -> Result<(), String> {
+        let mut args = args.into_iter().peekable();
+        let mut every = None;
+        let (mut native, mut fps, mut duration) = (false, None, None);
+        let mut threshold = None;
+        let mut on_change = None;
+        let mut copy_url = false;
+        let (mut json, mut limit, mut pick) = (false, None, None);
+        let command = args.next_if(|arg| !arg.starts_with('-'));
+        // The client's method or the pipeline to run
+        let argument = args.next_if(|arg| matches!(command.as_deref(), Some("client" | "run")) && !arg.starts_with('-'));
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    print!("{USAGE}");
+                    std::process::exit(0);
+                }
+                "--bind-button" => {
+                    let value = next_value(&mut args, &arg)?;
+                    self.bind_button(&value)?;
+                }
+                "--no-custom-cursor" => self.custom_cursor = false,
+                "--cursor-size" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let size = value.parse().ok().filter(|size| cursor::CURSOR_SIZES.contains(size)).ok_or_else(|| {
+                        format!("invalid cursor size '{value}', expected {} to {}", cursor::CURSOR_SIZES.start(), cursor::CURSOR_SIZES.end())
+                    })?;
+                    self.cursor_size = Some(size);
+                }
+                "--reduce-motion" | "--no-animation" => self.animate = false,
+                "--announce" => self.announce = true,
+                "--no-dim" => self.dim = Dim::Off,
+                "--invert" => self.dim = Dim::Invert,
+                "--freeze" => self.freeze = true,
+                "--guides" => self.guides = true,
+                "--inspect" => {
+                    self.hud.coordinates.get_or_insert(Anchor::Cursor);
+                }
+                "--blur" => {
+                    let radius = args.next_if(|value| !value.starts_with('-'));
+                    self.blur = Some(match radius {
+                        Some(value) => value.parse().ok().filter(|r| (1..=100).contains(r))
+                            .ok_or_else(|| format!("invalid blur radius '{value}', expected 1 to 100"))?,
+                        None => DEFAULT_BLUR_RADIUS,
+                    });
+                }
+                "--dim" => self.dim = parse_dim(&next_value(&mut args, &arg)?, self.dim)?,
+                "--badge" => {
+                    let corner = args.next_if(|value| !value.starts_with('-'));
+                    let corner = corner.as_deref().map_or(Ok(Position::BottomRight), process::parse_position)?;
+                    self.hud.size = Some(match corner {
+                        Position::Center => Anchor::Inside(corner),
+                        corner => Anchor::Outside(corner),
+                    });
+                }
+                "--buttons" => {
+                    let corner = args.next_if(|value| !value.starts_with('-'));
+                    let corner = corner.as_deref().map_or(Ok(Position::BottomRight), process::parse_position)?;
+                    self.hud.buttons = Some(match corner {
+                        Position::Center => Anchor::Inside(corner),
+                        corner => Anchor::Outside(corner),
+                    });
+                }
+                "--font" => self.font = Some(next_value(&mut args, &arg)?),
+                "--corner-radius" => {
+                    let value = next_value(&mut args, &arg)?;
+                    self.corner_radius = value.parse().ok().filter(|r| (0..=1000).contains(r))
+                        .ok_or_else(|| format!("invalid corner radius '{value}'"))?;
+                }
+                "--bind-key" => {
+                    let value = next_value(&mut args, &arg)?;
+                    self.bind_key(&value)?;
+                }
+                "--two-click" => self.two_click = true,
+                "--confine" => self.confine = true,
+                "--precision" => {
+                    let value = next_value(&mut args, &arg)?;
+                    self.precision = value.parse().ok().filter(|f| (0.01..=1.0).contains(f))
+                        .ok_or_else(|| format!("invalid precision '{value}', expected 0.01 to 1.0"))?;
+                }
+                "--snap-grid" => self.snap_grid = parse_snap_grid(&next_value(&mut args, &arg)?)?,
+                "--snap" => self.snap = Snap::parse(&next_value(&mut args, &arg)?)?,
+                "--initial" | "--initial-geometry" => {
+                    let mut value = next_value(&mut args, &arg)?;
+                    // Accept the size as a separate argument: --initial 10,20 300x200
+                    if !value.contains('x') {
+                        if let Some(size) = args.next_if(|size| !size.starts_with('-')) {
+                            value = format!("{value} {size}");
+                        }
+                    }
+                    let rect = Rect::parse(&value).ok_or_else(|| format!("invalid geometry '{value}', expected X,Y WxH"))?;
+                    self.initial_geometry = Some(rect);
+                }
+                "--no-clipboard-geometry" => self.clipboard_geometry = false,
+                "--adjust" => self.adjust = true,
+                "--allow-offscreen" => self.allow_offscreen = true,
+                "--capture" => self.capture = true,
+                "--save" => {
+                    self.capture = true;
+                    self.save = true;
+                }
+                "--optimize" => {
+                    let zopfli = args.next_if(|value| value == "zopfli").is_some();
+                    self.optimize = Some(if zopfli { Optimize::Zopfli } else { Optimize::Fast });
+                }
+                "--upload" => {
+                    let mut upload = self.upload_settings.clone();
+                    if let Some(target) = args.next_if(|value| !value.starts_with('-')) {
+                        upload.set_target(&target)?;
+                    }
+                    self.capture = true;
+                    self.upload = Some(upload);
+                }
+                "--s3" => {
+                    let mut s3 = self.s3_settings.clone();
+                    if let Some(bucket) = args.next_if(|value| !value.starts_with('-')) {
+                        s3.bucket = bucket;
+                    }
+                    self.capture = true;
+                    self.s3 = Some(s3);
+                }
+                "--copy-url" => copy_url = true,
+                "--clipboard-types" => self.clipboard_types = clipboard::parse_types(&next_value(&mut args, &arg)?)?,
+                "--copy" => {
+                    self.capture = true;
+                    self.copy = true;
+                }
+                "--cursor" => self.capture_cursor = true,
+                "--deep-color" => self.deep_color = true,
+                "--confirm-preview" => self.confirm_preview = true,
+                "--notify" => self.notify = true,
+                "--edit" => {
+                    self.capture = true;
+                    self.edit = true;
+                    self.editor = args.next_if(|value| !value.starts_with('-'));
+                }
+                "--histogram" => {
+                    self.histogram = Some(match next_value(&mut args, &arg)?.as_str() {
+                        "json" => HistogramOutput::Json,
+                        "plot" => HistogramOutput::Plot,
+                        other => return Err(format!("unknown histogram format '{other}'")),
+                    });
+                    self.capture = true;
+                }
+                "--palette" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let count = value.parse().ok().filter(|n| (1..=256).contains(n))
+                        .ok_or_else(|| format!("invalid palette size '{value}', expected 1 to 256"))?;
+                    self.capture = true;
+                    self.palette = Some(count);
+                }
+                "--resize" => self.resample = Some(process::parse_size(&next_value(&mut args, &arg)?)?),
+                "--scale" => self.resample = Some(process::parse_scale(&next_value(&mut args, &arg)?)?),
+                "--filter" => self.filter = process::parse_filter(&next_value(&mut args, &arg)?)?,
+                "--upscale" => {
+                    let value = next_value(&mut args, &arg)?;
+                    self.upscale = value.parse().ok().filter(|&n| (1..=64).contains(&n))
+                        .ok_or_else(|| format!("invalid upscale factor '{value}', expected 1 to 64"))?;
+                }
+                "-f" | "--format" => {
+                    let value = next_value(&mut args, &arg)?;
+                    if value == "env" {
+                        self.geometry_format = GeometryFormat::Env;
+                    } else if value.contains('%') {
+                        geometry::check_template(&value)?;
+                        self.geometry_format = GeometryFormat::Template(value);
+                    } else {
+                        self.formats = encode::parse_formats(&value)?;
+                    }
+                }
+                "--burst" => {
+                    let value = next_value(&mut args, &arg)?;
+                    self.burst = value.parse().ok().filter(|&n| n > 0)
+                        .ok_or_else(|| format!("invalid burst count '{value}'"))?;
+                }
+                "--interval" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let ms = value.parse().map_err(|_| format!("invalid interval '{value}'"))?;
+                    self.burst_interval = Duration::from_millis(ms);
+                }
+                "--delay" => self.delay = Some(parse_duration(&next_value(&mut args, &arg)?)?),
+                "--output-fd" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let fd = value.parse().ok().filter(|&fd| fd >= 0)
+                        .ok_or_else(|| format!("invalid file descriptor '{value}'"))?;
+                    self.output_fd = Some(fd);
+                }
+                "--trace-wayland" => self.trace_wayland = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--units" => self.units = Some(geometry::parse_units(&next_value(&mut args, &arg)?)?),
+                "--script" => self.script = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--rpc" => self.rpc = true,
+                "--driver" => self.driver = true,
+                "--record-input" => self.record_input = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--replay-input" => self.replay_input = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--json" => json = true,
+                "-n" => {
+                    let value = next_value(&mut args, &arg)?;
+                    limit = Some(value.parse().ok().filter(|&n| n > 0)
+                        .ok_or_else(|| format!("invalid entry count '{value}'"))?);
+                }
+                "--pick" => {
+                    let value = args.next_if(|value| !value.starts_with('-')).unwrap_or_else(|| "1".into());
+                    pick = Some(value.parse().ok().filter(|&n| n > 0)
+                        .ok_or_else(|| format!("invalid history entry '{value}'"))?);
+                }
+                "--every" => every = Some(parse_duration(&next_value(&mut args, &arg)?)?),
+                "--native" => native = true,
+                "--fps" => {
+                    let value = next_value(&mut args, &arg)?;
+                    fps = Some(value.parse().ok().filter(|fps| (1..=record::MAX_FPS).contains(fps))
+                        .ok_or_else(|| format!("invalid frame rate '{value}', expected 1 to {}", record::MAX_FPS))?);
+                }
+                "--duration" => duration = Some(parse_duration(&next_value(&mut args, &arg)?)?),
+                "--threshold" => {
+                    let value = next_value(&mut args, &arg)?;
+                    threshold = Some(value.parse().ok().filter(|t| (0.0..100.0).contains(t))
+                        .ok_or_else(|| format!("invalid threshold '{value}', expected a percentage from 0 to under 100"))?);
+                }
+                "--on-change" => on_change = Some(next_value(&mut args, &arg)?),
+                "--filename" => {
+                    let value = next_value(&mut args, &arg)?;
+                    sinks::check_filename(&value)?;
+                    self.filename = value;
+                }
+                "--layer" => self.layer = parse_layer(&next_value(&mut args, &arg)?)?,
+                "--namespace" => self.namespace = next_value(&mut args, &arg)?,
+                "--config" => {
+                    next_value(&mut args, &arg)?; // Already read by from_args
+                }
+                _ => return Err(format!("unknown option '{arg}'\n\n{USAGE}")),
+            }
+        }
+        self.command = match command.as_deref() {
+            None | Some("select") => Command::Select,
+            Some("watch") => {
+                let every = every.ok_or("watch needs --every")?;
+                // Watch always writes files
+                self.capture = true;
+                self.save = true;
+                Command::Watch { every }
+            }
+            Some("record") => {
+                // Room for handing off to an external recorder later
+                if !native {
+                    return Err("record needs --native, the built-in recorder".into());
+                }
+                self.capture = true;
+                Command::Record { fps: fps.unwrap_or(record::DEFAULT_FPS), duration }
+            }
+            Some("watch-changes") => {
+                // Captures are compared, never written
+                self.capture = true;
+                Command::WatchChanges { every: every.unwrap_or(DEFAULT_WATCH_INTERVAL), threshold: threshold.unwrap_or(0.0) }
+            }
+            Some("diff") => {
+                self.capture = true;
+                Command::Diff { every }
+            }
+            Some(command @ ("measure" | "contrast")) => {
+                // One click for each point
+                self.two_click = true;
+                if command == "measure" { Command::Measure } else { Command::Contrast }
+            }
+            Some("output") => Command::Output,
+            Some("window") => Command::Window,
+            Some("outputs") => Command::Outputs { json },
+            Some("windows") => Command::Windows { json },
+            Some("replay") => Command::Replay,
+            Some("check-config") => Command::CheckConfig,
+            Some("daemon") => Command::Daemon,
+            Some("run") => {
+                let name = argument.as_deref().ok_or("run needs the name of a pipeline")?;
+                let (_, stages) = self.pipelines.iter().find(|(other, _)| other == name).cloned().ok_or_else(|| {
+                    let names: Vec<_> = self.pipelines.iter().map(|(name, _)| name.as_str()).collect();
+                    if names.is_empty() {
+                        format!("unknown pipeline '{name}'; add it under [pipelines] in the configuration file")
+                    } else {
+                        format!("unknown pipeline '{name}', expected {}", names.join(", "))
+                    }
+                })?;
+                pipeline::apply(&stages, self)
+            }
+            Some("client") => Command::Client(match argument.as_deref() {
+                None | Some("select") => ClientMethod::Select,
+                Some("capture") => ClientMethod::Capture,
+                Some("pick") => ClientMethod::Pick,
+                Some(other) => return Err(format!("unknown client method '{other}', expected select, capture or pick")),
+            }),
+            Some("history") => Command::History { json, limit, pick },
+            Some(other) => return Err(format!("unknown command '{other}'\n\n{USAGE}")),
+        };
+        if every.is_some() && !matches!(self.command, Command::Watch { .. } | Command::Diff { .. } | Command::WatchChanges { .. }) {
+            return Err("--every is only valid for watch, diff and watch-changes".into());
+        }
+        if threshold.is_some() && !matches!(self.command, Command::WatchChanges { .. }) {
+            return Err("--threshold is only valid for watch-changes".into());
+        }
+        if on_change.is_some() {
+            if !matches!(self.command, Command::WatchChanges { .. }) {
+                return Err("--on-change is only valid for watch-changes".into());
+            }
+            self.hooks.on_change = on_change;
+        }
+        if (native || fps.is_some() || duration.is_some()) && !matches!(self.command, Command::Record { .. }) {
+            return Err("--native, --fps and --duration are only valid for record".into());
+        }
+        if copy_url {
+            if self.upload.is_none() && self.s3.is_none() {
+                return Err("--copy-url needs --upload or --s3".into());
+            }
+            self.copy_url = true;
+        }
+        if json && !matches!(self.command, Command::History { .. } | Command::Outputs { .. } | Command::Windows { .. }) {
+            return Err("--json is only valid for history, outputs and windows".into());
+        }
+        if (limit.is_some() || pick.is_some()) && !matches!(self.command, Command::History { .. }) {
+            return Err("-n and --pick are only valid for history".into());
+        }
+        Ok(())
+    }
+
+    /// Applies a `BUTTON=ACTION` binding, replacing any previous one for that button.
+    fn bind_button(&mut self, spec: &str) -> Result<(), String> {
+        let (button, action) = spec.split_once('=')
+            .ok_or_else(|| format!("invalid button binding '{spec}', expected BUTTON=ACTION"))?;
+        let button = parse_button(button)?;
+        let action = match action {
+            "select" => Some(ButtonAction::Select),
+            "cancel" => Some(ButtonAction::Cancel),
+            "confirm" => Some(ButtonAction::Confirm),
+            "output" => Some(ButtonAction::Output),
+            "none" => None,
+            _ => return Err(format!("unknown button action '{action}'")),
+        };
+        self.button_bindings.retain(|&(b, _)| b != button);
+        if let Some(action) = action {
+            self.button_bindings.push((button, action));
+        }
+        Ok(())
+    }
+
+    /// Applies a `KEY=ACTION` binding, replacing any previous one for that key combo.
+    fn bind_key(&mut self, spec: &str) -> Result<(), String> {
+        let (combo, action) = spec.rsplit_once('=')
+            .ok_or_else(|| format!("invalid key binding '{spec}', expected KEY=ACTION"))?;
+        let combo = keys::parse_combo(combo)?;
+        let action = keys::parse_action(action)?;
+        self.key_bindings.retain(|&(c, _)| c != combo);
+        if let Some(action) = action {
+            self.key_bindings.push((combo, action));
+        }
+        Ok(())
+    }
+
+    /// What --copy offers, in order.
+    pub fn offered_types(&self) -> Vec<ClipboardType> {
+        if self.clipboard_types.is_empty() {
+            vec![ClipboardType::Image(self.formats[0])]
+        } else {
+            self.clipboard_types.clone()
+        }
+    }
+
+    /// The formats to encode: those written, then any more the clipboard
+    /// offers.
+    pub fn encoded_formats(&self) -> Vec<Format> {
+        let mut formats = self.formats.clone();
+        if self.copy {
+            for kind in self.offered_types() {
+                match kind {
+                    ClipboardType::Image(format) if !formats.contains(&format) => formats.push(format),
+                    _ => {}
+                }
+            }
+        }
+        formats
+    }
+
+    /// Whether captures are encoded and written somewhere, rather than only
+    /// analyzed.
+    pub fn writes_image(&self) -> bool {
+        let analyzing = self.palette.is_some() || self.histogram == Some(HistogramOutput::Json);
+        self.save || self.copy || !analyzing
+    }
+
+    /// Looks up the action for a key. Alt is only matched when a binding
+    /// asks for it; otherwise it selects fine steps, reported as `true`.
+    pub fn key_action(&self, key: u32, mods: u8) -> Option<(KeyAction, bool)> {
+        let lookup = |mods| self.key_bindings.iter()
+            .find(|&&(c, _)| c == KeyCombo { key, mods })
+            .map(|&(_, action)| action);
+        lookup(mods).map(|action| (action, false))
+            .or_else(|| lookup(mods & !MOD_ALT).map(|action| (action, mods & MOD_ALT != 0)))
+    }
+
+    pub fn button_action(&self, button: u32) -> Option<ButtonAction> {
+        self.button_bindings.iter().find(|&&(b, _)| b == button).map(|&(_, action)| action)
+    }
+}
+
+/// Rejects an anchor for the buttons that would move away from the pointer
+/// on its way to them.
+fn parse_buttons_anchor(anchor: Option<Anchor>) -> Result<Option<Anchor>, String> {
+    match anchor {
+        Some(Anchor::Cursor) => Err("the buttons can't follow the pointer; anchor them to the selection or screen".into()),
+        anchor => Ok(anchor),
+    }
+}
+
+fn parse_copy_url(value: &str) -> Result<bool, String> {
+    value.parse().map_err(|_| format!("invalid copy_url '{value}', expected true or false"))
+}
+
+fn parse_layer(name: &str) -> Result<Layer, String> {
+    match name {
+        "overlay" => Ok(Layer::Overlay),
+        "top" => Ok(Layer::Top),
+        "bottom" => Ok(Layer::Bottom),
+        "background" => Ok(Layer::Background),
+        other => Err(format!("unknown layer '{other}'")),
+    }
+}
+
+fn parse_snap_grid(value: &str) -> Result<i32, String> {
+    value.parse().ok().filter(|s| (2..=1000).contains(s))
+        .ok_or_else(|| format!("invalid grid spacing '{value}', expected 2 to 1000"))
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("option '{flag}' requires a value"))
+}
+
+/// Parses a dim style name, keeping the spotlight radius of `current`.
+fn parse_dim(name: &str, current: Dim) -> Result<Dim, String> {
+    match name {
+        "uniform" => Ok(Dim::Uniform),
+        "none" => Ok(Dim::Off),
+        "invert" => Ok(Dim::Invert),
+        "spotlight" => match current {
+            Dim::Spotlight { .. } => Ok(current),
+            _ => Ok(Dim::Spotlight { radius: DEFAULT_SPOTLIGHT_RADIUS }),
+        },
+        _ => Err(format!("unknown dim style '{name}', expected uniform, spotlight, none or invert")),
+    }
+}
+
+/// Parses a duration such as `250ms`, `5s` or `1m`; plain numbers are seconds.
+pub fn parse_duration(spec: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{spec}'");
+    let (number, unit) = spec.find(|c: char| c.is_ascii_alphabetic())
+        .map_or((spec, "s"), |i| spec.split_at(i));
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        _ => return Err(invalid()),
+    };
+    Duration::try_from_secs_f64(seconds).ok().filter(|d| !d.is_zero()).ok_or_else(invalid)
+}
+
+fn parse_button(name: &str) -> Result<u32, String> {
+    Ok(match name {
+        "left" => BTN_LEFT,
+        "right" => BTN_RIGHT,
+        "middle" => BTN_MIDDLE,
+        "side" => BTN_SIDE,
+        "extra" => BTN_EXTRA,
+        _ => {
+            let code = match name.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => name.parse(),
+            };
+            code.map_err(|_| format!("unknown mouse button '{name}'"))?
+        }
+    })
+}
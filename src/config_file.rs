@@ -0,0 +1,69 @@
+//! The configuration file: `key = value` lines grouped under `[section]`
+//! headers, with `#` starting a comment.
+//!
+//! ```text
+//! [watermark]
+//! image = ~/Pictures/stamp.png
+//! position = bottom-right
+//!
+//! [compositor.sway]
+//! theme.dim = none
+//! ```
+//!
+//! Settings under `[compositor.NAME]` only apply on that compositor, over
+//! the others wherever they are in the file.
+
+/// A single setting, with the 1-based line it came from for error messages.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub section: String,
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+}
+
+impl Entry {
+    /// For a setting under `[compositor.NAME]`, the compositor it is for and
+    /// the setting itself, with a `section.key` key split up.
+    pub fn for_compositor(&self) -> Option<(&str, Entry)> {
+        let name = self.section.strip_prefix("compositor.")?;
+        let (section, key) = self.key.split_once('.').unwrap_or(("", &self.key));
+        let entry = Entry { section: section.to_string(), key: key.to_string(), value: self.value.clone(), line: self.line };
+        Some((name, entry))
+    }
+}
+
+pub fn parse(contents: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    let mut section = String::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name.strip_suffix(']')
+                .ok_or_else(|| format!("line {line_number}: unterminated section header"))?;
+            section = name.trim().to_string();
+            continue;
+        }
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| format!("line {line_number}: expected 'key = value'"))?;
+        // Quotes are optional, but allow values with surrounding spaces or a
+        // `#`; unquoted values end at a ` #` comment
+        let value = value.trim();
+        let quoted = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).filter(|v| !v.contains('"'));
+        let value = match quoted {
+            Some(quoted) => quoted,
+            None => value.split(" #").next().unwrap_or_default().trim_end(),
+        };
+        entries.push(Entry {
+            section: section.clone(),
+            key: key.trim().to_string(),
+            value: value.to_string(),
+            line: line_number,
+        });
+    }
+    Ok(entries)
+}
@@ -0,0 +1,59 @@
+//! WCAG 2 contrast ratios for `glimpse contrast`.
+
+use std::fmt;
+
+/// Contrast between a foreground and a background color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contrast {
+    pub foreground: [u8; 3],
+    pub background: [u8; 3],
+    pub ratio: f64, // 1.0 to 21.0
+}
+
+impl Contrast {
+    pub fn new(foreground: [u8; 3], background: [u8; 3]) -> Self {
+        let (a, b) = (luminance(foreground), luminance(background));
+        let ratio = (a.max(b) + 0.05) / (a.min(b) + 0.05);
+        Self { foreground, background, ratio }
+    }
+
+    /// Pass/fail per WCAG level: (name, minimum ratio, passed).
+    pub fn levels(&self) -> [(&'static str, f64, bool); 4] {
+        [("AA", 4.5), ("AA-large", 3.0), ("AAA", 7.0), ("AAA-large", 4.5)]
+            .map(|(name, minimum)| (name, minimum, self.ratio >= minimum))
+    }
+
+    /// Short text for the overlay label.
+    pub fn label(&self) -> String {
+        let mut label = format!("{} on {}  {:.2}:1", hex(self.foreground), hex(self.background), self.ratio);
+        for (name, _, passed) in self.levels() {
+            label += &format!("  {name} {}", if passed { "✓" } else { "✗" });
+        }
+        label
+    }
+}
+
+/// Machine-readable form printed on exit, e.g.
+/// `fg=#777777 bg=#ffffff ratio=4.48 AA=fail AA-large=pass ...`.
+impl fmt::Display for Contrast {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fg={} bg={} ratio={:.2}", hex(self.foreground), hex(self.background), self.ratio)?;
+        for (name, _, passed) in self.levels() {
+            write!(f, " {name}={}", if passed { "pass" } else { "fail" })?;
+        }
+        Ok(())
+    }
+}
+
+pub fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Relative luminance of an sRGB color.
+pub fn luminance(color: [u8; 3]) -> f64 {
+    let [r, g, b] = color.map(|channel| {
+        let c = channel as f64 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    });
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
@@ -0,0 +1,133 @@
+//! `glimpse daemon` and `glimpse client`: a Glimpse left running with its
+//! globals bound, its cursor and font loaded and the overlays' buffers
+//! kept, so a selection shows up without connecting and setting up first.
+//!
+//! The daemon answers the `--rpc` methods on a Unix socket in
+//! `$XDG_RUNTIME_DIR`, one client at a time. `glimpse client METHOD` sends
+//! one request and prints the result the way Glimpse itself would.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+use base64::Engine as _;
+use serde_json::{json, Value};
+use wayland_client::backend::WaylandError;
+use wayland_client::EventQueue;
+
+use crate::app::AppState;
+use crate::config::{ClientMethod, Config};
+use crate::paths;
+use crate::rpc;
+use crate::sinks;
+
+fn socket() -> Result<PathBuf, String> {
+    paths::daemon_socket().ok_or_else(|| "Cannot determine the runtime directory; is $XDG_RUNTIME_DIR set?".into())
+}
+
+/// Listens for clients until killed.
+pub fn serve(state: &mut AppState, event_queue: &mut EventQueue<AppState>) -> Result<(), Box<dyn std::error::Error>> {
+    let path = socket()?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(format!("A daemon is already listening on {}", path.display()).into());
+    }
+    // Left behind by a daemon that was killed
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).map_err(|err| format!("{}: {err}", path.display()))?;
+    eprintln!("Listening on {}", path.display());
+    loop {
+        let stream = accept(&listener, state, event_queue)?;
+        if let Err(err) = rpc::serve_stream(state, event_queue, stream) {
+            log::warn!("Lost a client: {err}");
+        }
+    }
+}
+
+/// Waits for the next client, keeping up with the compositor meanwhile,
+/// e.g. as outputs come and go.
+fn accept(
+    listener: &UnixListener,
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+) -> Result<UnixStream, Box<dyn std::error::Error>> {
+    loop {
+        event_queue.dispatch_pending(state)?;
+        event_queue.flush()?;
+        let Some(guard) = event_queue.prepare_read() else { continue };
+        let mut fds = [
+            libc::pollfd { fd: guard.connection_fd().as_raw_fd(), events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: listener.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+        ];
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err.into());
+            }
+            continue;
+        }
+        if fds[0].revents != 0 {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        } else {
+            drop(guard);
+        }
+        if fds[1].revents != 0 {
+            return Ok(listener.accept()?.0);
+        }
+    }
+}
+
+/// Asks the daemon to run `method` and prints its result.
+pub fn request(output: &mut dyn Write, config: &Config, method: ClientMethod) -> Result<(), Box<dyn std::error::Error>> {
+    let path = socket()?;
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|err| format!("No daemon listening on {} ({err}); start one with glimpse daemon", path.display()))?;
+    let params = match method {
+        ClientMethod::Select => json!({ "initial": config.initial_geometry.map(|r| format!("{},{} {}x{}", r.x, r.y, r.width, r.height)) }),
+        ClientMethod::Capture => json!({ "format": config.formats[0].extension() }),
+        ClientMethod::Pick => Value::Null,
+    };
+    writeln!(stream, "{}", json!({ "jsonrpc": "2.0", "id": 1, "method": method.name(), "params": params }))?;
+    // The request stays open for writing: the daemon cancels once it hangs up
+    for line in BufReader::new(&stream).lines() {
+        let message: Value = serde_json::from_str(&line?)?;
+        // Skips progress notifications
+        if message.get("id") != Some(&json!(1)) {
+            continue;
+        }
+        if let Some(error) = message.get("error") {
+            if error["code"].as_i64() == Some(rpc::CANCELLED) {
+                eprintln!("Selection cancelled.");
+                return Ok(());
+            }
+            return Err(error["message"].as_str().unwrap_or("the daemon failed").into());
+        }
+        return print_result(output, config, method, &message["result"]);
+    }
+    Err("The daemon hung up".into())
+}
+
+fn print_result(output: &mut dyn Write, config: &Config, method: ClientMethod, result: &Value) -> Result<(), Box<dyn std::error::Error>> {
+    let number = |key: &str| result[key].as_i64().unwrap_or_default();
+    let (x, y) = (number("x"), number("y"));
+    match method {
+        ClientMethod::Select => writeln!(output, "{x},{y},{}x{}", number("width"), number("height"))?,
+        ClientMethod::Pick => writeln!(output, "{x},{y} {}", result["color"].as_str().unwrap_or_default())?,
+        ClientMethod::Capture => {
+            let data = base64::engine::general_purpose::STANDARD.decode(result["data"].as_str().unwrap_or_default())?;
+            if config.save {
+                let name = Path::new(&sinks::expand_filename(&config.filename)?).with_extension(config.formats[0].extension());
+                let path = sinks::save_to_screenshots(&data, &name)?;
+                writeln!(output, "{}", path.display())?;
+            } else {
+                output.write_all(&data)?;
+            }
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
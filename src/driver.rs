@@ -0,0 +1,98 @@
+//! `--driver`: commands on stdin move and resize the selection while the
+//! overlay is shown, for automated tests and demo recordings.
+//!
+//! One command per line:
+//! - `move DX DY` moves the selection
+//! - `resize DW DH` grows or shrinks it
+//! - `set X,Y WxH` replaces it
+//! - `confirm` and `cancel` end the session as Enter and Esc would
+//!
+//! Moving or resizing without a selection starts one in the middle of the
+//! hovered output, as the arrow keys do. Bad lines are reported on stderr
+//! and skipped.
+
+use std::os::fd::AsRawFd;
+
+use wayland_client::backend::WaylandError;
+use wayland_client::EventQueue;
+
+use crate::app::AppState;
+use crate::rpc::Input;
+use crate::selection::Rect;
+
+/// Runs the session until it is confirmed or cancelled, from stdin or by
+/// the user. The pointer and keyboard keep working alongside.
+pub fn run(state: &mut AppState, event_queue: &mut EventQueue<AppState>) -> Result<(), Box<dyn std::error::Error>> {
+    let qh = event_queue.handle();
+    let mut input = Input::stdin();
+    let mut line_number = 0;
+    loop {
+        event_queue.dispatch_pending(state)?;
+        while state.running {
+            let Some(line) = input.pop_line() else { break };
+            line_number += 1;
+            if let Err(err) = apply(state, &line) {
+                eprintln!("driver: line {line_number}: {err}");
+            }
+        }
+        state.draw_pending(&qh);
+        if !state.running {
+            return Ok(());
+        }
+        event_queue.flush()?;
+        let Some(guard) = event_queue.prepare_read() else { continue };
+        let mut fds = [
+            libc::pollfd { fd: guard.connection_fd().as_raw_fd(), events: libc::POLLIN, revents: 0 },
+            // A negative descriptor is ignored once stdin is closed
+            libc::pollfd { fd: if input.closed { -1 } else { input.file.as_raw_fd() }, events: libc::POLLIN, revents: 0 },
+        ];
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err.into());
+            }
+            continue;
+        }
+        if fds[0].revents != 0 {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        } else {
+            drop(guard);
+        }
+        if fds[1].revents != 0 {
+            input.fill()?;
+        }
+    }
+}
+
+/// Carries out one command.
+fn apply(state: &mut AppState, line: &str) -> Result<(), String> {
+    let (command, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let numbers = || -> Result<(i32, i32), String> {
+        let mut values = args.split_whitespace().map(str::parse::<i32>);
+        match (values.next(), values.next(), values.next()) {
+            (Some(Ok(a)), Some(Ok(b)), None) => Ok((a, b)),
+            _ => Err(format!("{command} takes two whole numbers, got '{args}'")),
+        }
+    };
+    match command {
+        "" => {}
+        "move" | "resize" => {
+            let (dx, dy) = numbers()?;
+            let rect = state.editable_rect().ok_or("a drag is in progress")?;
+            state.set_selection(if command == "move" {
+                rect.translate(dx, dy)
+            } else {
+                Rect::new(rect.x, rect.y, (rect.width + dx).max(1), (rect.height + dy).max(1))
+            });
+        }
+        "set" => state.set_selection(Rect::parse(args).ok_or_else(|| format!("invalid geometry '{args}', expected X,Y WxH"))?),
+        "confirm" => state.finish_selection(),
+        "cancel" => state.cancel(),
+        other => return Err(format!("unknown command '{other}', expected move, resize, set, confirm or cancel")),
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+//! Handing captures to an external image editor before they are saved.
+
+use std::path::Path;
+use std::process::Command;
+
+use image::RgbaImage;
+
+/// Editors tried when `--edit` is given without a command, in order. `%f`
+/// is replaced with the capture file, which each is told to overwrite.
+const EDITORS: &[(&str, &str)] = &[
+    ("satty", "satty --filename %f --output-filename %f"),
+    ("swappy", "swappy -f %f -o %f"),
+    ("gimp", "gimp %f"),
+];
+
+/// First known editor found in `$PATH`.
+fn detect<'a>() -> Option<&'a str> {
+    let path = std::env::var_os("PATH")?;
+    EDITORS.iter()
+        .find(|(binary, _)| std::env::split_paths(&path).any(|dir| dir.join(binary).is_file()))
+        .map(|&(_, command)| command)
+}
+
+/// Writes `image` to a temporary PNG, runs the editor on it and waits for
+/// it to exit, then returns the file's contents as edited.
+pub fn edit(image: &RgbaImage, command: Option<&str>) -> Result<RgbaImage, Box<dyn std::error::Error>> {
+    let command = command.or_else(detect)
+        .ok_or("No editor found; pass one with --edit CMD (satty, swappy or gimp are detected)")?;
+    let file = tempfile::Builder::new().prefix("glimpse-").suffix(".png").tempfile()?;
+    image.save(file.path())?;
+
+    let status = Command::new("sh").arg("-c").arg(expand(command, file.path())).status()?;
+    if !status.success() {
+        return Err(format!("Editor '{command}' exited with {status}").into());
+    }
+    Ok(image::open(file.path())?.to_rgba8())
+}
+
+/// Substitutes the file for `%f`, or appends it when there is no `%f`.
+fn expand(command: &str, file: &Path) -> String {
+    let quoted = format!("'{}'", file.display().to_string().replace('\'', r"'\''"));
+    if command.contains("%f") {
+        command.replace("%f", &quoted)
+    } else {
+        format!("{command} {quoted}")
+    }
+}
@@ -0,0 +1,271 @@
+//! Encoding captures into image files.
+
+use image::{DynamicImage, RgbaImage};
+
+/// An output file format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Png,
+    Webp, // Lossless
+    Jpeg, // Opaque, at JPEG_QUALITY
+}
+
+/// JPEG quality, high enough that text stays legible.
+#[cfg_attr(not(feature = "jpeg"), allow(dead_code))]
+const JPEG_QUALITY: u8 = 90;
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Webp => "webp",
+            Format::Jpeg => "jpg",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Format::Png => "image/png",
+            Format::Webp => "image/webp",
+            Format::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+/// How hard `--optimize` works on PNG output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Optimize {
+    Fast,   // oxipng's filter search, compressed with libdeflate
+    Zopfli, // The same with Zopfli: a few percent smaller, many times slower
+}
+
+/// A capture encoded into one format.
+pub struct Encoded {
+    pub format: Format,
+    pub data: Vec<u8>,
+}
+
+/// Parses a comma-separated format list such as `png,webp`.
+pub fn parse_formats(spec: &str) -> Result<Vec<Format>, String> {
+    let mut formats = Vec::new();
+    for name in spec.split(',') {
+        let format = match name.trim() {
+            "png" => Format::Png,
+            "webp" if cfg!(feature = "webp") => Format::Webp,
+            "webp" => return Err("webp output needs the webp feature".to_string()),
+            "jpeg" | "jpg" if cfg!(feature = "jpeg") => Format::Jpeg,
+            "jpeg" | "jpg" => return Err("jpeg output needs the jpeg feature".to_string()),
+            _ => return Err(format!("unknown format '{name}'")),
+        };
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+    Ok(formats)
+}
+
+/// Encodes `image` into `format`. `metadata` is stored as text chunks in
+/// PNG and as EXIF in JPEG.
+pub fn encode(image: &RgbaImage, format: Format, metadata: &[(&str, String)]) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    match format {
+        Format::Png => Ok(encode_png(image.width(), image.height(), Depth::Eight(image.as_raw()), metadata)?),
+        #[cfg(feature = "webp")]
+        Format::Webp => {
+            let mut data = Vec::new();
+            image::codecs::webp::WebPEncoder::new_lossless(&mut data)
+                .encode(image.as_raw(), image.width(), image.height(), image::ExtendedColorType::Rgba8)?;
+            Ok(data)
+        }
+        #[cfg(not(feature = "webp"))]
+        Format::Webp => Err("webp output needs the webp feature".into()),
+        #[cfg(feature = "jpeg")]
+        Format::Jpeg => {
+            let mut data = Vec::new();
+            let rgb = image::DynamicImage::ImageRgba8(image.clone()).into_rgb8();
+            let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY);
+            image::ImageEncoder::set_exif_metadata(&mut encoder, exif(metadata))?;
+            encoder.encode_image(&rgb)?;
+            Ok(data)
+        }
+        #[cfg(not(feature = "jpeg"))]
+        Format::Jpeg => Err("jpeg output needs the jpeg feature".into()),
+    }
+}
+
+/// Encodes `image` into every format, each on its own thread since
+/// compression dominates the time spent after a capture. A 16-bit image
+/// stays 16-bit in PNG and is reduced to 8 bits for other formats. PNG
+/// output is then optimized if asked.
+pub fn encode_all(
+    image: &DynamicImage,
+    formats: &[Format],
+    metadata: &[(&str, String)],
+    optimize: Option<Optimize>,
+) -> Result<Vec<Encoded>, Box<dyn std::error::Error>> {
+    let reduced = match image {
+        DynamicImage::ImageRgba8(_) => None,
+        _ if formats.iter().all(|&format| format == Format::Png) => None,
+        _ => Some(image.to_rgba8()),
+    };
+    std::thread::scope(|scope| {
+        let reduced = reduced.as_ref();
+        let handles: Vec<_> = formats.iter()
+            .map(|&format| scope.spawn(move || -> Result<_, Box<dyn std::error::Error + Send + Sync>> {
+                let data = match (image, format) {
+                    (DynamicImage::ImageRgba8(image), _) => encode(image, format, metadata)?,
+                    (DynamicImage::ImageRgba16(image), Format::Png) => encode_png(image.width(), image.height(), Depth::Sixteen(image.as_raw()), metadata)?,
+                    _ => encode(reduced.unwrap(), format, metadata)?,
+                };
+                let data = match (format, optimize) {
+                    (Format::Png, Some(optimize)) => optimize_png(&data, optimize)?,
+                    _ => data,
+                };
+                Ok(Encoded { format, data })
+            }))
+            .collect();
+        handles.into_iter()
+            .map(|handle| handle.join().map_err(|_| "encoder thread panicked")?)
+            .collect::<Result<_, _>>()
+            .map_err(|err| err as Box<dyn std::error::Error>)
+    })
+}
+
+/// Shrinks a PNG losslessly, trying filters and compression the plain
+/// encoder doesn't. Text chunks are kept.
+#[cfg(feature = "optimize")]
+fn optimize_png(data: &[u8], optimize: Optimize) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut options = oxipng::Options::from_preset(2);
+    if optimize == Optimize::Zopfli {
+        options.deflater = oxipng::Deflater::Zopfli(oxipng::ZopfliOptions::default());
+    }
+    Ok(oxipng::optimize_from_memory(data, &options)?)
+}
+
+/// Stand-in when built without oxipng; `--optimize` is refused earlier.
+#[cfg(not(feature = "optimize"))]
+fn optimize_png(_data: &[u8], _optimize: Optimize) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    Err("--optimize needs Glimpse built with the optimize feature".into())
+}
+
+/// A value in an EXIF directory.
+#[cfg(feature = "jpeg")]
+enum Exif {
+    Ascii(String),
+    Long(u32),
+    Undefined([u8; 4]),
+}
+
+// EXIF tags
+#[cfg(feature = "jpeg")]
+const IMAGE_DESCRIPTION: u16 = 0x010E;
+#[cfg(feature = "jpeg")]
+const SOFTWARE: u16 = 0x0131;
+#[cfg(feature = "jpeg")]
+const DATE_TIME: u16 = 0x0132;
+#[cfg(feature = "jpeg")]
+const EXIF_IFD: u16 = 0x8769;
+#[cfg(feature = "jpeg")]
+const EXIF_VERSION: u16 = 0x9000;
+#[cfg(feature = "jpeg")]
+const OFFSET_TIME: u16 = 0x9010;
+
+/// `metadata` as the TIFF structure of an EXIF segment. The creation time
+/// becomes DateTime with its UTC offset, and the fields EXIF has no tag for,
+/// such as the geometry, output and compositor, go into ImageDescription as
+/// `Key: value` pairs.
+#[cfg(feature = "jpeg")]
+fn exif(metadata: &[(&str, String)]) -> Vec<u8> {
+    let mut description = Vec::new();
+    let mut software = None;
+    let mut time = None;
+    for (key, value) in metadata {
+        match (*key, chrono::DateTime::parse_from_rfc3339(value)) {
+            ("Software", _) => software = Some(value.clone()),
+            ("Creation Time", Ok(parsed)) => time = Some(parsed),
+            _ => description.push(format!("{key}: {value}")),
+        }
+    }
+    let mut main = Vec::new();
+    if !description.is_empty() {
+        main.push((IMAGE_DESCRIPTION, Exif::Ascii(description.join("; "))));
+    }
+    main.extend(software.map(|software| (SOFTWARE, Exif::Ascii(software))));
+    let mut sub = vec![(EXIF_VERSION, Exif::Undefined(*b"0232"))];
+    if let Some(time) = time {
+        main.push((DATE_TIME, Exif::Ascii(time.format("%Y:%m:%d %H:%M:%S").to_string())));
+        sub.push((OFFSET_TIME, Exif::Ascii(time.format("%:z").to_string())));
+    }
+    // Little-endian, with the first directory right after the header. The
+    // sub-directory's offset doesn't change the first one's size.
+    let mut tiff = b"II*\0\x08\0\0\0".to_vec();
+    main.push((EXIF_IFD, Exif::Long(0)));
+    let sub_offset = tiff.len() + directory(&main, tiff.len()).len();
+    main.last_mut().unwrap().1 = Exif::Long(sub_offset as u32);
+    tiff.extend(directory(&main, tiff.len()));
+    tiff.extend(directory(&sub, tiff.len()));
+    tiff
+}
+
+/// One EXIF directory of `entries`, given in tag order, followed by the values
+/// that don't fit in an entry, for placing at `offset` in the TIFF data.
+#[cfg(feature = "jpeg")]
+fn directory(entries: &[(u16, Exif)], offset: usize) -> Vec<u8> {
+    let values_offset = offset + 2 + 12 * entries.len() + 4;
+    let mut data = (entries.len() as u16).to_le_bytes().to_vec();
+    let mut values = Vec::new();
+    for (tag, value) in entries {
+        data.extend(tag.to_le_bytes());
+        let (kind, count, mut bytes) = match value {
+            Exif::Ascii(text) => (2u16, text.len() + 1, [text.as_bytes(), b"\0"].concat()),
+            Exif::Long(number) => (4, 1, number.to_le_bytes().to_vec()),
+            Exif::Undefined(bytes) => (7, 4, bytes.to_vec()),
+        };
+        data.extend(kind.to_le_bytes());
+        data.extend((count as u32).to_le_bytes());
+        if bytes.len() <= 4 {
+            bytes.resize(4, 0);
+            data.extend(bytes);
+        } else {
+            data.extend(((values_offset + values.len()) as u32).to_le_bytes());
+            values.extend(bytes);
+            // Values start on word boundaries
+            values.resize(values.len().next_multiple_of(2), 0);
+        }
+    }
+    data.extend(0u32.to_le_bytes()); // No next directory
+    data.extend(values);
+    data
+}
+
+/// RGBA samples at either depth PNG output supports.
+enum Depth<'a> {
+    Eight(&'a [u8]),
+    Sixteen(&'a [u16]),
+}
+
+/// Encodes RGBA samples as PNG with `metadata` as text chunks.
+fn encode_png(width: u32, height: u32, samples: Depth, metadata: &[(&str, String)]) -> Result<Vec<u8>, png::EncodingError> {
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(match samples {
+        Depth::Eight(_) => png::BitDepth::Eight,
+        Depth::Sixteen(_) => png::BitDepth::Sixteen,
+    });
+    for (keyword, text) in metadata {
+        // tEXt is Latin-1 only, iTXt carries UTF-8 such as output descriptions
+        if text.is_ascii() {
+            encoder.add_text_chunk(keyword.to_string(), text.clone())?;
+        } else {
+            encoder.add_itxt_chunk(keyword.to_string(), text.clone())?;
+        }
+    }
+    let mut writer = encoder.write_header()?;
+    match samples {
+        Depth::Eight(samples) => writer.write_image_data(samples)?,
+        // PNG stores 16-bit samples big-endian
+        Depth::Sixteen(samples) => writer.write_image_data(&samples.iter().flat_map(|sample| sample.to_be_bytes()).collect::<Vec<_>>())?,
+    }
+    writer.finish()?;
+    Ok(data)
+}
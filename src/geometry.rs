@@ -0,0 +1,130 @@
+//! Printing the selected geometry when nothing is captured.
+
+use crate::config::GeometryFormat;
+use crate::output::{self, Output};
+use crate::selection::Rect;
+
+/// A real-world length unit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Units {
+    Mm,
+    In,
+}
+
+impl Units {
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Units::Mm => "mm",
+            Units::In => "in",
+        }
+    }
+
+    fn per_mm(self) -> f64 {
+        match self {
+            Units::Mm => 1.0,
+            Units::In => 1.0 / 25.4,
+        }
+    }
+}
+
+pub fn parse_units(name: &str) -> Result<Units, String> {
+    match name {
+        "mm" => Ok(Units::Mm),
+        "in" => Ok(Units::In),
+        _ => Err(format!("unknown units '{name}', expected mm or in")),
+    }
+}
+
+/// Formats `rect` for printing. Physical sizes use the output covering most
+/// of the selection, and fail if it doesn't report its dimensions.
+pub fn format(format: &GeometryFormat, rect: Rect, outputs: &[Output], units: Option<Units>) -> Result<String, String> {
+    let output = output::containing(outputs, &rect);
+    let physical = |units: Units| -> Result<(f64, f64), String> {
+        let mm = output.and_then(Output::mm_per_logical_px)
+            .ok_or("the output doesn't report its physical size")?;
+        let factor = mm * units.per_mm();
+        Ok((rect.width as f64 * factor, rect.height as f64 * factor))
+    };
+    let Rect { x, y, width, height } = rect;
+    Ok(match format {
+        GeometryFormat::Plain => {
+            let mut line = format!("{x},{y},{width}x{height}");
+            if let Some(units) = units {
+                let (w, h) = physical(units)?;
+                line += &format!(" {w:.1}x{h:.1}{}", units.suffix());
+            }
+            line
+        }
+        GeometryFormat::Env => {
+            let name = output.and_then(|o| o.name.as_deref()).unwrap_or("");
+            let mut line = format!(
+                "GLIMPSE_X={x} GLIMPSE_Y={y} GLIMPSE_W={width} GLIMPSE_H={height} GLIMPSE_OUTPUT={}",
+                shell_quote(name),
+            );
+            if let Some(units) = units {
+                let (w, h) = physical(units)?;
+                let suffix = units.suffix().to_ascii_uppercase();
+                line += &format!(" GLIMPSE_W_{suffix}={w:.1} GLIMPSE_H_{suffix}={h:.1}");
+            }
+            line
+        }
+        GeometryFormat::Template(template) => {
+            let mut line = String::new();
+            let mut rest = template.as_str();
+            while let Some(i) = rest.find('%') {
+                line += &rest[..i];
+                rest = &rest[i + 1..];
+                let mut chars = rest.chars();
+                let Some(c) = chars.next() else {
+                    line.push('%');
+                    break;
+                };
+                rest = chars.as_str();
+                match c {
+                    'x' => line += &x.to_string(),
+                    'y' => line += &y.to_string(),
+                    'w' | 'h' => {
+                        // %wmm, %hin etc. give the size in that unit
+                        let units = [Units::Mm, Units::In].into_iter().find(|u| rest.starts_with(u.suffix()));
+                        match units {
+                            Some(units) => {
+                                rest = &rest[units.suffix().len()..];
+                                let (w, h) = physical(units)?;
+                                line += &format!("{:.1}", if c == 'w' { w } else { h });
+                            }
+                            None => line += &(if c == 'w' { width } else { height }).to_string(),
+                        }
+                    }
+                    'o' => line += output.and_then(|o| o.name.as_deref()).unwrap_or(""),
+                    '%' => line.push('%'),
+                    other => return Err(format!("unknown placeholder '%{other}' in format")),
+                }
+            }
+            line + rest
+        }
+    })
+}
+
+/// Checks the placeholders of a `--format` template, so a typo fails
+/// before anything is selected rather than after.
+pub fn check_template(template: &str) -> Result<(), String> {
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            match chars.next() {
+                None | Some('x' | 'y' | 'w' | 'h' | 'o' | '%') => {}
+                Some(other) => return Err(format!("unknown placeholder '%{other}' in format")),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Quotes `value` for sh, leaving plain words such as `DP-1` as they are.
+fn shell_quote(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:/".contains(c)) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
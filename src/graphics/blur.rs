@@ -0,0 +1,65 @@
+//! Stack blur: a close approximation of a gaussian blur whose cost does not
+//! depend on the radius.
+
+/// Blurs a BGRA image in place, first along rows, then along columns.
+pub fn stack_blur(pixels: &mut [u8], width: u32, height: u32, radius: u32) {
+    let (width, height) = (width as usize, height as usize);
+    if radius == 0 || width == 0 || height == 0 {
+        return;
+    }
+    let mut line = Vec::with_capacity(width.max(height));
+    for y in 0..height {
+        blur_line(pixels, y * width, 1, width, radius as usize, &mut line);
+    }
+    for x in 0..width {
+        blur_line(pixels, x, width, height, radius as usize, &mut line);
+    }
+}
+
+/// Blurs the `len` pixels starting at pixel `start`, `step` pixels apart.
+///
+/// Each output is a weighted sum with weights rising from 1 at `radius`
+/// pixels away to `radius + 1` at the center. Moving one pixel along, the
+/// pixels on the outgoing half all lose one weight and those on the
+/// incoming half gain one, so the sum is updated with two running totals.
+fn blur_line(pixels: &mut [u8], start: usize, step: usize, len: usize, radius: usize, line: &mut Vec<[u32; 4]>) {
+    line.clear();
+    line.extend((0..len).map(|i| {
+        let offset = (start + i * step) * 4;
+        let p = &pixels[offset..offset + 4];
+        [p[0] as u32, p[1] as u32, p[2] as u32, p[3] as u32]
+    }));
+    // Pixels past the ends repeat the edge
+    let at = |i: isize| line[i.clamp(0, len as isize - 1) as usize];
+    let r = radius as isize;
+    let divisor = ((radius + 1) * (radius + 1)) as u32;
+
+    let (mut sum, mut sum_out, mut sum_in) = ([0u32; 4], [0u32; 4], [0u32; 4]);
+    for i in -r..=r {
+        let p = at(i);
+        let weight = (r + 1 - i.abs()) as u32;
+        for c in 0..4 {
+            sum[c] += p[c] * weight;
+            if i <= 0 {
+                sum_out[c] += p[c];
+            } else {
+                sum_in[c] += p[c];
+            }
+        }
+    }
+    for x in 0..len as isize {
+        let offset = (start + x as usize * step) * 4;
+        for c in 0..4 {
+            pixels[offset + c] = (sum[c] / divisor) as u8;
+        }
+        let (leaving, entering, center) = (at(x - r), at(x + r + 1), at(x + 1));
+        for c in 0..4 {
+            sum[c] -= sum_out[c];
+            sum_out[c] -= leaving[c];
+            sum_in[c] += entering[c];
+            sum[c] += sum_in[c];
+            sum_out[c] += center[c];
+            sum_in[c] -= center[c];
+        }
+    }
+}
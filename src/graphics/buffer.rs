@@ -0,0 +1,219 @@
+//! Shared memory buffers: one-off buffers from pixel data and the buffers
+//! backing each overlay.
+
+use std::io::Write;
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use wayland_client::{Proxy, QueueHandle};
+use wayland_client::protocol::{wl_buffer, wl_shm, wl_shm_pool};
+
+use crate::app::{AppState, Overlay};
+use crate::graphics::blur::stack_blur;
+
+/// Creates a wl_buffer from raw pixel data.
+pub fn create_buffer_from_data(
+    shm: &wl_shm::WlShm,
+    qh: &QueueHandle<AppState>,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> Result<wl_buffer::WlBuffer, Box<dyn std::error::Error>> {
+    let stride = width * 4;
+    let size = (stride * height) as i32;
+
+    let mut file = tempfile::tempfile()?;
+    file.write_all(data)?;
+    file.flush()?;
+
+    let fd = unsafe { BorrowedFd::borrow_raw(file.as_raw_fd()) };
+
+    let pool = shm.create_pool(fd, size, qh, ());
+    let buffer = pool.create_buffer(0, width as i32, height as i32, stride as i32, wl_shm::Format::Argb8888, qh, ());
+    pool.destroy();
+
+    Ok(buffer)
+}
+
+/// Largest overlay side accepted from the compositor, well past 8K.
+pub const MAX_OVERLAY_SIDE: u32 = 16384;
+
+/// Bytes in one buffer for an overlay of `width` by `height`, or `None` for
+/// an empty or absurd size.
+pub fn overlay_buffer_size(width: u32, height: u32) -> Option<usize> {
+    let sane = |side| (1..=MAX_OVERLAY_SIDE).contains(&side);
+    (sane(width) && sane(height)).then(|| width as usize * height as usize * 4)
+}
+
+/// Buffers an overlay may have at once: two to flip between, and a third
+/// while the compositor holds on to both.
+pub const MAX_BUFFERS: usize = 3;
+
+/// Whether the compositor still reads from a buffer created with an
+/// `AtomicBool` as its user data; cleared by its release event.
+pub fn is_busy(buffer: &wl_buffer::WlBuffer) -> bool {
+    buffer.data::<AtomicBool>().is_some_and(|busy| busy.load(Ordering::Relaxed))
+}
+
+/// Marks a buffer as held by the compositor, once committed.
+pub fn set_busy(buffer: &wl_buffer::WlBuffer) {
+    if let Some(busy) = buffer.data::<AtomicBool>() {
+        busy.store(true, Ordering::Relaxed);
+    }
+}
+
+/// One of the full-size buffers an overlay is presented from. Its pool is
+/// grown on resize, never recreated.
+pub struct ShmBuffer {
+    pub buffer: wl_buffer::WlBuffer,
+    file: std::fs::File,
+    pool: wl_shm_pool::WlShmPool,
+    capacity: usize,
+    pub mmap: memmap2::MmapMut,
+    pub stale: Option<(u32, u32, u32, u32)>, // Region changed since it was drawn, as (min_x, min_y, max_x, max_y)
+}
+
+impl ShmBuffer {
+    fn new(shm: &wl_shm::WlShm, width: u32, height: u32, qh: &QueueHandle<AppState>) -> std::io::Result<Self> {
+        let size = (width * height * 4) as usize;
+        let file = tempfile::tempfile()?;
+        file.set_len(size as u64)?;
+        let mmap = unsafe { memmap2::MmapMut::map_mut(&file)? };
+        let fd = unsafe { BorrowedFd::borrow_raw(file.as_raw_fd()) };
+        let pool = shm.create_pool(fd, size as i32, qh, ());
+        let buffer = create_overlay_buffer(&pool, width, height, qh);
+        Ok(Self { buffer, file, pool, capacity: size, mmap, stale: Some((0, 0, width, height)) })
+    }
+
+    /// Replaces the buffer with one of the new size, growing the pool and
+    /// remapping it if needed.
+    fn resize(&mut self, width: u32, height: u32, qh: &QueueHandle<AppState>) -> std::io::Result<()> {
+        let size = (width * height * 4) as usize;
+        if size > self.capacity {
+            self.file.set_len(size as u64)?;
+            self.mmap = unsafe { memmap2::MmapMut::map_mut(&self.file)? };
+            self.pool.resize(size as i32);
+            self.capacity = size;
+        }
+        self.buffer.destroy();
+        self.buffer = create_overlay_buffer(&self.pool, width, height, qh);
+        self.stale = Some((0, 0, width, height));
+        Ok(())
+    }
+
+    pub fn destroy(self) {
+        self.buffer.destroy();
+        self.pool.destroy();
+    }
+}
+
+/// A buffer of `width` by `height` pixels at the start of `pool`.
+fn create_overlay_buffer(pool: &wl_shm_pool::WlShmPool, width: u32, height: u32, qh: &QueueHandle<AppState>) -> wl_buffer::WlBuffer {
+    let format = wl_shm::Format::Argb8888;
+    pool.create_buffer(0, width as i32, height as i32, (width * 4) as i32, format, qh, AtomicBool::new(false))
+}
+
+/// (Re)allocates the background cache and shm buffers of an overlay for its
+/// current size. Buffers the compositor is done with keep their pools.
+///
+/// Failing to allocate the second buffer, e.g. with a full tmpfs, leaves
+/// the overlay single-buffered; without any, it is not drawn.
+pub fn allocate_overlay_buffers(overlay: &mut Overlay, qh: &QueueHandle<AppState>) {
+    let (width, height) = (overlay.width, overlay.height);
+
+    overlay.frozen = None;
+    overlay.prev_scene_rect = None;
+
+    if width == 0 || height == 0 || overlay.dim_layer.is_some() {
+        for buffer in overlay.buffers.drain(..) {
+            buffer.destroy();
+        }
+        overlay.background_cache = None;
+        return;
+    }
+
+    // The compositor may still read from a held buffer, so it is replaced
+    // rather than drawn over
+    let (mut buffers, held): (Vec<_>, Vec<_>) = overlay.buffers.drain(..).partition(|b| !is_busy(&b.buffer));
+    let extra = buffers.split_off(buffers.len().min(2));
+    for buffer in held.into_iter().chain(extra) {
+        buffer.destroy();
+    }
+    let mut resized = Vec::new();
+    let mut error = None;
+    for mut buffer in buffers {
+        match buffer.resize(width, height, qh) {
+            Ok(()) => resized.push(buffer),
+            Err(err) => {
+                buffer.destroy();
+                error = Some(err);
+            }
+        }
+    }
+    while resized.len() < 2 && error.is_none() {
+        match ShmBuffer::new(&overlay.shm, width, height, qh) {
+            Ok(buffer) => resized.push(buffer),
+            Err(err) => error = Some(err),
+        }
+    }
+    match (resized.len(), error) {
+        (0, Some(err)) => log::error!("Failed to allocate a {width}x{height} overlay buffer, not drawing it: {err}"),
+        (_, Some(err)) => log::warn!("Failed to allocate a second overlay buffer, drawing with one: {err}"),
+        _ => {}
+    }
+    overlay.buffers = resized;
+
+    // Generate background cache
+    let buffer_size = (width * height * 4) as usize;
+    let mut bg = vec![0; buffer_size];
+    let semi_transparent_black = [0x00, 0x00, 0x00, overlay.dim_alpha];
+    for chunk in bg.chunks_exact_mut(4) {
+        chunk.copy_from_slice(&semi_transparent_black);
+    }
+    overlay.background_cache = Some(bg);
+    // The buffers need the full background once
+    overlay.full_redraw = true;
+}
+
+/// Index of a buffer the compositor is done with, allocating another if
+/// all are held and there is room for one. `None` means waiting for a
+/// release.
+pub fn free_buffer(overlay: &mut Overlay, qh: &QueueHandle<AppState>) -> Option<usize> {
+    if let Some(index) = overlay.buffers.iter().position(|b| !is_busy(&b.buffer)) {
+        return Some(index);
+    }
+    if overlay.buffers.len() >= MAX_BUFFERS {
+        return None;
+    }
+    log::debug!("All {} overlay buffers are busy, adding another", overlay.buffers.len());
+    match ShmBuffer::new(&overlay.shm, overlay.width, overlay.height, qh) {
+        Ok(buffer) => {
+            overlay.buffers.push(buffer);
+            Some(overlay.buffers.len() - 1)
+        }
+        Err(err) => {
+            log::debug!("Failed to allocate another overlay buffer, waiting for a release: {err}");
+            None
+        }
+    }
+}
+
+/// Shows the frozen screen on an overlay: as it was inside the selection,
+/// and dimmed, or blurred with `blur` as the radius, around it.
+pub fn set_frozen(overlay: &mut Overlay, frozen: Vec<u8>, blur: Option<u32>) {
+    let mut background = frozen.clone();
+    match blur {
+        Some(radius) => stack_blur(&mut background, overlay.width, overlay.height, radius),
+        None => {
+            let keep = 255 - overlay.dim_alpha as u32;
+            for pixel in background.chunks_exact_mut(4) {
+                for value in &mut pixel[..3] {
+                    *value = (*value as u32 * keep / 255) as u8;
+                }
+            }
+        }
+    }
+    overlay.background_cache = Some(background);
+    overlay.frozen = Some(frozen);
+    overlay.full_redraw = true;
+}
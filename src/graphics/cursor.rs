@@ -0,0 +1,131 @@
+//! Custom crosshair cursor.
+//!
+//! The crosshair is rendered at the integer scale of the output the pointer
+//! is on, so it keeps the same logical size and a centered hotspot on HiDPI
+//! outputs instead of being upscaled by the compositor. With wl_surface v6
+//! the compositor names the scale and transform to render for.
+//!
+//! The crosshair is embedded as raw BGRA, converted by the build script, and
+//! only set up once the pointer first enters an overlay. Its logical size
+//! comes from `--cursor-size` or `XCURSOR_SIZE`, and is otherwise the
+//! image's own.
+
+use image::RgbaImage;
+use image::imageops::{self, FilterType};
+use wayland_client::QueueHandle;
+use wayland_client::protocol::{wl_buffer, wl_compositor, wl_shm, wl_surface};
+use wayland_client::protocol::wl_output::Transform;
+
+use crate::app::AppState;
+use crate::graphics::buffer::create_buffer_from_data;
+
+/// The crosshair's pixels in BGRA order, and its size.
+const CROSSHAIR: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/crosshair.bgra"));
+const CROSSHAIR_SIZE: (u32, u32) = include!(concat!(env!("OUT_DIR"), "/crosshair_size.rs"));
+
+/// Range of logical cursor sizes accepted.
+pub const CURSOR_SIZES: std::ops::RangeInclusive<u32> = 8..=512;
+
+/// The cursor size the desktop asks for in `XCURSOR_SIZE`, if it is valid.
+pub fn theme_size() -> Option<u32> {
+    std::env::var("XCURSOR_SIZE").ok()?.trim().parse().ok().filter(|size| CURSOR_SIZES.contains(size))
+}
+
+pub struct Cursor {
+    pub surface: wl_surface::WlSurface,
+    image: RgbaImage, // BGRA, which resizing and turning don't mind
+    size: (u32, u32), // Logical size the image is shown at
+    buffers: Vec<(i32, Transform, wl_buffer::WlBuffer)>, // Rendered buffers per scale and transform
+    scale: i32,                                          // Scale currently attached
+    transform: Transform,                                // Buffer transform the compositor prefers
+}
+
+impl Cursor {
+    /// Creates the crosshair's surface, `size` logical pixels across its
+    /// longer side if given. Buffers are created per scale on demand by
+    /// [`Cursor::set_scale`].
+    pub fn new(compositor: &wl_compositor::WlCompositor, size: Option<u32>, qh: &QueueHandle<AppState>) -> Self {
+        let (width, height) = CROSSHAIR_SIZE;
+        let size = match size {
+            Some(size) => {
+                let longer = width.max(height);
+                ((width * size / longer).max(1), (height * size / longer).max(1))
+            }
+            None => (width, height),
+        };
+        Self {
+            surface: compositor.create_surface(qh, ()),
+            image: RgbaImage::from_raw(width, height, CROSSHAIR.to_vec()).expect("Crosshair size doesn't match its pixels"),
+            size,
+            buffers: Vec::new(),
+            scale: 0,
+            transform: Transform::Normal,
+        }
+    }
+
+    /// Hotspot in surface-local (logical) coordinates.
+    pub fn hotspot(&self) -> (i32, i32) {
+        let (width, height) = self.size;
+        ((width / 2) as i32, (height / 2) as i32)
+    }
+
+    /// Attaches a buffer rendered for `scale`, creating it if needed.
+    pub fn set_scale(&mut self, scale: i32, shm: &wl_shm::WlShm, qh: &QueueHandle<AppState>) -> Result<(), Box<dyn std::error::Error>> {
+        let scale = scale.max(1);
+        if scale == self.scale {
+            return Ok(());
+        }
+        self.attach(scale, self.transform, shm, qh)
+    }
+
+    /// Pre-transforms the crosshair the way the output it is on is
+    /// transformed, from wl_surface v6, so the compositor can show it as is.
+    pub fn set_transform(&mut self, transform: Transform, shm: &wl_shm::WlShm, qh: &QueueHandle<AppState>) -> Result<(), Box<dyn std::error::Error>> {
+        if transform == self.transform {
+            return Ok(());
+        }
+        self.transform = transform;
+        if self.scale == 0 {
+            return Ok(()); // Nothing attached yet; set_scale will pick it up
+        }
+        self.attach(self.scale, transform, shm, qh)
+    }
+
+    fn attach(&mut self, scale: i32, transform: Transform, shm: &wl_shm::WlShm, qh: &QueueHandle<AppState>) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.buffers.iter().any(|&(s, t, _)| (s, t) == (scale, transform)) {
+            let (width, height) = (self.size.0 * scale as u32, self.size.1 * scale as u32);
+            // Always resampled from the original, so sizes don't compound
+            let scaled = if (width, height) == self.image.dimensions() {
+                self.image.clone()
+            } else {
+                imageops::resize(&self.image, width, height, FilterType::CatmullRom)
+            };
+            let transformed = transformed(scaled, transform);
+            let (width, height) = transformed.dimensions();
+            let buffer = create_buffer_from_data(shm, qh, width, height, transformed.as_raw())?;
+            self.buffers.push((scale, transform, buffer));
+        }
+
+        let (.., buffer) = self.buffers.iter().find(|&&(s, t, _)| (s, t) == (scale, transform)).unwrap();
+        self.surface.set_buffer_scale(scale);
+        self.surface.set_buffer_transform(transform);
+        self.surface.attach(Some(buffer), 0, 0);
+        self.surface.damage_buffer(0, 0, i32::MAX, i32::MAX);
+        self.surface.commit();
+        self.scale = scale;
+        Ok(())
+    }
+}
+
+/// The buffer contents showing `image` under `transform`: flipped around
+/// the vertical axis for the flipped variants, then turned counter-clockwise.
+fn transformed(image: RgbaImage, transform: Transform) -> RgbaImage {
+    let flipped = matches!(transform, Transform::Flipped | Transform::Flipped90 | Transform::Flipped180 | Transform::Flipped270);
+    let image = if flipped { imageops::flip_horizontal(&image) } else { image };
+    match transform {
+        Transform::_90 | Transform::Flipped90 => imageops::rotate270(&image),
+        Transform::_180 | Transform::Flipped180 => imageops::rotate180(&image),
+        Transform::_270 | Transform::Flipped270 => imageops::rotate90(&image),
+        _ => image,
+    }
+}
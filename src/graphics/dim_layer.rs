@@ -0,0 +1,272 @@
+//! The dim layer built from single-pixel buffers, when the compositor has
+//! wp_single_pixel_buffer_manager_v1 and wp_viewporter.
+//!
+//! The overlay surface shows one clear pixel stretched over the output. Four
+//! subsurfaces stretch a dim pixel over everything around the band the
+//! scene covers, and only that band is drawn into shared memory, on a fifth
+//! subsurface. A full-screen buffer is never allocated.
+
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+use std::sync::atomic::AtomicBool;
+
+use wayland_client::QueueHandle;
+use wayland_client::protocol::{wl_buffer, wl_compositor, wl_shm, wl_shm_pool, wl_subcompositor, wl_subsurface, wl_surface};
+use wayland_protocols::wp::single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1;
+use wayland_protocols::wp::viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter};
+
+use crate::app::AppState;
+use crate::graphics::buffer::{is_busy, set_busy, MAX_BUFFERS};
+use crate::graphics::drawing::{for_rows, paint_scene, Scene};
+use crate::selection::Rect;
+
+/// A subsurface of the overlay that takes no input.
+struct Child {
+    surface: wl_surface::WlSurface,
+    subsurface: wl_subsurface::WlSubsurface,
+    viewport: Option<WpViewport>, // Stretches a single pixel
+}
+
+impl Child {
+    fn new(
+        parent: &wl_surface::WlSurface,
+        compositor: &wl_compositor::WlCompositor,
+        subcompositor: &wl_subcompositor::WlSubcompositor,
+        viewporter: Option<&WpViewporter>,
+        qh: &QueueHandle<AppState>,
+    ) -> Self {
+        let surface = compositor.create_surface(qh, ());
+        let subsurface = subcompositor.get_subsurface(&surface, parent, qh, ());
+        // Pointer events go to the overlay surface underneath
+        let region = compositor.create_region(qh, ());
+        surface.set_input_region(Some(&region));
+        region.destroy();
+        let viewport = viewporter.map(|viewporter| viewporter.get_viewport(&surface, qh, ()));
+        Self { surface, subsurface, viewport }
+    }
+
+    /// Stretches `pixel` over `rect`, in the parent's coordinates, or unmaps
+    /// the child if either is missing.
+    fn stretch(&self, pixel: Option<&wl_buffer::WlBuffer>, rect: Option<Rect>) {
+        match (pixel, rect, &self.viewport) {
+            (Some(pixel), Some(rect), Some(viewport)) => {
+                self.subsurface.set_position(rect.x, rect.y);
+                viewport.set_destination(rect.width, rect.height);
+                self.surface.attach(Some(pixel), 0, 0);
+                self.surface.damage_buffer(0, 0, 1, 1);
+            }
+            _ => self.surface.attach(None, 0, 0),
+        }
+        self.surface.commit();
+    }
+
+    fn destroy(self) {
+        if let Some(viewport) = self.viewport {
+            viewport.destroy();
+        }
+        self.subsurface.destroy();
+        self.surface.destroy();
+    }
+}
+
+/// Shared memory for the band, grown as needed but never shrunk.
+struct Slot {
+    file: std::fs::File,
+    pool: wl_shm_pool::WlShmPool,
+    mmap: memmap2::MmapMut,
+    capacity: usize,
+    buffer: Option<(wl_buffer::WlBuffer, i32, i32)>, // With its width and height
+}
+
+impl Slot {
+    fn new(shm: &wl_shm::WlShm, size: usize, qh: &QueueHandle<AppState>) -> std::io::Result<Self> {
+        let file = tempfile::tempfile()?;
+        file.set_len(size as u64)?;
+        let mmap = unsafe { memmap2::MmapMut::map_mut(&file)? };
+        let fd = unsafe { BorrowedFd::borrow_raw(file.as_raw_fd()) };
+        let pool = shm.create_pool(fd, size as i32, qh, ());
+        Ok(Self { file, pool, mmap, capacity: size, buffer: None })
+    }
+
+    /// Whether the compositor still reads from the slot's buffer.
+    fn busy(&self) -> bool {
+        self.buffer.as_ref().is_some_and(|(buffer, ..)| is_busy(buffer))
+    }
+
+    /// Grows the pool to hold at least `size` bytes.
+    fn reserve(&mut self, size: usize) -> std::io::Result<()> {
+        if size > self.capacity {
+            self.file.set_len(size as u64)?;
+            self.mmap = unsafe { memmap2::MmapMut::map_mut(&self.file)? };
+            self.pool.resize(size as i32);
+            self.capacity = size;
+        }
+        Ok(())
+    }
+
+    /// A buffer of `width` by `height` pixels at the start of the pool,
+    /// reusing the last one if it has that size.
+    fn buffer(&mut self, width: i32, height: i32, qh: &QueueHandle<AppState>) -> &wl_buffer::WlBuffer {
+        if self.buffer.as_ref().is_some_and(|&(_, w, h)| (w, h) != (width, height)) {
+            if let Some((buffer, ..)) = self.buffer.take() {
+                buffer.destroy();
+            }
+        }
+        let (buffer, ..) = self.buffer.get_or_insert_with(|| {
+            let format = wl_shm::Format::Argb8888;
+            let buffer = self.pool.create_buffer(0, width, height, width * 4, format, qh, AtomicBool::new(false));
+            (buffer, width, height)
+        });
+        buffer
+    }
+
+    fn destroy(self) {
+        if let Some((buffer, ..)) = self.buffer {
+            buffer.destroy();
+        }
+        self.pool.destroy();
+    }
+}
+
+pub struct DimLayer {
+    manager: WpSinglePixelBufferManagerV1,
+    shm: wl_shm::WlShm,
+    viewport: WpViewport,         // Of the overlay surface, stretching `clear`
+    clear: wl_buffer::WlBuffer,
+    dim: Option<(wl_buffer::WlBuffer, u8)>, // With its alpha; None while fully clear
+    size: (u32, u32),             // Of the overlay when `clear` was stretched
+    strips: [Child; 4],           // Above, below, left and right of the band
+    band: Child,
+    slots: Vec<Slot>, // Two, or three while the compositor holds both
+}
+
+impl DimLayer {
+    /// Sets up the layer on an overlay surface, or returns `None` if a
+    /// needed global is missing.
+    pub fn new(surface: &wl_surface::WlSurface, state: &AppState, qh: &QueueHandle<AppState>) -> Option<Self> {
+        let (Some(compositor), Some(subcompositor), Some(viewporter), Some(manager), Some(shm)) = (
+            &state.compositor,
+            &state.subcompositor,
+            &state.viewporter,
+            &state.single_pixel_buffer_manager,
+            &state.shm,
+        ) else {
+            return None;
+        };
+        let child = |viewporter| Child::new(surface, compositor, subcompositor, viewporter, qh);
+        Some(Self {
+            clear: manager.create_u32_rgba_buffer(0, 0, 0, 0, qh, ()),
+            manager: manager.clone(),
+            shm: shm.clone(),
+            viewport: viewporter.get_viewport(surface, qh, ()),
+            dim: None,
+            size: (0, 0),
+            strips: [(); 4].map(|_| child(Some(viewporter))),
+            band: child(None),
+            slots: Vec::new(),
+        })
+    }
+
+    /// Shows `scene` on an overlay of `width` by `height` over a background
+    /// dimmed with `alpha`. The caller commits the overlay surface, which
+    /// applies the subsurfaces' state along with it. Returns `false`, having
+    /// changed nothing, while the compositor holds every band buffer or
+    /// memory for the band can't be had.
+    pub fn draw(&mut self, surface: &wl_surface::WlSurface, width: u32, height: u32, alpha: u8, scene: &Scene, qh: &QueueHandle<AppState>) -> bool {
+        let free = self.slots.iter().position(|slot| !slot.busy());
+        if free.is_none() && self.slots.len() >= MAX_BUFFERS {
+            return false;
+        }
+        let screen = Rect::new(0, 0, width as i32, height as i32);
+        let band = scene.bounds().and_then(|r| r.intersection(&screen));
+        let slot = match band.map(|band| self.slot(free, (band.width * band.height * 4) as usize, qh)) {
+            Some(Ok(index)) => Some(index),
+            Some(Err(err)) => {
+                log::warn!("Failed to allocate memory for the selection, waiting to retry: {err}");
+                return false;
+            }
+            None => None,
+        };
+
+        if self.size != (width, height) {
+            self.size = (width, height);
+            self.viewport.set_destination(width as i32, height as i32);
+            surface.attach(Some(&self.clear), 0, 0);
+            surface.damage_buffer(0, 0, 1, 1);
+        }
+
+        if self.dim.as_ref().map(|&(_, a)| a) != Some(alpha) {
+            if let Some((old, _)) = self.dim.take() {
+                old.destroy();
+            }
+            // Premultiplied black, with each channel spanning the whole u32
+            let dim = (alpha > 0).then(|| self.manager.create_u32_rgba_buffer(0, 0, 0, alpha as u32 * 0x0101_0101, qh, ()));
+            self.dim = dim.map(|buffer| (buffer, alpha));
+        }
+        let dim = self.dim.as_ref().map(|(buffer, _)| buffer);
+
+        let nonempty = |rect: Rect| (rect.width > 0 && rect.height > 0).then_some(rect);
+        let strips = match band {
+            Some(b) => [
+                Rect::new(0, 0, screen.width, b.y),
+                Rect::new(0, b.bottom(), screen.width, screen.height - b.bottom()),
+                Rect::new(0, b.y, b.x, b.height),
+                Rect::new(b.right(), b.y, screen.width - b.right(), b.height),
+            ],
+            None => [screen, Rect::new(0, 0, 0, 0), Rect::new(0, 0, 0, 0), Rect::new(0, 0, 0, 0)],
+        };
+        for (strip, rect) in self.strips.iter().zip(strips) {
+            strip.stretch(dim, nonempty(rect));
+        }
+
+        let (Some(band), Some(slot)) = (band, slot) else {
+            self.band.surface.attach(None, 0, 0);
+            self.band.surface.commit();
+            return true;
+        };
+        let len = (band.width * band.height * 4) as usize;
+        let slot = &mut self.slots[slot];
+        // The band is drawn over the same dim color the strips show
+        let background = [0x00, 0x00, 0x00, alpha];
+        let canvas = &mut slot.mmap[..len];
+        let (band_width, band_height) = (band.width as u32, band.height as u32);
+        for_rows(canvas, band_width, (0, 0, band_width, band_height), |_, row| {
+            for pixel in row.chunks_exact_mut(4) {
+                pixel.copy_from_slice(&background);
+            }
+        });
+        paint_scene(canvas, band_width, band_height, &scene.translate(-band.x, -band.y), |_| [0x00; 4], |_| background);
+        let buffer = slot.buffer(band.width, band.height, qh);
+        set_busy(buffer);
+        self.band.subsurface.set_position(band.x, band.y);
+        self.band.surface.attach(Some(buffer), 0, 0);
+        self.band.surface.damage_buffer(0, 0, band.width, band.height);
+        self.band.surface.commit();
+        true
+    }
+
+    /// Index of the free slot, grown to `len` bytes, or of a new one.
+    fn slot(&mut self, free: Option<usize>, len: usize, qh: &QueueHandle<AppState>) -> std::io::Result<usize> {
+        match free {
+            Some(index) => self.slots[index].reserve(len).map(|()| index),
+            None => {
+                self.slots.push(Slot::new(&self.shm, len, qh)?);
+                Ok(self.slots.len() - 1)
+            }
+        }
+    }
+
+    pub fn destroy(self) {
+        for strip in self.strips {
+            strip.destroy();
+        }
+        self.band.destroy();
+        for slot in self.slots {
+            slot.destroy();
+        }
+        if let Some((dim, _)) = self.dim {
+            dim.destroy();
+        }
+        self.clear.destroy();
+        self.viewport.destroy();
+    }
+}
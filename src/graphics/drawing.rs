@@ -0,0 +1,792 @@
+//! Pixel drawing and dirty region tracking for overlay surfaces.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use wayland_client::QueueHandle;
+
+use crate::app::{AppState, Overlay};
+use crate::graphics::buffer::{free_buffer, set_busy};
+use crate::graphics::text::Mask;
+use crate::selection::Rect;
+
+// Label padding around the text, in pixels
+const LABEL_PADDING: i32 = 4;
+
+/// Alpha of the dim layer outside the selection, unless dimming is off.
+pub const DIM_ALPHA: u8 = 0x80;
+
+/// Radius of the ring marking the focused edit handle.
+const FOCUS_RADIUS: i32 = 6;
+
+/// Premultiplied translucent white of the guide lines.
+const GUIDE_COLOR: [u8; 4] = [0x60; 4];
+
+/// Alpha of the selection's fill when the dimming is inverted.
+pub const INVERTED_ALPHA: u8 = 0xD0;
+
+/// How long the dim layer takes to fade in.
+const FADE_IN: Duration = Duration::from_millis(100);
+
+/// How long a confirmed selection flashes before Glimpse exits.
+pub const FLASH: Duration = Duration::from_millis(150);
+
+/// Repainted area, in pixels, above which rows are filled on several threads.
+#[cfg(feature = "parallel")]
+const PARALLEL_PIXELS: u32 = 1 << 20;
+
+/// Progress of an overlay's fade-in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fade {
+    Waiting,          // Starts with the first frame drawn
+    Running(Instant), // Started at this time, redrawing on every frame callback
+    Done,
+}
+
+/// Everything drawn on top of the dimmed background, in surface-local
+/// coordinates. Items may extend past the surface when they span several
+/// outputs.
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    pub selection: Option<Rect>,
+    pub lines: Vec<((i32, i32), (i32, i32))>,
+    pub labels: Vec<Label>,
+    pub pictures: Vec<Picture>,
+    pub flash: Option<u8>, // Alpha of a white fill over the selection
+    pub animated: bool,    // Redrawn on every frame, e.g. while counting down
+    pub corner_radius: i32,
+    pub shadow: Option<Shadow>,
+    pub spotlight: Option<Spotlight>,
+    pub inverted: bool, // Darken the selection, for previewing what is left out
+    pub guides: bool,   // Extend the selection's edges to the screen's edges
+    pub focus: Option<(i32, i32)>, // Edit handle the keyboard moves, shown with a ring
+    pub hidden: bool, // Peeking: nothing is drawn, not even the dim layer
+}
+
+/// How the screen outside the selection is dimmed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dim {
+    Uniform,
+    Spotlight { radius: i32 }, // Clear around the pointer, dimmer up to `radius` away
+    Off,                       // Fully transparent; only the selection and labels are drawn
+    Invert,                    // The selection is darkened instead of the rest
+}
+
+impl Dim {
+    /// Alpha of the dim layer away from any spotlight.
+    pub fn alpha(self) -> u8 {
+        match self {
+            Dim::Off | Dim::Invert => 0,
+            Dim::Uniform | Dim::Spotlight { .. } => DIM_ALPHA,
+        }
+    }
+}
+
+/// A clear circle in the dim layer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spotlight {
+    pub center: (i32, i32),
+    pub radius: i32,
+}
+
+impl Spotlight {
+    fn rect(&self) -> Rect {
+        let (x, y) = self.center;
+        Rect::new(x - self.radius, y - self.radius, 2 * self.radius, 2 * self.radius)
+    }
+}
+
+/// A soft shadow just outside the selection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shadow {
+    pub size: i32,    // How far it reaches, in pixels
+    pub opacity: f32, // At the selection's edge
+}
+
+impl Default for Shadow {
+    fn default() -> Self {
+        Self { size: 8, opacity: 0.4 }
+    }
+}
+
+/// Text on a box, positioned by its top-left corner.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub position: (i32, i32),
+    pub text: Mask,
+    pub style: LabelStyle,
+}
+
+/// Colors (RGB) and corner radius of a label.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabelStyle {
+    pub background: [u8; 3],
+    pub foreground: [u8; 3],
+    pub radius: i32,
+}
+
+impl LabelStyle {
+    /// White text on black, for labels over dimmed or dark content.
+    pub const DARK: LabelStyle = LabelStyle { background: [0x00; 3], foreground: [0xFF; 3], radius: 0 };
+    /// Black text on white, for labels over light content.
+    pub const LIGHT: LabelStyle = LabelStyle { background: [0xFF; 3], foreground: [0x00; 3], radius: 0 };
+    /// White text on gray, for a button under the pointer.
+    pub const HOVERED: LabelStyle = LabelStyle { background: [0x50; 3], foreground: [0xFF; 3], radius: 0 };
+
+    pub fn rounded(self, radius: i32) -> Self {
+        Self { radius, ..self }
+    }
+}
+
+// Opacity of label boxes
+const LABEL_ALPHA: u8 = 0xCC;
+
+/// An opaque BGRA image, drawn 1:1 with a white frame.
+#[derive(Debug, Clone)]
+pub struct Picture {
+    pub position: (i32, i32),
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Arc<Vec<u8>>, // Shared by every frame showing it
+}
+
+impl Picture {
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.position.0, self.position.1, self.width as i32, self.height as i32)
+    }
+}
+
+impl Label {
+    /// Area covered by the label including its padding.
+    pub fn rect(&self) -> Rect {
+        let (x, y) = self.position;
+        Rect::new(x, y, self.text.width as i32 + 2 * LABEL_PADDING, self.text.height as i32 + 2 * LABEL_PADDING)
+    }
+}
+
+impl Scene {
+    /// Moves every item by `(dx, dy)`, e.g. from global to surface-local coordinates.
+    pub fn translate(&self, dx: i32, dy: i32) -> Scene {
+        let shift = |(x, y): (i32, i32)| (x + dx, y + dy);
+        Scene {
+            selection: self.selection.map(|rect| rect.translate(dx, dy)),
+            lines: self.lines.iter().map(|&(a, b)| (shift(a), shift(b))).collect(),
+            labels: self.labels.iter()
+                .map(|label| Label { position: shift(label.position), ..label.clone() })
+                .collect(),
+            pictures: self.pictures.iter()
+                .map(|picture| Picture { position: shift(picture.position), ..picture.clone() })
+                .collect(),
+            flash: self.flash,
+            animated: self.animated,
+            corner_radius: self.corner_radius,
+            shadow: self.shadow,
+            spotlight: self.spotlight.map(|spotlight| Spotlight { center: shift(spotlight.center), ..spotlight }),
+            inverted: self.inverted,
+            guides: self.guides,
+            focus: self.focus.map(shift),
+            hidden: self.hidden,
+        }
+    }
+
+    /// Bounding box of everything in the scene, grown by a pixel on each side.
+    pub fn bounds(&self) -> Option<Rect> {
+        let grow = |r: Rect| Rect::new(r.x - 1, r.y - 1, r.width + 2, r.height + 2);
+        let shadow = self.shadow.map_or(0, |shadow| shadow.size);
+        // Guides reach across every surface, so they span far past any of them
+        let far = i32::MAX / 4;
+        let guides = self.selection.filter(|_| self.guides).into_iter().flat_map(|r| {
+            [Rect::new(-far, r.y, 2 * far, r.height), Rect::new(r.x, -far, r.width, 2 * far)]
+        });
+        let rects = self.selection.into_iter()
+            .map(|r| Rect::new(r.x - shadow, r.y - shadow, r.width + 2 * shadow, r.height + 2 * shadow))
+            .chain(guides)
+            .chain(self.lines.iter().map(|&(a, b)| Rect::from_corners(a, b)))
+            .chain(self.labels.iter().map(Label::rect))
+            .chain(self.pictures.iter().map(Picture::rect))
+            .chain(self.focus.map(|(x, y)| {
+                let reach = FOCUS_RADIUS + 3;
+                Rect::new(x - reach, y - reach, 2 * reach, 2 * reach)
+            }))
+            .chain(self.spotlight.as_ref().map(Spotlight::rect))
+            .map(grow);
+        rects.reduce(|a, b| {
+            Rect::from_corners((a.x.min(b.x), a.y.min(b.y)), (a.right().max(b.right()), a.bottom().max(b.bottom())))
+        })
+    }
+}
+
+/// Draws the overlay and the part of the scene that falls on it.
+///
+/// Only the selection edges that lie on this surface get a border, so a
+/// selection spanning several outputs shows a single outline.
+pub fn draw_frame(overlay: &mut Overlay, scene: &Scene, qh: &QueueHandle<AppState>) {
+    let width = overlay.width;
+    let height = overlay.height;
+
+    if let Some(layer) = overlay.dim_layer.as_mut().filter(|_| width > 0 && height > 0) {
+        let alpha = fade_step(&mut overlay.fade, overlay.dim_alpha).unwrap_or(overlay.dim_alpha);
+        let alpha = if scene.hidden { 0 } else { alpha };
+        if !layer.draw(&overlay.surface, width, height, alpha, scene, qh) {
+            // Draw once the compositor releases a buffer
+            overlay.needs_redraw = true;
+            return;
+        }
+        if overlay.fade != Fade::Done || scene.flash.is_some() || scene.animated {
+            overlay.surface.frame(qh, overlay.surface.clone());
+        }
+        overlay.surface.commit();
+        return;
+    }
+
+    // Check for valid size and buffer initialization
+    if width == 0 || height == 0 || overlay.buffers.is_empty() {
+        return;
+    }
+    let Some(target) = free_buffer(overlay, qh) else {
+        // Draw once the compositor releases one
+        overlay.needs_redraw = true;
+        return;
+    };
+
+    let fully_transparent = [0x00, 0x00, 0x00, 0x00];
+    let bounds = Rect::new(0, 0, width as i32, height as i32);
+
+    if scene.hidden {
+        draw_hidden(overlay, target);
+        return;
+    }
+    overlay.hidden = false;
+
+    // Track previous and current scene bounds, union for dirty region
+    let mut dirty_min_x = width;
+    let mut dirty_min_y = height;
+    let mut dirty_max_x = 0;
+    let mut dirty_max_y = 0;
+
+    // Previous scene
+    if let Some((old_min_x, old_min_y, old_max_x, old_max_y)) = overlay.prev_scene_rect {
+        dirty_min_x = dirty_min_x.min(old_min_x);
+        dirty_min_y = dirty_min_y.min(old_min_y);
+        dirty_max_x = dirty_max_x.max(old_max_x);
+        dirty_max_y = dirty_max_y.max(old_max_y);
+    }
+
+    // Current scene, clipped to the surface
+    let curr_rect = scene.bounds()
+        .and_then(|r| r.intersection(&bounds))
+        .map(|r| (r.x as u32, r.y as u32, r.right() as u32, r.bottom() as u32));
+    if let Some((curr_min_x, curr_min_y, curr_max_x, curr_max_y)) = curr_rect {
+        dirty_min_x = dirty_min_x.min(curr_min_x);
+        dirty_min_y = dirty_min_y.min(curr_min_y);
+        dirty_max_x = dirty_max_x.max(curr_max_x);
+        dirty_max_y = dirty_max_y.max(curr_max_y);
+    }
+    // Save current bounds for next frame
+    overlay.prev_scene_rect = curr_rect;
+
+    // While fading in, every frame repaints the whole dim layer at a rising alpha
+    let fading = overlay.fade != Fade::Done;
+    let fade_alpha = fade_step(&mut overlay.fade, overlay.dim_alpha);
+    if fading && fade_alpha.is_none() {
+        // The buffers still hold a partial dim layer
+        overlay.full_redraw = true;
+    }
+
+    if fade_alpha.is_some() || overlay.full_redraw {
+        // Fading, or a fresh background: paint all of it
+        overlay.full_redraw = false;
+        dirty_min_x = 0;
+        dirty_min_y = 0;
+        dirty_max_x = width;
+        dirty_max_y = height;
+    } else if dirty_max_x <= dirty_min_x || dirty_max_y <= dirty_min_y {
+        // The scene neither touches nor left this surface
+        return;
+    }
+
+    // The other buffers now lag behind in the dirty region too
+    let dirty = (dirty_min_x, dirty_min_y, dirty_max_x, dirty_max_y);
+    for (index, buffer) in overlay.buffers.iter_mut().enumerate() {
+        if index != target {
+            buffer.stale = Some(union(buffer.stale, dirty));
+        }
+    }
+
+    // Draw straight into the free buffer: this frame's changes, plus
+    // whatever changed while it was last on screen. The scene lies within
+    // the dirty region, so everything it draws over is repainted first.
+    let buffer = &mut overlay.buffers[target];
+    let (min_x, min_y, max_x, max_y) = union(buffer.stale.take(), dirty);
+    let canvas = &mut buffer.mmap[..];
+    match (fade_alpha, &overlay.background_cache) {
+        (Some(alpha), _) => for_rows(canvas, width, (0, 0, width, height), |_, row| {
+            for pixel in row.chunks_exact_mut(4) {
+                pixel.copy_from_slice(&[0x00, 0x00, 0x00, alpha]);
+            }
+        }),
+        (None, Some(bg)) => for_rows(canvas, width, (min_x, min_y, max_x, max_y), |start, row| {
+            row.copy_from_slice(&bg[start..start + row.len()]);
+        }),
+        (None, None) => {}
+    }
+
+    // What the clear parts show: the live screen through a transparent
+    // overlay, or the frozen one
+    let frozen = overlay.frozen.as_deref();
+    let clear_at = |offset: usize| frozen.map_or(fully_transparent, |frozen| pixel_at(frozen, offset));
+    // The background as painted this frame
+    let background = &overlay.background_cache;
+    let dim_at = |offset: usize| match (fade_alpha, background) {
+        (None, Some(background)) => pixel_at(background, offset),
+        _ => [0x00, 0x00, 0x00, fade_alpha.unwrap_or(overlay.dim_alpha)],
+    };
+
+    paint_scene(canvas, width, height, scene, clear_at, dim_at);
+    buffer.mmap.flush().expect("Failed to flush mmap");
+
+    // Display it; the compositor holds it until it sends a release
+    set_busy(&buffer.buffer);
+    overlay.surface.attach(Some(&buffer.buffer), 0, 0);
+    // Only damage the dirty region
+    overlay.surface.damage_buffer(
+        dirty_min_x as i32,
+        dirty_min_y as i32,
+        (dirty_max_x - dirty_min_x) as i32,
+        (dirty_max_y - dirty_min_y) as i32,
+    );
+    if fade_alpha.is_some() || scene.flash.is_some() || scene.animated {
+        // Draw the next step once this one is on screen
+        overlay.surface.frame(qh, overlay.surface.clone());
+    }
+    overlay.surface.commit();
+}
+
+/// Shows the screen as is, or the frozen one, over the whole surface while
+/// peeking. Everything is repainted once peeking ends.
+fn draw_hidden(overlay: &mut Overlay, target: usize) {
+    if overlay.hidden {
+        return;
+    }
+    let (width, height) = (overlay.width, overlay.height);
+    let len = (width * height * 4) as usize;
+    let buffer = &mut overlay.buffers[target];
+    // The mapping may be larger, left over from a bigger surface
+    match &overlay.frozen {
+        Some(frozen) => buffer.mmap[..frozen.len()].copy_from_slice(frozen),
+        None => buffer.mmap[..len].fill(0),
+    }
+    buffer.mmap.flush().expect("Failed to flush mmap");
+    set_busy(&buffer.buffer);
+    overlay.surface.attach(Some(&buffer.buffer), 0, 0);
+    overlay.surface.damage_buffer(0, 0, width as i32, height as i32);
+    overlay.surface.commit();
+    for buffer in &mut overlay.buffers {
+        buffer.stale = Some((0, 0, width, height));
+    }
+    overlay.hidden = true;
+    overlay.full_redraw = true;
+    overlay.prev_scene_rect = None;
+}
+
+/// Calls `f` with the part of each row of `canvas` inside `region`, and
+/// its byte offset. Large regions are split across threads with the
+/// parallel feature.
+pub fn for_rows(canvas: &mut [u8], width: u32, region: (u32, u32, u32, u32), f: impl Fn(usize, &mut [u8]) + Sync) {
+    let (min_x, min_y, max_x, max_y) = region;
+    if max_x <= min_x || max_y <= min_y {
+        return;
+    }
+    let stride = width as usize * 4;
+    let (left, right) = (min_x as usize * 4, max_x as usize * 4);
+    let rows = &mut canvas[min_y as usize * stride..max_y as usize * stride];
+    let row = |(y, row): (usize, &mut [u8])| f((min_y as usize + y) * stride + left, &mut row[left..right]);
+    #[cfg(feature = "parallel")]
+    if (max_x - min_x) * (max_y - min_y) >= PARALLEL_PIXELS {
+        rows.par_chunks_mut(stride).enumerate().for_each(row);
+        return;
+    }
+    rows.chunks_mut(stride).enumerate().for_each(row);
+}
+
+/// Smallest `(min_x, min_y, max_x, max_y)` region covering both.
+fn union(a: Option<(u32, u32, u32, u32)>, b: (u32, u32, u32, u32)) -> (u32, u32, u32, u32) {
+    match a {
+        Some(a) => (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)),
+        None => b,
+    }
+}
+
+/// Alpha of the dim layer while it fades in to `alpha`, or `None` once the
+/// fade is over.
+pub fn fade_step(fade: &mut Fade, alpha: u8) -> Option<u8> {
+    match *fade {
+        Fade::Waiting => {
+            *fade = Fade::Running(Instant::now());
+            Some(0)
+        }
+        Fade::Running(start) => {
+            let progress = start.elapsed().as_secs_f32() / FADE_IN.as_secs_f32();
+            if progress < 1.0 {
+                Some((alpha as f32 * progress) as u8)
+            } else {
+                *fade = Fade::Done;
+                None
+            }
+        }
+        Fade::Done => None,
+    }
+}
+
+/// Draws the scene over a canvas that already holds the background.
+/// `clear_at` gives what the clear parts show and `dim_at` the background,
+/// by byte offset into the canvas.
+pub fn paint_scene(
+    canvas_data: &mut [u8],
+    width: u32,
+    height: u32,
+    scene: &Scene,
+    clear_at: impl Fn(usize) -> [u8; 4],
+    dim_at: impl Fn(usize) -> [u8; 4],
+) {
+    let bounds = Rect::new(0, 0, width as i32, height as i32);
+    if let Some(spotlight) = scene.spotlight {
+        draw_spotlight(canvas_data, width, bounds, spotlight, &clear_at);
+    }
+
+    if let (Some(sel), Some(shadow)) = (scene.selection, scene.shadow) {
+        let radius = scene.corner_radius.min(sel.width / 2).min(sel.height / 2);
+        draw_shadow(canvas_data, width, bounds, sel, radius, shadow);
+    }
+
+    // Draw current selection rectangle and border if present
+    if let Some(sel) = scene.selection {
+        if let Some(inner) = sel.intersection(&bounds) {
+            let min_x = inner.x as u32;
+            let max_x = inner.right() as u32;
+            let min_y = inner.y as u32;
+            let max_y = inner.bottom() as u32;
+
+            // Draw the clear fill (darkened when inverted), under white
+            // while flashing
+            let darken = if scene.inverted { INVERTED_ALPHA } else { 0 };
+            let fill = |offset: usize| {
+                let fill = over([0x00, 0x00, 0x00, darken], clear_at(offset));
+                scene.flash.map_or(fill, |alpha| over([alpha; 4], fill))
+            };
+            for y in min_y..max_y {
+                let row_start = ((y * width + min_x) * 4) as usize;
+                let row_end = ((y * width + max_x) * 4) as usize;
+                for offset in (row_start..row_end).step_by(4) {
+                    canvas_data[offset..offset + 4].copy_from_slice(&fill(offset));
+                }
+            }
+
+            // Draw white border, skipping edges that lie on another output
+            let white = [0xFF, 0xFF, 0xFF, 0xFF]; // BGRA
+            let mut put = |x: u32, y: u32| {
+                let offset = ((y * width + x) * 4) as usize;
+                canvas_data[offset..offset + 4].copy_from_slice(&white);
+            };
+            // Top border
+            if sel.y == inner.y {
+                for x in min_x..max_x {
+                    put(x, min_y);
+                }
+            }
+            // Bottom border
+            if sel.bottom() == inner.bottom() {
+                for x in min_x..max_x {
+                    put(x, max_y - 1);
+                }
+            }
+            // Left border
+            if sel.x == inner.x {
+                for y in min_y..max_y {
+                    put(min_x, y);
+                }
+            }
+            // Right border
+            if sel.right() == inner.right() {
+                for y in min_y..max_y {
+                    put(max_x - 1, y);
+                }
+            }
+
+            let radius = scene.corner_radius.min(sel.width / 2).min(sel.height / 2);
+            if radius > 0 {
+                round_corners(canvas_data, width, sel, inner, radius, fill, dim_at);
+            }
+        }
+    }
+
+    if let Some(sel) = scene.selection.filter(|_| scene.guides) {
+        draw_guides(canvas_data, width, height, sel);
+    }
+    for &(from, to) in &scene.lines {
+        draw_line(canvas_data, width, height, from, to);
+    }
+    if let Some(center) = scene.focus {
+        draw_focus_ring(canvas_data, width, bounds, center);
+    }
+    for picture in &scene.pictures {
+        draw_picture(canvas_data, width, bounds, picture);
+    }
+    for label in &scene.labels {
+        draw_label(canvas_data, width, height, label);
+    }
+}
+
+/// Premultiplied `top` composited over `under`.
+fn over(top: [u8; 4], under: [u8; 4]) -> [u8; 4] {
+    let keep = 255 - top[3] as u32;
+    std::array::from_fn(|channel| top[channel] + (under[channel] as u32 * keep / 255) as u8)
+}
+
+fn pixel_at(data: &[u8], offset: usize) -> [u8; 4] {
+    data[offset..offset + 4].try_into().unwrap()
+}
+
+/// Clears the background around the spotlight's center: fully clear in the
+/// middle, easing back to the background at its edge.
+fn draw_spotlight(canvas: &mut [u8], width: u32, bounds: Rect, spotlight: Spotlight, clear_at: impl Fn(usize) -> [u8; 4]) {
+    let Some(area) = spotlight.rect().intersection(&bounds) else { return };
+    let (cx, cy) = spotlight.center;
+    let r = spotlight.radius as f32;
+    for y in area.y..area.bottom() {
+        for x in area.x..area.right() {
+            let distance = (x as f32 + 0.5 - cx as f32).hypot(y as f32 + 0.5 - cy as f32) / r;
+            if distance >= 1.0 {
+                continue;
+            }
+            // Smoothstep from a third of the radius out to its edge
+            let t = ((distance - 0.33) / 0.67).clamp(0.0, 1.0);
+            let t = t * t * (3.0 - 2.0 * t);
+            let offset = ((y as u32 * width + x as u32) * 4) as usize;
+            let clear = clear_at(offset);
+            for (channel, value) in canvas[offset..offset + 4].iter_mut().enumerate() {
+                *value = (clear[channel] as f32 * (1.0 - t) + *value as f32 * t).round() as u8;
+            }
+        }
+    }
+}
+
+/// Darkens the band around `sel` with a gaussian falloff, following its
+/// rounded corners.
+fn draw_shadow(canvas: &mut [u8], width: u32, bounds: Rect, sel: Rect, radius: i32, shadow: Shadow) {
+    let size = shadow.size;
+    let sigma = size as f32 / 2.0;
+    let falloff: Vec<f32> = (0..size)
+        .map(|d| shadow.opacity * (-(d as f32).powi(2) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let band = Rect::new(sel.x - size, sel.y - size, sel.width + 2 * size, sel.height + 2 * size);
+    let Some(area) = band.intersection(&bounds) else { return };
+    let (half_w, half_h) = (sel.width as f32 / 2.0, sel.height as f32 / 2.0);
+    let (cx, cy) = (sel.x as f32 + half_w, sel.y as f32 + half_h);
+    let r = radius as f32;
+    for y in area.y..area.bottom() {
+        for x in area.x..area.right() {
+            // Distance from the pixel center to the (rounded) selection
+            let qx = (x as f32 + 0.5 - cx).abs() - (half_w - r);
+            let qy = (y as f32 + 0.5 - cy).abs() - (half_h - r);
+            let distance = qx.max(0.0).hypot(qy.max(0.0)) + qx.max(qy).min(0.0) - r;
+            if distance < 0.0 {
+                continue;
+            }
+            let Some(&alpha) = falloff.get(distance as usize) else { continue };
+            // Black over what is there, premultiplied
+            let offset = ((y as u32 * width + x as u32) * 4) as usize;
+            for value in &mut canvas[offset..offset + 4] {
+                *value = (*value as f32 * (1.0 - alpha)).round() as u8;
+            }
+            let under = canvas[offset + 3] as f32;
+            canvas[offset + 3] = (under + alpha * 255.0).round().min(255.0) as u8;
+        }
+    }
+}
+
+/// Repaints the corners of the selection as anti-aliased quarter circles:
+/// the outermost pixel of the radius is border, inside it the fill, and
+/// outside it the dim layer. Corners on another output are left square.
+fn round_corners(
+    canvas: &mut [u8],
+    width: u32,
+    sel: Rect,
+    inner: Rect,
+    radius: i32,
+    fill_at: impl Fn(usize) -> [u8; 4],
+    dim_at: impl Fn(usize) -> [u8; 4],
+) {
+    let r = radius as f32;
+    let (left, top, right, bottom) = (sel.x, sel.y, sel.right() - radius, sel.bottom() - radius);
+    // Whether the corner is on this surface, the top-left of its square and
+    // the center of its arc
+    let corners = [
+        (sel.x == inner.x && sel.y == inner.y, (left, top), (left + radius, top + radius)),
+        (sel.right() == inner.right() && sel.y == inner.y, (right, top), (right, top + radius)),
+        (sel.x == inner.x && sel.bottom() == inner.bottom(), (left, bottom), (left + radius, bottom)),
+        (sel.right() == inner.right() && sel.bottom() == inner.bottom(), (right, bottom), (right, bottom)),
+    ];
+    for (on_surface, (x0, y0), (cx, cy)) in corners {
+        if !on_surface {
+            continue;
+        }
+        let Some(area) = Rect::new(x0, y0, radius, radius).intersection(&inner) else { continue };
+        for y in area.y..area.bottom() {
+            for x in area.x..area.right() {
+                let distance = (x as f32 + 0.5 - cx as f32).hypot(y as f32 + 0.5 - cy as f32);
+                // Coverage of the whole shape and of the part inside the border
+                let shape = (r - distance + 0.5).clamp(0.0, 1.0);
+                let inside = (r - 1.0 - distance + 0.5).clamp(0.0, 1.0);
+                let weights = [shape - inside, inside, 1.0 - shape]; // Border, fill, dim
+                let offset = ((y as u32 * width + x as u32) * 4) as usize;
+                let (fill, dim) = (fill_at(offset), dim_at(offset));
+                for (channel, value) in canvas[offset..offset + 4].iter_mut().enumerate() {
+                    let blended = weights[0] * 255.0 + weights[1] * fill[channel] as f32 + weights[2] * dim[channel] as f32;
+                    *value = blended.round() as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Continues each edge of `sel` out to the edges of the canvas with a faint
+/// line.
+fn draw_guides(canvas: &mut [u8], width: u32, height: u32, sel: Rect) {
+    let mut blend = |x: i32, y: i32| {
+        let offset = ((y as u32 * width + x as u32) * 4) as usize;
+        let blended = over(GUIDE_COLOR, pixel_at(canvas, offset));
+        canvas[offset..offset + 4].copy_from_slice(&blended);
+    };
+    let (width, height) = (width as i32, height as i32);
+    for y in [sel.y, sel.bottom() - 1].into_iter().filter(|y| (0..height).contains(y)) {
+        for x in (0..width).filter(|x| !(sel.x..sel.right()).contains(x)) {
+            blend(x, y);
+        }
+    }
+    for x in [sel.x, sel.right() - 1].into_iter().filter(|x| (0..width).contains(x)) {
+        for y in (0..height).filter(|y| !(sel.y..sel.bottom()).contains(y)) {
+            blend(x, y);
+        }
+    }
+}
+
+/// Draws an anti-aliased white ring with a dark halo around `center`, so it
+/// stands out over both the selection and the dim layer.
+fn draw_focus_ring(canvas: &mut [u8], width: u32, bounds: Rect, (cx, cy): (i32, i32)) {
+    let reach = FOCUS_RADIUS + 3;
+    let Some(area) = Rect::new(cx - reach, cy - reach, 2 * reach, 2 * reach).intersection(&bounds) else { return };
+    for y in area.y..area.bottom() {
+        for x in area.x..area.right() {
+            let distance = (x as f32 + 0.5 - cx as f32).hypot(y as f32 + 0.5 - cy as f32);
+            let off_ring = (distance - FOCUS_RADIUS as f32).abs();
+            // A 2px ring inside a 4px halo
+            let ring = (1.5 - off_ring).clamp(0.0, 1.0);
+            let halo = (2.5 - off_ring).clamp(0.0, 1.0) * 0.6;
+            if halo <= 0.0 {
+                continue;
+            }
+            let offset = ((y as u32 * width + x as u32) * 4) as usize;
+            let dark = over([0x00, 0x00, 0x00, (halo * 255.0) as u8], pixel_at(canvas, offset));
+            let white = (ring * 255.0) as u8;
+            canvas[offset..offset + 4].copy_from_slice(&over([white; 4], dark));
+        }
+    }
+}
+
+/// Draws a 1px white line with Bresenham's algorithm, clipped to the canvas.
+fn draw_line(canvas: &mut [u8], width: u32, height: u32, from: (i32, i32), to: (i32, i32)) {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let step_x = if x < to.0 { 1 } else { -1 };
+    let step_y = if y < to.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    loop {
+        if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+            let offset = ((y as u32 * width + x as u32) * 4) as usize;
+            canvas[offset..offset + 4].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        }
+        if (x, y) == to {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Copies a picture onto the canvas, with its outermost pixels replaced by
+/// a white frame.
+fn draw_picture(canvas: &mut [u8], width: u32, bounds: Rect, picture: &Picture) {
+    let rect = picture.rect();
+    let Some(area) = rect.intersection(&bounds) else { return };
+    for y in area.y..area.bottom() {
+        for x in area.x..area.right() {
+            let offset = ((y as u32 * width + x as u32) * 4) as usize;
+            let edge = x == rect.x || y == rect.y || x == rect.right() - 1 || y == rect.bottom() - 1;
+            let pixel = if edge {
+                [0xFF; 4]
+            } else {
+                let source = (((y - rect.y) as u32 * picture.width + (x - rect.x) as u32) * 4) as usize;
+                pixel_at(&picture.pixels, source)
+            };
+            canvas[offset..offset + 4].copy_from_slice(&pixel);
+        }
+    }
+}
+
+/// Draws a label's text on its box, clipped to the canvas.
+fn draw_label(canvas: &mut [u8], width: u32, height: u32, label: &Label) {
+    let bounds = Rect::new(0, 0, width as i32, height as i32);
+    let rect = label.rect();
+    let Some(area) = rect.intersection(&bounds) else { return };
+    let (text_x, text_y) = (label.position.0 + LABEL_PADDING, label.position.1 + LABEL_PADDING);
+    let LabelStyle { background, foreground, radius } = label.style;
+    let radius = radius.min(rect.width / 2).min(rect.height / 2) as f32;
+    let (half_w, half_h) = (rect.width as f32 / 2.0, rect.height as f32 / 2.0);
+    let (cx, cy) = (rect.x as f32 + half_w, rect.y as f32 + half_h);
+    for y in area.y..area.bottom() {
+        for x in area.x..area.right() {
+            // Part of the pixel inside the (rounded) box
+            let qx = (x as f32 + 0.5 - cx).abs() - (half_w - radius);
+            let qy = (y as f32 + 0.5 - cy).abs() - (half_h - radius);
+            let outside = qx.max(0.0).hypot(qy.max(0.0)) - radius;
+            let inside = (0.5 - outside).clamp(0.0, 1.0);
+            if inside == 0.0 {
+                continue;
+            }
+            let (tx, ty) = (x - text_x, y - text_y);
+            let coverage = if tx >= 0 && ty >= 0 && (tx as u32) < label.text.width && (ty as u32) < label.text.height {
+                label.text.alpha[(ty as u32 * label.text.width + tx as u32) as usize] as f32 / 255.0
+            } else {
+                0.0
+            };
+            // Premultiplied text over the translucent box, replacing what is
+            // there except at the antialiased corners
+            let box_alpha = LABEL_ALPHA as f32 / 255.0;
+            let alpha = box_alpha + (1.0 - box_alpha) * coverage;
+            let offset = ((y as u32 * width + x as u32) * 4) as usize;
+            let pixel = &mut canvas[offset..offset + 4];
+            for (i, value) in pixel.iter_mut().enumerate() {
+                let label_value = match i {
+                    3 => alpha * 255.0,
+                    // BGRA order
+                    _ => {
+                        let channel = 2 - i;
+                        foreground[channel] as f32 * coverage + background[channel] as f32 * box_alpha * (1.0 - coverage)
+                    }
+                };
+                *value = (label_value * inside + *value as f32 * (1.0 - inside)).round() as u8;
+            }
+        }
+    }
+}
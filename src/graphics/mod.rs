@@ -0,0 +1,8 @@
+//! Buffer management, drawing routines, text and the custom cursor.
+
+pub mod blur;
+pub mod buffer;
+pub mod dim_layer;
+pub mod cursor;
+pub mod drawing;
+pub mod text;
@@ -0,0 +1,204 @@
+//! Text rendering for labels drawn on the overlay.
+//!
+//! The font is resolved through fontconfig (`fc-match`), so labels follow the
+//! system's sans-serif font or a configured family. Characters the font lacks
+//! are looked up again by charset and drawn with a fallback font.
+//!
+//! Running fc-match is a noticeable part of starting up, so the file found
+//! for each family is cached until fontconfig's own caches change.
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use ab_glyph::{Font, FontVec, GlyphId, PxScale, ScaleFont};
+
+use crate::paths;
+
+/// Fonts tried in order when fontconfig is unavailable, covering the usual
+/// distribution locations.
+const FONT_PATHS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu-sans-fonts/DejaVuSans.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/liberation/LiberationSans-Regular.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+];
+
+/// Family used when none is configured.
+const DEFAULT_FAMILY: &str = "sans-serif";
+
+/// Default text height in pixels.
+pub const TEXT_SIZE: f32 = 16.0;
+
+/// A rendered line of text: one coverage value per pixel.
+#[derive(Debug, Clone)]
+pub struct Mask {
+    pub width: u32,
+    pub height: u32,
+    pub alpha: Vec<u8>,
+}
+
+pub struct TextRenderer {
+    family: String,
+    fonts: RefCell<Vec<(PathBuf, FontVec)>>, // The main font first, then fallbacks found so far
+    missing: RefCell<Vec<char>>,             // Characters no font could provide
+}
+
+impl TextRenderer {
+    /// Loads `family` (default: sans-serif) through fontconfig, falling back
+    /// to well-known font files. `None` if there is no usable font.
+    pub fn load(family: Option<&str>) -> Option<Self> {
+        let family = family.unwrap_or(DEFAULT_FAMILY);
+        let font = find_font(family)
+            .and_then(|path| read_font(&path).map(|font| (path, font)))
+            .or_else(|| {
+                FONT_PATHS.iter().find_map(|path| {
+                    let path = PathBuf::from(path);
+                    read_font(&path).map(|font| (path, font))
+                })
+            })?;
+        log::debug!("Using font {}", font.0.display());
+        Some(Self {
+            family: family.to_string(),
+            fonts: RefCell::new(vec![font]),
+            missing: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// Makes sure some loaded font has a glyph for `c`, asking fontconfig for
+    /// one that covers it if needed.
+    fn ensure_glyph(&self, c: char) {
+        if c.is_control() || self.missing.borrow().contains(&c) {
+            return;
+        }
+        if self.fonts.borrow().iter().any(|(_, font)| font.glyph_id(c) != GlyphId(0)) {
+            return;
+        }
+        let pattern = format!("{}:charset={:x}", escape_family(&self.family), c as u32);
+        let fallback = fc_match(&pattern)
+            .filter(|path| !self.fonts.borrow().iter().any(|(loaded, _)| loaded == path))
+            .and_then(|path| read_font(&path).map(|font| (path, font)))
+            .filter(|(_, font)| font.glyph_id(c) != GlyphId(0));
+        match fallback {
+            Some(font) => {
+                log::debug!("Using fallback font {} for '{c}'", font.0.display());
+                self.fonts.borrow_mut().push(font);
+            }
+            None => self.missing.borrow_mut().push(c),
+        }
+    }
+
+    /// Renders `text` on a single line at `size` pixels.
+    pub fn render(&self, text: &str, size: f32) -> Mask {
+        for c in text.chars() {
+            self.ensure_glyph(c);
+        }
+        let fonts = self.fonts.borrow();
+        // Each character comes from the first font that has it, or shows the
+        // main font's missing glyph box
+        let glyphs: Vec<(&FontVec, GlyphId)> = text.chars()
+            .map(|c| {
+                fonts.iter()
+                    .map(|(_, font)| (font, font.glyph_id(c)))
+                    .find(|&(_, id)| id != GlyphId(0))
+                    .unwrap_or((&fonts[0].1, GlyphId(0)))
+            })
+            .collect();
+        let scale = PxScale::from(size);
+        let main = fonts[0].1.as_scaled(scale);
+        let width = glyphs.iter()
+            .map(|&(font, id)| font.as_scaled(scale).h_advance(id))
+            .sum::<f32>()
+            .ceil() as u32;
+        let height = main.height().ceil() as u32;
+        let mut mask = Mask { width, height, alpha: vec![0; (width * height) as usize] };
+
+        let mut caret = 0.0;
+        let mut previous: Option<(&FontVec, GlyphId)> = None;
+        for &(font, id) in &glyphs {
+            let scaled = font.as_scaled(scale);
+            // Kerning only applies between glyphs of the same font
+            if let Some((_, previous_id)) = previous.filter(|&(f, _)| std::ptr::eq(f, font)) {
+                caret += scaled.kern(previous_id, id);
+            }
+            previous = Some((font, id));
+            let glyph = id.with_scale_and_position(scale, ab_glyph::point(caret, main.ascent()));
+            caret += scaled.h_advance(id);
+            let Some(outline) = font.outline_glyph(glyph) else { continue };
+            let bounds = outline.px_bounds();
+            outline.draw(|x, y, coverage| {
+                let x = bounds.min.x as i32 + x as i32;
+                let y = bounds.min.y as i32 + y as i32;
+                if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+                    let pixel = &mut mask.alpha[(y as u32 * width + x as u32) as usize];
+                    *pixel = (*pixel).max((coverage * 255.0).round() as u8);
+                }
+            });
+        }
+        mask
+    }
+}
+
+fn read_font(path: &Path) -> Option<FontVec> {
+    FontVec::try_from_vec(std::fs::read(path).ok()?).ok()
+}
+
+/// The file fontconfig picks for `family`, from the cache if it is still
+/// current.
+fn find_font(family: &str) -> Option<PathBuf> {
+    let cache = paths::font_cache_file();
+    // Entries are dropped altogether once fonts change
+    let entries = cache.as_deref().filter(|cache| is_current(cache))
+        .and_then(|cache| std::fs::read_to_string(cache).ok())
+        .unwrap_or_default();
+    let cached = entries.lines()
+        .filter_map(|line| line.split_once('\t'))
+        .find(|&(f, _)| f == family)
+        .map(|(_, path)| PathBuf::from(path));
+    if let Some(path) = cached.filter(|path| path.is_file()) {
+        return Some(path);
+    }
+    let path = fc_match(&escape_family(family))?;
+    if let Some(cache) = cache {
+        // One `family<TAB>path` line each
+        let mut contents: String = entries.lines()
+            .filter(|line| line.split_once('\t').is_some_and(|(f, _)| f != family))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        contents += &format!("{family}\t{}\n", path.display());
+        if let Err(err) = paths::create_parent(&cache).and_then(|()| std::fs::write(&cache, contents)) {
+            log::debug!("Failed to cache the font for '{family}' in {}: {err}", cache.display());
+        }
+    }
+    Some(path)
+}
+
+/// Whether `cache` was written after fontconfig's caches last changed.
+fn is_current(cache: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let Some(written) = modified(cache) else { return false };
+    !paths::fontconfig_cache_dirs().iter().filter_map(|dir| modified(dir)).any(|changed| changed > written)
+}
+
+/// Asks fontconfig for the file of the best match for `pattern`.
+fn fc_match(pattern: &str) -> Option<PathBuf> {
+    let output = Command::new("fc-match").args(["--format=%{file}", pattern]).output().ok()?;
+    let path = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// Escapes the characters fontconfig patterns treat specially, so a family
+/// like `Noto Sans CJK-JP` is matched as written.
+fn escape_family(family: &str) -> String {
+    family.chars().fold(String::new(), |mut escaped, c| {
+        if matches!(c, '\\' | '-' | ':' | ',') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
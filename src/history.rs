@@ -0,0 +1,114 @@
+//! The history of confirmed selections, kept in
+//! `$XDG_STATE_HOME/glimpse/history` so regions can be reused across
+//! sessions.
+//!
+//! Each line holds one entry: time, geometry, output and selection method,
+//! separated by tabs. The newest entry comes last.
+
+use std::io::Write;
+
+use serde_json::json;
+
+use crate::paths;
+use crate::selection::Rect;
+
+// Older entries are dropped beyond this
+const MAX_ENTRIES: usize = 1000;
+
+pub struct Entry {
+    pub time: String, // RFC 3339
+    pub rect: Rect,
+    pub output: String, // Empty when unknown
+    pub method: String, // drag, click, output or edit
+}
+
+impl Entry {
+    pub fn new(rect: Rect, output: Option<&str>, method: &str) -> Self {
+        Self {
+            time: chrono::Local::now().to_rfc3339(),
+            rect,
+            output: output.unwrap_or_default().to_string(),
+            method: method.to_string(),
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let time = fields.next()?.to_string();
+        let rect = Rect::parse(fields.next()?)?;
+        let output = fields.next()?.to_string();
+        let method = fields.next()?.to_string();
+        Some(Self { time, rect, output, method })
+    }
+
+    fn to_line(&self) -> String {
+        let Rect { x, y, width, height } = self.rect;
+        format!("{}\t{x},{y} {width}x{height}\t{}\t{}", self.time, self.output, self.method)
+    }
+
+    /// The geometry as Glimpse prints it, e.g. for `--initial`.
+    pub fn geometry(&self) -> String {
+        let Rect { x, y, width, height } = self.rect;
+        format!("{x},{y},{width}x{height}")
+    }
+}
+
+/// Reads all entries, oldest first. Unreadable lines are skipped.
+pub fn load() -> Result<Vec<Entry>, Box<dyn std::error::Error>> {
+    let path = paths::history_file().ok_or("Cannot determine the state directory; is $HOME set?")?;
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("{}: {err}", path.display()).into()),
+    };
+    Ok(contents.lines().filter_map(Entry::parse).collect())
+}
+
+/// Appends an entry, dropping the oldest ones past [`MAX_ENTRIES`].
+pub fn record(entry: Entry) -> Result<(), Box<dyn std::error::Error>> {
+    let path = paths::history_file().ok_or("Cannot determine the state directory; is $HOME set?")?;
+    let mut entries = load()?;
+    entries.push(entry);
+    let skip = entries.len().saturating_sub(MAX_ENTRIES);
+    let mut contents = String::new();
+    for entry in &entries[skip..] {
+        contents.push_str(&entry.to_line());
+        contents.push('\n');
+    }
+    paths::create_parent(&path)?;
+    std::fs::write(&path, contents)?;
+    Ok(())
+}
+
+/// Writes the `limit` newest entries, newest first, or the geometry of the
+/// `pick`th newest one.
+pub fn show(output: &mut dyn Write, json: bool, limit: Option<usize>, pick: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = load()?;
+    let newest = entries.iter().rev();
+    if let Some(n) = pick {
+        let entry = newest.clone().nth(n - 1).ok_or_else(|| format!("history has only {} entries", entries.len()))?;
+        writeln!(output, "{}", entry.geometry())?;
+        return Ok(());
+    }
+    let shown = newest.take(limit.unwrap_or(usize::MAX));
+    if json {
+        let list: Vec<_> = shown
+            .map(|entry| {
+                let Rect { x, y, width, height } = entry.rect;
+                json!({
+                    "time": entry.time,
+                    "x": x, "y": y, "width": width, "height": height,
+                    "output": Some(&entry.output).filter(|o| !o.is_empty()),
+                    "method": entry.method,
+                })
+            })
+            .collect();
+        writeln!(output, "{}", serde_json::Value::Array(list))?;
+    } else {
+        for (i, entry) in shown.enumerate() {
+            let output_name = if entry.output.is_empty() { "-" } else { &entry.output };
+            writeln!(output, "{:>3}  {}  {:<20}  {:<10}  {}", i + 1, entry.time, entry.geometry(), output_name, entry.method)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+//! User commands run on selection events, configured in the `[hooks]`
+//! section. Commands go through `sh -c` and receive the selection in
+//! `GLIMPSE_X`, `GLIMPSE_Y`, `GLIMPSE_WIDTH` and `GLIMPSE_HEIGHT`, plus the
+//! saved file in `GLIMPSE_PATH` for `on_save`, and the changed pixels'
+//! share, as a percentage, in `GLIMPSE_CHANGED` for `on_change`.
+
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+
+use crate::selection::Rect;
+
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    pub on_success: Option<String>, // A selection was confirmed
+    pub on_cancel: Option<String>,  // The selection was cancelled
+    pub on_save: Option<String>,    // A capture was written, once per file
+    pub on_change: Option<String>,  // The region's content changed, with watch-changes
+}
+
+/// Runs `command` and waits for it. Hooks are best effort: failures are
+/// logged, never fatal.
+pub fn run(command: &Option<String>, rect: Option<Rect>, path: Option<&Path>) {
+    let path = path.map(|path| ("GLIMPSE_PATH", path.as_os_str().to_owned()));
+    run_with(command, rect, path.as_slice());
+}
+
+/// Runs `command` like `run`, with `env` added to its environment.
+pub fn run_with(command: &Option<String>, rect: Option<Rect>, env: &[(&str, OsString)]) {
+    let Some(command) = command else { return };
+    let mut child = Command::new("sh");
+    child.arg("-c").arg(command);
+    if let Some(Rect { x, y, width, height }) = rect {
+        child.env("GLIMPSE_X", x.to_string())
+            .env("GLIMPSE_Y", y.to_string())
+            .env("GLIMPSE_WIDTH", width.to_string())
+            .env("GLIMPSE_HEIGHT", height.to_string());
+    }
+    child.envs(env.iter().map(|(name, value)| (name, value)));
+    match child.status() {
+        Ok(status) if !status.success() => log::warn!("Hook '{command}' exited with {status}"),
+        Ok(_) => {}
+        Err(err) => log::warn!("Failed to run hook '{command}': {err}"),
+    }
+}
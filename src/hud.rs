@@ -0,0 +1,169 @@
+//! Placement of the labels drawn over the overlay.
+//!
+//! Each element can be hidden or anchored next to the pointer, at a corner
+//! of the output under the pointer, or at a corner inside or outside the
+//! selection. Elements sharing an anchor are stacked instead of overlapping.
+
+use crate::graphics::drawing::{Label, LabelStyle};
+use crate::graphics::text::Mask;
+use crate::process::{self, Position};
+use crate::selection::Rect;
+
+/// Space between a label and the selection edge it is anchored to.
+const GAP: i32 = 6;
+
+/// Distance from the pointer, clear of the crosshair.
+const CURSOR_OFFSET: i32 = 16;
+
+/// Distance from the output's edges for labels in a screen corner.
+const SCREEN_MARGIN: i32 = 12;
+
+/// Space between labels stacked at the same anchor.
+const STACK_GAP: i32 = 4;
+
+/// What a label is placed next to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    Cursor,
+    Screen(Position),  // A corner of the output under the pointer
+    Inside(Position),  // A corner of the selection, or its center
+    Outside(Position), // Just past a corner of the selection
+}
+
+impl Anchor {
+    /// Whether further labels at this anchor stack upwards, away from a
+    /// bottom edge.
+    fn stacks_up(self) -> bool {
+        matches!(
+            self,
+            Anchor::Screen(Position::BottomLeft | Position::BottomRight)
+                | Anchor::Inside(Position::BottomLeft | Position::BottomRight)
+                | Anchor::Outside(Position::TopLeft | Position::TopRight)
+        )
+    }
+}
+
+/// Which elements are shown and where, from the `[hud]` section.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hud {
+    pub size: Option<Anchor>,        // The selection's width and height
+    pub coordinates: Option<Anchor>, // The pointer position, and the color under it with screencopy
+    pub hints: Option<Anchor>,       // How to confirm or cancel
+    pub readout: Option<Anchor>,     // Distances and contrast ratios
+    pub buttons: Option<Anchor>,     // Accept and Cancel, while editing
+    pub toolbar: Option<Anchor>,     // Mode and action buttons, shown from the start
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Self {
+            size: None,
+            coordinates: None,
+            hints: None,
+            readout: Some(Anchor::Cursor),
+            buttons: None,
+            toolbar: None,
+        }
+    }
+}
+
+impl Hud {
+    /// Whether any label is shown outside of measuring and picking.
+    pub fn shows_labels(&self) -> bool {
+        self.size.is_some() || self.coordinates.is_some() || self.hints.is_some() || self.buttons.is_some() || self.toolbar.is_some()
+    }
+}
+
+/// Parses `none`, `cursor`, a screen corner such as `top-right`, or a
+/// selection corner prefixed with `inside-` or `outside-`. `None` hides the
+/// element.
+pub fn parse_anchor(spec: &str) -> Result<Option<Anchor>, String> {
+    let anchor = if spec == "none" {
+        return Ok(None);
+    } else if spec == "cursor" {
+        Anchor::Cursor
+    } else if let Some(corner) = spec.strip_prefix("inside-") {
+        Anchor::Inside(process::parse_position(corner)?)
+    } else if let Some(corner) = spec.strip_prefix("outside-") {
+        match process::parse_position(corner)? {
+            Position::Center => return Err(format!("invalid anchor '{spec}', the center is inside the selection")),
+            corner => Anchor::Outside(corner),
+        }
+    } else {
+        Anchor::Screen(process::parse_position(spec)?)
+    };
+    Ok(Some(anchor))
+}
+
+/// Places labels for one frame, keeping track of how much space each anchor
+/// has used up.
+pub struct Layout {
+    selection: Option<Rect>,
+    pointer: (i32, i32),
+    screen: Option<Rect>,
+    stacked: Vec<(Anchor, i32)>,
+}
+
+impl Layout {
+    pub fn new(selection: Option<Rect>, pointer: (i32, i32), screen: Option<Rect>) -> Self {
+        Self { selection, pointer, screen, stacked: Vec::new() }
+    }
+
+    /// A label showing `text` at `anchor`, or `None` if there is nothing to
+    /// anchor it to, e.g. no selection yet.
+    pub fn place(&mut self, anchor: Anchor, text: Mask) -> Option<Label> {
+        let label = Label { position: (0, 0), text, style: LabelStyle::DARK };
+        let position = self.reserve(anchor, label.rect())?;
+        Some(Label { position, ..label })
+    }
+
+    /// Position for a box of `size` at `anchor`, e.g. a row of labels, or
+    /// `None` if there is nothing to anchor it to.
+    pub fn reserve(&mut self, anchor: Anchor, size: Rect) -> Option<(i32, i32)> {
+        let (x, y) = match anchor {
+            Anchor::Cursor => (self.pointer.0 + CURSOR_OFFSET, self.pointer.1 + CURSOR_OFFSET),
+            Anchor::Screen(corner) => inside(self.screen?, corner, size, SCREEN_MARGIN),
+            Anchor::Inside(corner) => inside(self.selection?, corner, size, GAP),
+            Anchor::Outside(corner) => outside(self.selection?, corner, size),
+        };
+        let index = match self.stacked.iter().position(|&(a, _)| a == anchor) {
+            Some(index) => index,
+            None => {
+                self.stacked.push((anchor, 0));
+                self.stacked.len() - 1
+            }
+        };
+        let offset = &mut self.stacked[index].1;
+        let y = if anchor.stacks_up() { y - *offset } else { y + *offset };
+        *offset += size.height + STACK_GAP;
+        Some((x, y))
+    }
+}
+
+/// Position of `size` in a corner of `rect`, `margin` from its edges.
+fn inside(rect: Rect, corner: Position, size: Rect, margin: i32) -> (i32, i32) {
+    let left = rect.x + margin;
+    let right = rect.right() - margin - size.width;
+    let top = rect.y + margin;
+    let bottom = rect.bottom() - margin - size.height;
+    match corner {
+        Position::TopLeft => (left, top),
+        Position::TopRight => (right, top),
+        Position::BottomLeft => (left, bottom),
+        Position::BottomRight => (right, bottom),
+        Position::Center => (rect.x + (rect.width - size.width) / 2, rect.y + (rect.height - size.height) / 2),
+    }
+}
+
+/// Position of `size` above or below a corner of `rect`, aligned with its
+/// left or right edge.
+fn outside(rect: Rect, corner: Position, size: Rect) -> (i32, i32) {
+    let above = rect.y - size.height - GAP;
+    let below = rect.bottom() + GAP;
+    match corner {
+        Position::TopLeft => (rect.x, above),
+        Position::TopRight => (rect.right() - size.width, above),
+        Position::BottomLeft | Position::Center => (rect.x, below),
+        Position::BottomRight => (rect.right() - size.width, below),
+    }
+}
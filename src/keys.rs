@@ -0,0 +1,168 @@
+//! Keyboard bindings.
+//!
+//! Keys are identified by their Linux input event codes, i.e. by physical
+//! position on a US layout, since the overlay does not load an xkb keymap.
+
+pub const KEY_ESC: u32 = 1;
+pub const KEY_1: u32 = 2; // Through KEY_9 = 10
+pub const KEY_TAB: u32 = 15;
+pub const KEY_ENTER: u32 = 28;
+pub const KEY_KPENTER: u32 = 96;
+pub const KEY_O: u32 = 24;
+pub const KEY_R: u32 = 19;
+pub const KEY_T: u32 = 20;
+pub const KEY_X: u32 = 45;
+pub const KEY_H: u32 = 35;
+pub const KEY_J: u32 = 36;
+pub const KEY_K: u32 = 37;
+pub const KEY_L: u32 = 38;
+pub const KEY_UP: u32 = 103;
+pub const KEY_LEFT: u32 = 105;
+pub const KEY_RIGHT: u32 = 106;
+pub const KEY_DOWN: u32 = 108;
+
+const KEY_NAMES: &[(&str, u32)] = &[
+    ("escape", KEY_ESC), ("esc", KEY_ESC), ("return", KEY_ENTER), ("enter", KEY_ENTER),
+    ("kp_enter", KEY_KPENTER), ("tab", KEY_TAB), ("space", 57), ("backspace", 14),
+    ("left", KEY_LEFT), ("right", KEY_RIGHT), ("up", KEY_UP), ("down", KEY_DOWN),
+    ("1", 2), ("2", 3), ("3", 4), ("4", 5), ("5", 6), ("6", 7), ("7", 8), ("8", 9), ("9", 10), ("0", 11),
+    ("q", 16), ("w", 17), ("e", 18), ("r", KEY_R), ("t", KEY_T), ("y", 21), ("u", 22), ("i", 23), ("o", KEY_O), ("p", 25),
+    ("a", 30), ("s", 31), ("d", 32), ("f", 33), ("g", 34), ("h", KEY_H), ("j", KEY_J), ("k", KEY_K), ("l", KEY_L),
+    ("z", 44), ("x", KEY_X), ("c", 46), ("v", 47), ("b", 48), ("n", 49), ("m", 50),
+];
+
+// Modifier flags
+pub const MOD_SHIFT: u8 = 1 << 0;
+pub const MOD_CTRL: u8 = 1 << 1;
+pub const MOD_ALT: u8 = 1 << 2;
+
+/// Converts a wl_keyboard modifier mask into `MOD_*` flags, assuming the
+/// standard xkb modifier indices (Shift = 0, Control = 2, Mod1 = 3).
+pub fn modifiers_from_mask(mask: u32) -> u8 {
+    let mut mods = 0;
+    if mask & (1 << 0) != 0 {
+        mods |= MOD_SHIFT;
+    }
+    if mask & (1 << 2) != 0 {
+        mods |= MOD_CTRL;
+    }
+    if mask & (1 << 3) != 0 {
+        mods |= MOD_ALT;
+    }
+    mods
+}
+
+/// What a key does when pressed. Directions are unit steps, scaled by the
+/// step size when applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyAction {
+    Cancel,
+    Confirm,
+    Move(i32, i32),   // Move the selection
+    Resize(i32, i32), // Grow or shrink the selection from its bottom-right corner
+    CycleHandle(i32), // Pick the edge or corner that moves, this many handles clockwise
+    Preset(usize),    // Select the configured region with this index, from 0
+    Peek,             // Hide the overlay while held
+    SwapAnchor,       // Make the corner following the pointer the fixed one during a drag
+    Retry,            // Select again from the capture preview
+    Toolbar,          // Show or hide the mode toolbar
+}
+
+/// A key together with the modifiers that must be held.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyCombo {
+    pub key: u32,
+    pub mods: u8,
+}
+
+impl KeyCombo {
+    const fn new(key: u32, mods: u8) -> Self {
+        Self { key, mods }
+    }
+}
+
+pub fn default_key_bindings() -> Vec<(KeyCombo, KeyAction)> {
+    let mut bindings = vec![
+        (KeyCombo::new(KEY_ESC, 0), KeyAction::Cancel),
+        (KeyCombo::new(KEY_ENTER, 0), KeyAction::Confirm),
+        (KeyCombo::new(KEY_KPENTER, 0), KeyAction::Confirm),
+        (KeyCombo::new(KEY_TAB, 0), KeyAction::CycleHandle(1)),
+        (KeyCombo::new(KEY_TAB, MOD_SHIFT), KeyAction::CycleHandle(-1)),
+    ];
+    // Arrows and vim keys move; with Shift (or Ctrl) they resize
+    let directions = [
+        (KEY_LEFT, KEY_H, (-1, 0)),
+        (KEY_DOWN, KEY_J, (0, 1)),
+        (KEY_UP, KEY_K, (0, -1)),
+        (KEY_RIGHT, KEY_L, (1, 0)),
+    ];
+    for (arrow, vim, (dx, dy)) in directions {
+        for key in [arrow, vim] {
+            bindings.push((KeyCombo::new(key, 0), KeyAction::Move(dx, dy)));
+            bindings.push((KeyCombo::new(key, MOD_SHIFT), KeyAction::Resize(dx, dy)));
+            bindings.push((KeyCombo::new(key, MOD_CTRL), KeyAction::Resize(dx, dy)));
+        }
+    }
+    bindings.push((KeyCombo::new(KEY_X, 0), KeyAction::Peek));
+    bindings.push((KeyCombo::new(KEY_O, 0), KeyAction::SwapAnchor));
+    bindings.push((KeyCombo::new(KEY_R, 0), KeyAction::Retry));
+    bindings.push((KeyCombo::new(KEY_T, 0), KeyAction::Toolbar));
+    // Number keys pick the presets
+    for index in 0..9 {
+        bindings.push((KeyCombo::new(KEY_1 + index as u32, 0), KeyAction::Preset(index)));
+    }
+    bindings
+}
+
+/// Parses a combo such as `l`, `shift+l` or `ctrl+left`.
+pub fn parse_combo(spec: &str) -> Result<KeyCombo, String> {
+    let mut mods = 0;
+    let mut parts: Vec<&str> = spec.split('+').collect();
+    let key = parts.pop().unwrap_or_default();
+    for part in parts {
+        mods |= match part.to_ascii_lowercase().as_str() {
+            "shift" => MOD_SHIFT,
+            "ctrl" | "control" => MOD_CTRL,
+            "alt" => MOD_ALT,
+            other => return Err(format!("unknown modifier '{other}'")),
+        };
+    }
+    // A lone uppercase letter implies Shift, so `L` means `shift+l`
+    if key.len() == 1 && key.chars().all(|c| c.is_ascii_uppercase()) {
+        mods |= MOD_SHIFT;
+    }
+    let name = key.to_ascii_lowercase();
+    let code = KEY_NAMES.iter()
+        .find(|&&(n, _)| n == name)
+        .map(|&(_, code)| code)
+        .ok_or_else(|| format!("unknown key '{key}'"))?;
+    Ok(KeyCombo::new(code, mods))
+}
+
+/// Parses an action name; `none` unbinds the key.
+pub fn parse_action(name: &str) -> Result<Option<KeyAction>, String> {
+    Ok(Some(match name {
+        "cancel" => KeyAction::Cancel,
+        "confirm" => KeyAction::Confirm,
+        "move-left" => KeyAction::Move(-1, 0),
+        "move-right" => KeyAction::Move(1, 0),
+        "move-up" => KeyAction::Move(0, -1),
+        "move-down" => KeyAction::Move(0, 1),
+        "shrink-width" => KeyAction::Resize(-1, 0),
+        "grow-width" => KeyAction::Resize(1, 0),
+        "shrink-height" => KeyAction::Resize(0, -1),
+        "grow-height" => KeyAction::Resize(0, 1),
+        "next-handle" => KeyAction::CycleHandle(1),
+        "previous-handle" => KeyAction::CycleHandle(-1),
+        "peek" => KeyAction::Peek,
+        "swap-anchor" => KeyAction::SwapAnchor,
+        "retry" => KeyAction::Retry,
+        "toolbar" => KeyAction::Toolbar,
+        "none" => return Ok(None),
+        _ if name.starts_with("preset-") => match name["preset-".len()..].parse() {
+            Ok(number @ 1..=9) => KeyAction::Preset(number - 1),
+            _ => return Err(format!("invalid preset '{name}', expected preset-1 to preset-9")),
+        },
+        _ => return Err(format!("unknown key action '{name}'")),
+    }))
+}
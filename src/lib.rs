@@ -0,0 +1,61 @@
+//! A layer shell application that creates a full-screen, semi-transparent
+//! overlay on every output for selecting a region, built using wayland-client
+//! directly. The program prints the region (or captures it) and exits once a
+//! region is selected.
+//!
+//! The same selector is a library for other programs: `select_region`
+//! shows the overlay and returns the confirmed `Rect`.
+//!
+//! Dependencies are listed in Cargo.toml, where `[features]` names the
+//! optional ones and what each enables.
+
+mod analysis;
+mod announce;
+mod app;
+#[cfg(feature = "capi")]
+mod capi;
+mod capture;
+mod check;
+mod cli;
+mod clipboard;
+mod compositor;
+mod config;
+mod config_file;
+mod contrast;
+mod daemon;
+mod driver;
+mod editor;
+mod encode;
+mod geometry;
+mod graphics;
+mod history;
+mod hooks;
+mod hud;
+mod keys;
+mod measure;
+mod notify;
+mod output;
+mod paths;
+mod pipeline;
+mod preview;
+mod process;
+mod record;
+mod recording;
+mod rpc;
+mod s3;
+mod script;
+mod select;
+mod selection;
+mod sinks;
+mod snap;
+mod sounds;
+mod trace;
+mod upload;
+mod wayland;
+mod windows;
+
+pub use cli::run;
+pub use select::{select_region, Options};
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+pub use select::select_region_async;
+pub use selection::Rect;
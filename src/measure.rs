@@ -0,0 +1,46 @@
+//! Distance measurement for `glimpse measure`.
+
+use std::fmt;
+
+use crate::output::Output;
+
+/// A segment between two points in global logical coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub dx: i32,
+    pub dy: i32,
+    pub length: f64,            // Logical pixels
+    pub length_mm: Option<f64>, // Using the output the segment starts on
+}
+
+impl Measurement {
+    pub fn new(from: (i32, i32), to: (i32, i32), outputs: &[Output]) -> Self {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length = ((dx as f64).powi(2) + (dy as f64).powi(2)).sqrt();
+        let length_mm = outputs.iter()
+            .find(|output| output.contains(from))
+            .and_then(Output::mm_per_logical_px)
+            .map(|mm| length * mm);
+        Self { dx, dy, length, length_mm }
+    }
+
+    /// Short text for the overlay label.
+    pub fn label(&self) -> String {
+        let mut label = format!("{:.1} px  ({} × {})", self.length, self.dx, self.dy);
+        if let Some(mm) = self.length_mm {
+            label += &format!("  {mm:.1} mm");
+        }
+        label
+    }
+}
+
+/// Machine-readable form printed on exit, e.g. `dx=100 dy=-72 px=123.2 mm=32.6`.
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "dx={} dy={} px={:.1}", self.dx, self.dy, self.length)?;
+        if let Some(mm) = self.length_mm {
+            write!(f, " mm={mm:.1}")?;
+        }
+        Ok(())
+    }
+}
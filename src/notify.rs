@@ -0,0 +1,32 @@
+//! `--notify`: a desktop notification through notify-send once the result
+//! is delivered, for hotkeys that give no other sign of it.
+
+use std::process::{Command, Stdio};
+
+use crate::config::Config;
+use crate::selection::Rect;
+
+/// Says what became of `rect`, without waiting for the notification to show.
+pub fn delivered(config: &Config, rect: Rect) {
+    let what = match (config.save, config.copy) {
+        (true, true) => "Saved and copied",
+        (true, false) => "Saved",
+        (false, true) => "Copied",
+        (false, false) if config.capture => "Captured",
+        (false, false) => "Selected",
+    };
+    let body = format!("{what} {}×{} at {}, {}", rect.width, rect.height, rect.x, rect.y);
+    // Backgrounded by the shell so it is never waited for
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg("notify-send --app-name Glimpse Glimpse \"$1\" &")
+        .arg("sh")
+        .arg(&body)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    if let Err(err) = status {
+        log::warn!("Failed to send a notification: {err}");
+    }
+}
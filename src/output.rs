@@ -0,0 +1,186 @@
+//! Output (monitor) bookkeeping.
+//!
+//! Every `wl_output` advertised by the compositor is tracked here together with
+//! its optional `zxdg_output_v1` companion, so that surface-local pointer
+//! coordinates can be mapped into the compositor's global (logical) space.
+
+use std::io::Write;
+
+use serde_json::json;
+use wayland_client::protocol::wl_output::{self, Transform};
+use wayland_protocols::xdg::xdg_output::zv1::client::zxdg_output_v1::ZxdgOutputV1;
+
+use crate::selection::Rect;
+
+#[derive(Debug)]
+pub struct Output {
+    pub registry_name: u32,
+    pub wl_output: wl_output::WlOutput,
+    pub xdg_output: Option<ZxdgOutputV1>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+
+    // As advertised by wl_output
+    pub position: (i32, i32),
+    pub physical_size_mm: (i32, i32),
+    pub mode_size: (i32, i32), // Hardware pixels, before the transform is applied
+    pub refresh_mhz: i32,
+    pub transform: Transform,
+    pub scale: i32,
+
+    // As advertised by zxdg_output_v1, if bound
+    pub logical_position: Option<(i32, i32)>,
+    pub logical_size: Option<(i32, i32)>,
+}
+
+impl Output {
+    pub fn new(registry_name: u32, wl_output: wl_output::WlOutput) -> Self {
+        Self {
+            registry_name,
+            wl_output,
+            xdg_output: None,
+            name: None,
+            description: None,
+            position: (0, 0),
+            physical_size_mm: (0, 0),
+            mode_size: (0, 0),
+            refresh_mhz: 0,
+            transform: Transform::Normal,
+            scale: 1,
+            logical_position: None,
+            logical_size: None,
+        }
+    }
+
+    /// Size of the output in hardware pixels once its transform is applied,
+    /// i.e. with width and height swapped for portrait (90/270) rotations.
+    pub fn transformed_size(&self) -> (i32, i32) {
+        let (w, h) = self.mode_size;
+        if transform_swaps_axes(self.transform) {
+            (h, w)
+        } else {
+            (w, h)
+        }
+    }
+
+    /// Logical geometry `(x, y, width, height)` of the output in the global
+    /// compositor space. Prefers xdg-output, falling back to deriving it from
+    /// the mode, transform and integer scale.
+    pub fn logical_geometry(&self) -> (i32, i32, i32, i32) {
+        let (x, y) = self.logical_position.unwrap_or(self.position);
+        let (w, h) = self.logical_size.unwrap_or_else(|| {
+            let (w, h) = self.transformed_size();
+            let scale = self.scale.max(1);
+            (w / scale, h / scale)
+        });
+        (x, y, w, h)
+    }
+
+    /// Hardware pixels per logical pixel, which may be fractional.
+    pub fn fractional_scale(&self) -> f64 {
+        let (_, _, width, _) = self.logical_geometry();
+        self.transformed_size().0 as f64 / width.max(1) as f64
+    }
+
+    /// Physical width of a logical pixel in millimeters, if the output
+    /// reports its dimensions (projectors and virtual outputs often don't).
+    pub fn mm_per_logical_px(&self) -> Option<f64> {
+        let (width_mm, height_mm) = self.physical_size_mm;
+        let width_mm = if transform_swaps_axes(self.transform) { height_mm } else { width_mm };
+        let (_, _, width, _) = self.logical_geometry();
+        (width_mm > 0 && width > 0).then(|| width_mm as f64 / width as f64)
+    }
+
+    /// Whether a point in global logical coordinates lies on this output.
+    pub fn contains(&self, (px, py): (i32, i32)) -> bool {
+        let (x, y, width, height) = self.logical_geometry();
+        px >= x && py >= y && px < x + width && py < y + height
+    }
+
+    /// Maps surface-local coordinates of a surface covering this output into
+    /// global logical coordinates.
+    pub fn to_global(&self, local: (i32, i32)) -> (i32, i32) {
+        let (x, y, _, _) = self.logical_geometry();
+        (x + local.0, y + local.1)
+    }
+}
+
+/// The output covering most of `rect`.
+pub fn containing<'a>(outputs: &'a [Output], rect: &Rect) -> Option<&'a Output> {
+    outputs.iter()
+        .filter_map(|output| {
+            let (x, y, width, height) = output.logical_geometry();
+            let overlap = rect.intersection(&Rect::new(x, y, width, height))?;
+            Some((output, overlap.width as i64 * overlap.height as i64))
+        })
+        .max_by_key(|&(_, area)| area)
+        .map(|(output, _)| output)
+}
+
+/// The smallest rectangle holding every output, in global logical
+/// coordinates.
+pub fn bounds(outputs: &[Output]) -> Option<Rect> {
+    outputs.iter()
+        .map(|output| {
+            let (x, y, width, height) = output.logical_geometry();
+            Rect::new(x, y, width, height)
+        })
+        .reduce(|a, b| Rect::from_corners((a.x.min(b.x), a.y.min(b.y)), (a.right().max(b.right()), a.bottom().max(b.bottom()))))
+}
+
+/// Whether `transform` rotates the output by 90 or 270 degrees.
+pub fn transform_swaps_axes(transform: Transform) -> bool {
+    matches!(
+        transform,
+        Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270
+    )
+}
+
+fn transform_name(transform: Transform) -> &'static str {
+    match transform {
+        Transform::_90 => "90",
+        Transform::_180 => "180",
+        Transform::_270 => "270",
+        Transform::Flipped => "flipped",
+        Transform::Flipped90 => "flipped-90",
+        Transform::Flipped180 => "flipped-180",
+        Transform::Flipped270 => "flipped-270",
+        _ => "normal",
+    }
+}
+
+/// Prints every output with its logical geometry, scale, transform and
+/// refresh rate, one per line or as a JSON array.
+pub fn list(out: &mut dyn Write, outputs: &[Output], json: bool) -> std::io::Result<()> {
+    if json {
+        let list: Vec<_> = outputs.iter()
+            .map(|output| {
+                let (x, y, width, height) = output.logical_geometry();
+                let (pixel_width, pixel_height) = output.transformed_size();
+                json!({
+                    "name": output.name,
+                    "description": output.description,
+                    "x": x, "y": y, "width": width, "height": height,
+                    "pixel_width": pixel_width, "pixel_height": pixel_height,
+                    "scale": output.fractional_scale(),
+                    "transform": transform_name(output.transform),
+                    "refresh": output.refresh_mhz as f64 / 1000.0,
+                })
+            })
+            .collect();
+        return writeln!(out, "{}", serde_json::Value::Array(list));
+    }
+    for output in outputs {
+        let (x, y, width, height) = output.logical_geometry();
+        writeln!(
+            out,
+            "{:<10}  {:<20}  scale {:<5}  transform {:<11}  {:.2} Hz",
+            output.name.as_deref().unwrap_or("-"),
+            format!("{x},{y} {width}x{height}"),
+            format!("{:.2}", output.fractional_scale()).trim_end_matches('0').trim_end_matches('.'),
+            transform_name(output.transform),
+            output.refresh_mhz as f64 / 1000.0,
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! Well-known directories, following the XDG base directory spec: the
+//! configuration under `$XDG_CONFIG_HOME/glimpse`, the history under
+//! `$XDG_STATE_HOME/glimpse` and caches under `$XDG_CACHE_HOME/glimpse`.
+
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").filter(|h| !h.is_empty()).map(PathBuf::from)
+}
+
+/// The directory in `var`, falling back to `default` under the home
+/// directory. Relative paths are ignored, as the spec asks.
+fn base_dir(var: &str, default: &[&str]) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home_dir().map(|home| default.iter().fold(home, |dir, part| dir.join(part))))
+}
+
+/// `$XDG_CONFIG_HOME`, falling back to `~/.config`.
+fn config_home() -> Option<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", &[".config"])
+}
+
+/// `$XDG_STATE_HOME`, falling back to `~/.local/state`.
+fn state_home() -> Option<PathBuf> {
+    base_dir("XDG_STATE_HOME", &[".local", "state"])
+}
+
+/// `$XDG_CACHE_HOME`, falling back to `~/.cache`.
+fn cache_home() -> Option<PathBuf> {
+    base_dir("XDG_CACHE_HOME", &[".cache"])
+}
+
+/// Default location of the configuration file.
+pub fn config_file() -> Option<PathBuf> {
+    config_home().map(|dir| dir.join("glimpse").join("config"))
+}
+
+/// Default Lua script, used when Glimpse is built with Lua support.
+pub fn script_file() -> Option<PathBuf> {
+    config_home().map(|dir| dir.join("glimpse").join("init.lua"))
+}
+
+/// Where confirmed selections are recorded.
+pub fn history_file() -> Option<PathBuf> {
+    state_home().map(|dir| dir.join("glimpse").join("history"))
+}
+
+/// Where the files fontconfig picked for each family are remembered.
+pub fn font_cache_file() -> Option<PathBuf> {
+    cache_home().map(|dir| dir.join("glimpse").join("fonts"))
+}
+
+/// Where fontconfig keeps its own caches, which it rewrites when fonts are
+/// added or removed.
+pub fn fontconfig_cache_dirs() -> Vec<PathBuf> {
+    cache_home().map(|dir| dir.join("fontconfig")).into_iter().chain([PathBuf::from("/var/cache/fontconfig")]).collect()
+}
+
+/// The socket `glimpse daemon` listens on, one per Wayland display.
+pub fn daemon_socket() -> Option<PathBuf> {
+    let runtime = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).filter(|dir| dir.is_absolute())?;
+    let display = std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".into());
+    // WAYLAND_DISPLAY may be a path of its own
+    let display = Path::new(&display).file_name()?.to_string_lossy().into_owned();
+    Some(runtime.join(format!("glimpse-{display}.sock")))
+}
+
+/// Creates the directory `file` goes in, with any missing parents. New
+/// directories are private to the user, as the spec asks.
+pub fn create_parent(file: &Path) -> std::io::Result<()> {
+    match file.parent() {
+        Some(dir) => std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir),
+        None => Ok(()),
+    }
+}
+
+/// Expands a leading `~/` to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// `$XDG_PICTURES_DIR`, read from the environment or `user-dirs.dirs`, falling
+/// back to `~/Pictures`.
+pub fn pictures_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_PICTURES_DIR").map(PathBuf::from).filter(|dir| dir.is_absolute()) {
+        return Some(dir);
+    }
+    let home = home_dir()?;
+    let config_home = config_home().unwrap_or_else(|| home.join(".config"));
+    if let Ok(contents) = std::fs::read_to_string(config_home.join("user-dirs.dirs")) {
+        for line in contents.lines() {
+            let Some(value) = line.trim().strip_prefix("XDG_PICTURES_DIR=") else { continue };
+            let value = value.trim_matches('"');
+            let dir = match value.strip_prefix("$HOME") {
+                Some(rest) => home.join(rest.trim_start_matches('/')),
+                None => PathBuf::from(value),
+            };
+            return Some(dir);
+        }
+    }
+    Some(home.join("Pictures"))
+}
+
+/// Directory `--save` writes screenshots to.
+pub fn screenshots_dir() -> Option<PathBuf> {
+    pictures_dir().map(|dir| dir.join("Screenshots"))
+}
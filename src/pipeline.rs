@@ -0,0 +1,163 @@
+//! Named pipelines from the `[pipelines]` section, run with `glimpse run
+//! NAME`. A pipeline lists stages separated by `->`, starting with where
+//! the region comes from:
+//!
+//! ```text
+//! [pipelines]
+//! shot = select -> edit -> save -> copy -> notify
+//! later = window -> delay 3s -> save
+//! ```
+//!
+//! Each stage turns on the option of the same name, so a pipeline is a
+//! saved set of flags checked like any other: `preview`, for one, only
+//! follows `select`.
+
+use std::fmt;
+use std::mem;
+use std::time::Duration;
+
+use crate::config::{self, Command, Config};
+
+/// One step of a pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stage {
+    Select, // Where the region comes from
+    Window,
+    Output,
+    Replay,               // The last confirmed selection, from the history
+    Delay(Duration),      // --delay
+    Preview,              // --confirm-preview
+    Edit(Option<String>), // --edit, with an optional editor command
+    Capture,              // --capture
+    Save,
+    Copy,
+    Upload,               // --upload, to the host under [upload]
+    S3,                   // --s3, to the bucket under [s3]
+    Notify,               // --notify
+}
+
+impl Stage {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let (name, argument) = match spec.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim())),
+            None => (spec, None),
+        };
+        let stage = match name {
+            "select" => Stage::Select,
+            "window" => Stage::Window,
+            "output" => Stage::Output,
+            "replay" => Stage::Replay,
+            "delay" => Stage::Delay(config::parse_duration(argument.ok_or("delay needs a duration, e.g. 'delay 3s'")?)?),
+            "preview" => Stage::Preview,
+            "edit" => return Ok(Stage::Edit(argument.map(str::to_string))),
+            "capture" => Stage::Capture,
+            "save" => Stage::Save,
+            "copy" => Stage::Copy,
+            "upload" => Stage::Upload,
+            "s3" => Stage::S3,
+            "notify" => Stage::Notify,
+            "" => return Err("empty stage".into()),
+            _ => return Err(format!("unknown stage '{name}', expected select, window, output, replay, delay, preview, edit, capture, save, copy, upload, s3 or notify")),
+        };
+        match (&stage, argument) {
+            (Stage::Delay(_), _) | (_, None) => Ok(stage),
+            (_, Some(_)) => Err(format!("stage '{name}' takes no argument")),
+        }
+    }
+
+    /// Where the stage runs, as the stages are always carried out in the
+    /// same order: the region, the wait, the preview, the editor, the
+    /// outputs and the notification.
+    fn step(&self) -> u8 {
+        match self {
+            Stage::Select | Stage::Window | Stage::Output | Stage::Replay => 0,
+            Stage::Delay(_) => 1,
+            Stage::Preview => 2,
+            Stage::Edit(_) => 3,
+            Stage::Capture | Stage::Save | Stage::Copy | Stage::Upload | Stage::S3 => 4,
+            Stage::Notify => 5,
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Stage::Select => "select",
+            Stage::Window => "window",
+            Stage::Output => "output",
+            Stage::Replay => "replay",
+            Stage::Delay(_) => "delay",
+            Stage::Preview => "preview",
+            Stage::Edit(_) => "edit",
+            Stage::Capture => "capture",
+            Stage::Save => "save",
+            Stage::Copy => "copy",
+            Stage::Upload => "upload",
+            Stage::S3 => "s3",
+            Stage::Notify => "notify",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Parses `select -> save -> copy`, checking that the region comes first
+/// and the rest are listed in the order they run, each once.
+pub fn parse(spec: &str) -> Result<Vec<Stage>, String> {
+    let stages = spec.split("->").map(|stage| Stage::parse(stage.trim())).collect::<Result<Vec<_>, _>>()?;
+    match stages.first() {
+        Some(first) if first.step() == 0 => {}
+        Some(first) => return Err(format!("a pipeline starts with select, window, output or replay, not '{first}'")),
+        None => return Err("empty pipeline".into()),
+    }
+    for (index, pair) in stages.windows(2).enumerate() {
+        let (before, stage) = (&pair[0], &pair[1]);
+        if stages[..=index].iter().any(|other| mem::discriminant(other) == mem::discriminant(stage)) {
+            return Err(format!("stage '{stage}' appears twice"));
+        }
+        if stage.step() < before.step() || stage.step() == 0 {
+            return Err(format!("stage '{stage}' can't come after '{before}'"));
+        }
+    }
+    Ok(stages)
+}
+
+/// Turns on the options for `stages` and returns the command their region
+/// comes from.
+pub fn apply(stages: &[Stage], config: &mut Config) -> Command {
+    let mut command = Command::Select;
+    for stage in stages {
+        match stage {
+            Stage::Select => command = Command::Select,
+            Stage::Window => command = Command::Window,
+            Stage::Output => command = Command::Output,
+            Stage::Replay => command = Command::Replay,
+            Stage::Delay(delay) => config.delay = Some(*delay),
+            Stage::Preview => config.confirm_preview = true,
+            Stage::Edit(editor) => {
+                config.capture = true;
+                config.edit = true;
+                config.editor = editor.clone();
+            }
+            Stage::Capture => config.capture = true,
+            Stage::Save => {
+                config.capture = true;
+                config.save = true;
+            }
+            Stage::Copy => {
+                config.capture = true;
+                config.copy = true;
+            }
+            Stage::Upload => {
+                config.capture = true;
+                config.upload = Some(config.upload_settings.clone());
+            }
+            Stage::S3 => {
+                config.capture = true;
+                config.s3 = Some(config.s3_settings.clone());
+            }
+            Stage::Notify => config.notify = true,
+        }
+    }
+    command
+}
@@ -0,0 +1,156 @@
+//! `--confirm-preview`: a thumbnail of the capture in a corner of the
+//! overlay, with Save, Retry and Cancel buttons, shown before anything is
+//! written so a bad grab can be redone.
+
+use std::sync::Arc;
+
+use image::{imageops, RgbaImage};
+use wayland_client::EventQueue;
+
+use crate::app::{AppState, SelectionState};
+use crate::graphics::drawing::{Label, LabelStyle, Picture};
+use crate::graphics::text::{TextRenderer, TEXT_SIZE};
+use crate::output;
+use crate::selection::Rect;
+
+/// Largest thumbnail, in logical pixels.
+const THUMBNAIL_SIZE: (u32, u32) = (320, 240);
+
+/// Distance from the output's edges.
+const MARGIN: i32 = 24;
+
+/// Space between the thumbnail and the buttons, and between buttons.
+const GAP: i32 = 8;
+
+/// What the user picked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Choice {
+    Save,
+    Retry,
+    Cancel,
+}
+
+impl Choice {
+    fn label(self) -> &'static str {
+        match self {
+            Choice::Save => "Save (Enter)",
+            Choice::Retry => "Retry (R)",
+            Choice::Cancel => "Cancel (Esc)",
+        }
+    }
+}
+
+pub struct Preview {
+    thumbnail: Picture,
+    rect: Rect, // The captured selection, placing the thumbnail on its output
+    pub choice: Option<Choice>,
+}
+
+impl Preview {
+    pub fn new(image: &RgbaImage, rect: Rect) -> Self {
+        let (width, height) = image.dimensions();
+        let fit = (THUMBNAIL_SIZE.0 as f64 / width as f64).min(THUMBNAIL_SIZE.1 as f64 / height as f64).min(1.0);
+        let size = |side: u32| ((side as f64 * fit).round() as u32).max(1);
+        let thumbnail = imageops::thumbnail(image, size(width), size(height));
+        // Opaque, so premultiplying changes nothing
+        let pixels = thumbnail.pixels().flat_map(|p| [p[2], p[1], p[0], 0xFF]).collect();
+        Self {
+            thumbnail: Picture {
+                position: (0, 0),
+                width: thumbnail.width(),
+                height: thumbnail.height(),
+                pixels: Arc::new(pixels),
+            },
+            rect,
+            choice: None,
+        }
+    }
+
+    /// The thumbnail and buttons in the bottom-right corner of the output
+    /// holding the selection, in global logical coordinates. Without a font
+    /// there are no buttons and the keys or a click on the thumbnail decide.
+    pub fn layout(&self, state: &AppState) -> (Picture, Vec<(Label, Choice)>) {
+        let screen = output::containing(&state.outputs, &self.rect).map_or(self.rect, |output| {
+            let (x, y, width, height) = output.logical_geometry();
+            Rect::new(x, y, width, height)
+        });
+        let mut buttons: Vec<_> = state.text.iter()
+            .flat_map(|text| [Choice::Save, Choice::Retry, Choice::Cancel].map(|choice| {
+                let mask = text.render(choice.label(), TEXT_SIZE);
+                (Label { position: (0, 0), text: mask, style: LabelStyle::DARK.rounded(6) }, choice)
+            }))
+            .collect();
+        let buttons_height = buttons.iter().map(|(label, _)| label.rect().height + GAP).max().unwrap_or(0);
+        let (width, height) = (self.thumbnail.width as i32, self.thumbnail.height as i32);
+        let position = (screen.right() - MARGIN - width, screen.bottom() - MARGIN - buttons_height - height);
+        // Right-aligned under the thumbnail, Save nearest the middle
+        let mut x = screen.right() - MARGIN;
+        for (label, _) in buttons.iter_mut().rev() {
+            x -= label.rect().width;
+            label.position = (x, position.1 + height + GAP);
+            x -= GAP;
+        }
+        (Picture { position, ..self.thumbnail.clone() }, buttons)
+    }
+
+    /// What a click at `point` picks, if anything.
+    pub fn choice_at(&self, state: &AppState, (x, y): (i32, i32)) -> Option<Choice> {
+        let (thumbnail, buttons) = self.layout(state);
+        let hit = |rect: Rect| rect.intersection(&Rect::new(x, y, 1, 1)).is_some();
+        buttons.iter()
+            .find(|(label, _)| hit(label.rect()))
+            .map(|&(_, choice)| choice)
+            .or(hit(thumbnail.rect()).then_some(Choice::Save))
+    }
+}
+
+/// Shows `image` over the screen until the user saves, retries or cancels.
+pub fn show(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    image: &RgbaImage,
+    rect: Rect,
+) -> Result<Choice, Box<dyn std::error::Error>> {
+    let qh = event_queue.handle();
+    if state.text.is_none() {
+        state.text = TextRenderer::load(state.config.font.as_deref());
+    }
+    state.preview = Some(Preview::new(image, rect));
+    state.selection_state = SelectionState::Idle;
+    state.running = true;
+    state.create_overlays(&qh);
+    eprintln!("Press Enter to save, R to retry or Esc to cancel.");
+    run(state, event_queue)?;
+    state.close_overlays();
+    Ok(state.preview.take().and_then(|preview| preview.choice).unwrap_or(Choice::Cancel))
+}
+
+/// Shows the overlays again with `rect` ready to adjust or redraw, and
+/// returns the new selection, or `None` if it was cancelled.
+pub fn reselect(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    rect: Rect,
+) -> Result<Option<Rect>, Box<dyn std::error::Error>> {
+    let qh = event_queue.handle();
+    state.selection_state = SelectionState::Editing { rect };
+    state.result = None;
+    state.running = true;
+    state.create_overlays(&qh);
+    run(state, event_queue)?;
+    Ok(state.result)
+}
+
+fn run(state: &mut AppState, event_queue: &mut EventQueue<AppState>) -> Result<(), Box<dyn std::error::Error>> {
+    let qh = event_queue.handle();
+    event_queue.roundtrip(state)?;
+    state.draw_pending(&qh);
+    while state.running {
+        event_queue.blocking_dispatch(state)?;
+        state.draw_pending(&qh);
+    }
+    match state.error.take() {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
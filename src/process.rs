@@ -0,0 +1,140 @@
+//! Post-processing applied to captures before they are encoded.
+
+use std::path::PathBuf;
+
+use image::RgbaImage;
+use image::imageops::{self, FilterType};
+
+/// Target size for `--resize`/`--scale`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resample {
+    Size(u32, u32), // Exact width and height in pixels
+    Scale(f32),     // Factor applied to both dimensions
+}
+
+/// Parses a `WxH` size such as `1280x720`.
+pub fn parse_size(spec: &str) -> Result<Resample, String> {
+    let invalid = || format!("invalid size '{spec}', expected WxH");
+    let (width, height) = spec.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.parse().map_err(|_| invalid())?;
+    let height: u32 = height.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok(Resample::Size(width, height))
+}
+
+/// Parses a positive scale factor such as `0.5`.
+pub fn parse_scale(spec: &str) -> Result<Resample, String> {
+    match spec.parse::<f32>() {
+        Ok(factor) if factor.is_finite() && factor > 0.0 => Ok(Resample::Scale(factor)),
+        _ => Err(format!("invalid scale factor '{spec}'")),
+    }
+}
+
+pub fn parse_filter(name: &str) -> Result<FilterType, String> {
+    Ok(match name {
+        "nearest" => FilterType::Nearest,
+        "bilinear" => FilterType::Triangle,
+        "lanczos" => FilterType::Lanczos3,
+        _ => return Err(format!("unknown filter '{name}'")),
+    })
+}
+
+/// Resamples `image` to the requested size; a no-op when it already matches.
+pub fn resample(image: RgbaImage, resample: Resample, filter: FilterType) -> RgbaImage {
+    let (width, height) = match resample {
+        Resample::Size(width, height) => (width, height),
+        Resample::Scale(factor) => {
+            let (width, height) = image.dimensions();
+            let scaled = |v: u32| ((v as f32 * factor).round() as u32).max(1);
+            (scaled(width), scaled(height))
+        }
+    };
+    if image.dimensions() == (width, height) {
+        return image;
+    }
+    imageops::resize(&image, width, height, filter)
+}
+
+/// Scales `image` up by an integer `factor`, repeating each pixel so hard
+/// edges stay sharp.
+pub fn upscale(image: RgbaImage, factor: u32) -> RgbaImage {
+    if factor <= 1 {
+        return image;
+    }
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn(width * factor, height * factor, |x, y| *image.get_pixel(x / factor, y / factor))
+}
+
+/// A corner or the center of a rectangle, e.g. where a watermark is placed
+/// on the capture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+pub fn parse_position(name: &str) -> Result<Position, String> {
+    Ok(match name {
+        "top-left" => Position::TopLeft,
+        "top-right" => Position::TopRight,
+        "bottom-left" => Position::BottomLeft,
+        "bottom-right" => Position::BottomRight,
+        "center" => Position::Center,
+        _ => return Err(format!("unknown position '{name}'")),
+    })
+}
+
+/// A PNG stamped onto every capture, configured in the `[watermark]` section.
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    pub image: PathBuf,
+    pub position: Position,
+    pub opacity: f32, // 0.0 to 1.0, multiplied with the image's own alpha
+    pub margin: u32,  // Distance from the capture edges in pixels
+}
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Self {
+            image: PathBuf::new(),
+            position: Position::BottomRight,
+            opacity: 1.0,
+            margin: 16,
+        }
+    }
+}
+
+/// Composites the watermark onto `image`. Watermarks larger than the
+/// capture are cropped rather than scaled.
+pub fn apply_watermark(image: &mut RgbaImage, watermark: &Watermark) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stamp = image::open(&watermark.image)
+        .map_err(|err| format!("Failed to load watermark {}: {err}", watermark.image.display()))?
+        .to_rgba8();
+    if watermark.opacity < 1.0 {
+        for pixel in stamp.pixels_mut() {
+            pixel[3] = (pixel[3] as f32 * watermark.opacity).round() as u8;
+        }
+    }
+
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let (stamp_width, stamp_height) = (stamp.width() as i64, stamp.height() as i64);
+    let margin = watermark.margin as i64;
+    let left = margin;
+    let top = margin;
+    let right = width - stamp_width - margin;
+    let bottom = height - stamp_height - margin;
+    let (x, y) = match watermark.position {
+        Position::TopLeft => (left, top),
+        Position::TopRight => (right, top),
+        Position::BottomLeft => (left, bottom),
+        Position::BottomRight => (right, bottom),
+        Position::Center => ((width - stamp_width) / 2, (height - stamp_height) / 2),
+    };
+    imageops::overlay(image, &stamp, x, y);
+    Ok(())
+}
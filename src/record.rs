@@ -0,0 +1,151 @@
+//! `glimpse record --native`: the selected region captured over and over
+//! and piped to ffmpeg as raw frames, encoded as VP9 in a .webm file or
+//! H.264 in an .mp4, with the `record` feature.
+//!
+//! Recording stops after `--duration`, on Ctrl+C, or on SIGUSR1, which a
+//! compositor hotkey can send: `pkill -USR1 -x glimpse`.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use wayland_client::EventQueue;
+
+use crate::app::AppState;
+use crate::selection::Rect;
+
+/// Frame rate without `--fps`.
+pub const DEFAULT_FPS: u32 = 30;
+
+/// Highest frame rate `--fps` takes; screencopy rarely keeps up beyond it.
+pub const MAX_FPS: u32 = 60;
+
+/// The video file for a file name from the template: .webm or .mp4 are
+/// kept, anything else becomes .webm.
+pub fn video_name(name: &Path) -> PathBuf {
+    match name.extension().and_then(|ext| ext.to_str()) {
+        Some("webm" | "mp4") => name.to_path_buf(),
+        _ => name.with_extension("webm"),
+    }
+}
+
+/// ffmpeg's encoder options for a video file, by its extension.
+#[cfg_attr(not(feature = "record"), allow(dead_code))]
+fn encoder_args(path: &Path) -> &'static [&'static str] {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("mp4") => &["-c:v", "libx264", "-preset", "veryfast", "-crf", "23", "-movflags", "+faststart"],
+        _ => &["-c:v", "libvpx-vp9", "-deadline", "realtime", "-cpu-used", "8", "-row-mt", "1", "-crf", "32", "-b:v", "0"],
+    }
+}
+
+#[cfg(feature = "record")]
+mod stop {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn request(_: libc::c_int) {
+        REQUESTED.store(true, Ordering::Relaxed);
+    }
+
+    /// Turns SIGINT, SIGTERM and SIGUSR1 into a stop request, so the file
+    /// is finished rather than cut off.
+    pub fn listen() {
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGUSR1] {
+            unsafe { libc::signal(signal, request as extern "C" fn(libc::c_int) as libc::sighandler_t) };
+        }
+    }
+
+    pub fn requested() -> bool {
+        REQUESTED.load(Ordering::Relaxed)
+    }
+}
+
+/// Records `rect` into `path` until stopped, and returns once the file is
+/// complete. Frames that can't be captured in time repeat the last one, so
+/// the video plays at the pace things happened.
+#[cfg(feature = "record")]
+pub fn record(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    path: &Path,
+    rect: Rect,
+    fps: u32,
+    duration: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+    use std::time::Instant;
+
+    stop::listen();
+    let first = crate::capture::capture_region(state, event_queue, rect)?;
+    let (width, height) = first.dimensions();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgba"])
+        .args(["-s", &format!("{width}x{height}"), "-r", &fps.to_string(), "-i", "-"])
+        // 4:2:0 chroma needs even sides
+        .args(["-vf", "crop=trunc(iw/2)*2:trunc(ih/2)*2", "-pix_fmt", "yuv420p"])
+        .args(encoder_args(path))
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        // Out of the terminal's process group, so Ctrl+C reaches only us
+        // and the file is finished properly
+        .process_group(0)
+        .spawn()
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => "record --native needs ffmpeg in $PATH".to_string(),
+            _ => format!("Failed to start ffmpeg: {err}"),
+        })?;
+    let mut input = ffmpeg.stdin.take().expect("ffmpeg's stdin is piped");
+    eprintln!("Recording to {}; press Ctrl+C or send SIGUSR1 to stop.", path.display());
+
+    let started = Instant::now();
+    let interval = Duration::from_secs_f64(1.0 / fps as f64);
+    let mut frame = first;
+    let mut written = 0u32;
+    let result: Result<(), Box<dyn std::error::Error>> = 'recording: loop {
+        // Every frame slot that passed since the last one shows this frame
+        let due = (started.elapsed().as_secs_f64() * fps as f64) as u32 + 1;
+        while written < due {
+            if let Err(err) = input.write_all(frame.as_raw()) {
+                break 'recording Err(format!("ffmpeg stopped taking frames: {err}").into());
+            }
+            written += 1;
+        }
+        if stop::requested() || duration.is_some_and(|duration| started.elapsed() >= duration) {
+            break Ok(());
+        }
+        std::thread::sleep((started + interval * written).saturating_duration_since(Instant::now()));
+        match crate::capture::capture_region(state, event_queue, rect) {
+            Ok(image) if image.dimensions() == (width, height) => frame = image,
+            // After a scale change, the last frame at the old size stands in
+            Ok(_) => log::warn!("The region changed size while recording; repeating the last frame"),
+            Err(err) => break Err(err),
+        }
+    };
+    // Closing ffmpeg's input ends the video
+    drop(input);
+    // ffmpeg's own failure explains a broken pipe better
+    let status = ffmpeg.wait()?;
+    if !status.success() {
+        return Err(format!("ffmpeg exited with {status}").into());
+    }
+    result
+}
+
+/// Stand-in when built without recording; `record` is refused earlier.
+#[cfg(not(feature = "record"))]
+pub fn record(
+    _state: &mut AppState,
+    _event_queue: &mut EventQueue<AppState>,
+    _path: &Path,
+    _rect: Rect,
+    _fps: u32,
+    _duration: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("record --native needs Glimpse built with the record feature".into())
+}
@@ -0,0 +1,185 @@
+//! `--record-input` and `--replay-input`: pointer and keyboard input as the
+//! selection logic sees it, saved with timestamps and fed back later, so a
+//! session can be repeated without anyone at the keyboard.
+//!
+//! One event per line, after the milliseconds since the first one:
+//! - `motion X Y` moves the pointer, in global logical coordinates
+//! - `relative DX DY` moves it by a delta in precision mode
+//! - `button CODE press|release` for a mouse button's evdev code
+//! - `axis DX DY` scrolls
+//! - `key CODE press|release` for a key's evdev code
+//! - `modifiers MASK` for the keys::MOD_* flags held
+//!
+//! Lines starting with `#` are comments.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use wayland_client::backend::WaylandError;
+use wayland_client::EventQueue;
+
+use crate::app::AppState;
+
+/// One pointer or keyboard event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Motion(i32, i32),
+    Relative(f64, f64),
+    Button { button: u32, pressed: bool },
+    Axis(f64, f64),
+    Key { key: u32, pressed: bool },
+    Modifiers(u8),
+}
+
+impl InputEvent {
+    /// Parses an event as written by `Display`.
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut words = text.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        let number = |index: usize| -> Result<f64, String> {
+            args.get(index).and_then(|arg| arg.parse().ok()).ok_or_else(|| format!("{name} takes numbers, got '{}'", args.join(" ")))
+        };
+        let code = || -> Result<u32, String> {
+            args.first().and_then(|arg| arg.parse().ok()).ok_or_else(|| format!("invalid {name} code in '{text}'"))
+        };
+        let pressed = || match args.get(1) {
+            Some(&"press") => Ok(true),
+            Some(&"release") => Ok(false),
+            _ => Err(format!("{name} needs press or release, got '{text}'")),
+        };
+        let count = match name {
+            "motion" | "relative" | "axis" | "button" | "key" => 2,
+            "modifiers" => 1,
+            _ => return Err(format!("unknown event '{name}'")),
+        };
+        if args.len() != count {
+            return Err(format!("{name} takes {count} values, got '{}'", args.join(" ")));
+        }
+        Ok(match name {
+            "motion" => {
+                let position = args[0].parse().ok().zip(args[1].parse().ok());
+                let (x, y) = position.ok_or_else(|| format!("motion takes whole numbers, got '{}'", args.join(" ")))?;
+                InputEvent::Motion(x, y)
+            }
+            "relative" => InputEvent::Relative(number(0)?, number(1)?),
+            "axis" => InputEvent::Axis(number(0)?, number(1)?),
+            "button" => InputEvent::Button { button: code()?, pressed: pressed()? },
+            "key" => InputEvent::Key { key: code()?, pressed: pressed()? },
+            _ => InputEvent::Modifiers(args[0].parse().map_err(|_| format!("invalid modifier mask '{}'", args[0]))?),
+        })
+    }
+}
+
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = |pressed| if pressed { "press" } else { "release" };
+        match *self {
+            InputEvent::Motion(x, y) => write!(f, "motion {x} {y}"),
+            InputEvent::Relative(dx, dy) => write!(f, "relative {dx} {dy}"),
+            InputEvent::Button { button, pressed } => write!(f, "button {button} {}", action(pressed)),
+            InputEvent::Axis(dx, dy) => write!(f, "axis {dx} {dy}"),
+            InputEvent::Key { key, pressed } => write!(f, "key {key} {}", action(pressed)),
+            InputEvent::Modifiers(mask) => write!(f, "modifiers {mask}"),
+        }
+    }
+}
+
+/// Writes events to a file as they happen.
+pub struct Recorder {
+    file: BufWriter<File>,
+    start: Option<Instant>, // When the first event came
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::create(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        writeln!(file, "# glimpse input recording")?;
+        Ok(Self { file, start: None })
+    }
+
+    pub fn record(&mut self, event: InputEvent) {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        // Flushed each time so a session that crashes still leaves its input
+        let written = writeln!(self.file, "{} {event}", start.elapsed().as_millis()).and_then(|()| self.file.flush());
+        if let Err(err) = written {
+            log::warn!("Failed to record input: {err}");
+        }
+    }
+}
+
+/// Reads a recording, checking every line before any is replayed.
+pub fn load(path: &Path) -> Result<Vec<(Duration, InputEvent)>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let mut events = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |err: String| format!("{}: line {}: {err}", path.display(), index + 1);
+        let (time, event) = line.split_once(char::is_whitespace).ok_or_else(|| error(format!("expected a time and an event, got '{line}'")))?;
+        let time = time.parse().map_err(|_| error(format!("invalid time '{time}'")))?;
+        events.push((Duration::from_millis(time), InputEvent::parse(event.trim()).map_err(error)?));
+    }
+    Ok(events)
+}
+
+/// Feeds `events` to the session at their times, counted from now. Live
+/// input is ignored meanwhile. A recording that runs out before the
+/// selection is confirmed or cancelled ends the session with an error.
+pub fn replay(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    events: Vec<(Duration, InputEvent)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let qh = event_queue.handle();
+    let start = Instant::now();
+    let mut events = events.into_iter().peekable();
+    loop {
+        event_queue.dispatch_pending(state)?;
+        while state.running {
+            let Some(&(time, event)) = events.peek() else { break };
+            if start.elapsed() < time {
+                break;
+            }
+            events.next();
+            state.apply_input(event, &qh);
+        }
+        state.draw_pending(&qh);
+        if !state.running {
+            return Ok(());
+        }
+        let Some(&(time, _)) = events.peek() else {
+            state.error = Some("The recording ended before the selection was confirmed or cancelled".into());
+            state.running = false;
+            return Ok(());
+        };
+        event_queue.flush()?;
+        let Some(guard) = event_queue.prepare_read() else { continue };
+        let mut fds = [libc::pollfd { fd: guard.connection_fd().as_raw_fd(), events: libc::POLLIN, revents: 0 }];
+        // Rounded up so the next event is due on waking
+        let timeout = time.saturating_sub(start.elapsed()).as_micros().div_ceil(1000);
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout.min(i32::MAX as u128) as i32) } < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err.into());
+            }
+            continue;
+        }
+        if fds[0].revents != 0 {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        } else {
+            drop(guard);
+        }
+    }
+}
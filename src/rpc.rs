@@ -0,0 +1,342 @@
+//! `--rpc`: newline-delimited JSON-RPC 2.0 on stdin and stdout, for programs
+//! that drive Glimpse as a subprocess.
+//!
+//! Methods:
+//! - `select {initial?: "X,Y WxH"}` returns `{x, y, width, height}`
+//! - `capture {geometry?: "X,Y WxH", format?: "png" | "webp"}` returns the
+//!   geometry plus `format` and base64 `data`; without a geometry the user
+//!   selects one first
+//! - `pick` returns `{x, y, color}` for one clicked pixel
+//! - `cancel` ends the interaction in progress, which then fails with
+//!   [`CANCELLED`]
+//!
+//! While the user interacts, `progress` notifications report the stage of
+//! the request and any request other than `cancel` fails with [`BUSY`].
+//!
+//! `glimpse daemon` serves the same methods on a socket, see the daemon
+//! module.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+
+use base64::Engine as _;
+use serde_json::{json, Value};
+use wayland_client::backend::WaylandError;
+use wayland_client::EventQueue;
+
+use crate::app::{AppState, SelectionState};
+use crate::capture;
+use crate::config::Command;
+use crate::contrast;
+use crate::encode::{self, Format};
+use crate::selection::Rect;
+
+// Standard JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// Glimpse's own
+pub const CANCELLED: i64 = 1; // The user or a `cancel` request aborted the interaction
+const BUSY: i64 = 2;      // Another request is waiting for the user
+const FAILED: i64 = 3;    // Capturing or talking to the compositor failed
+
+struct Error {
+    code: i64,
+    message: String,
+}
+
+impl Error {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+impl<E: std::fmt::Display> From<E> for Error {
+    fn from(err: E) -> Self {
+        Self::new(FAILED, err.to_string())
+    }
+}
+
+/// Lines read from stdin without buffering ahead of what was polled for.
+pub struct Input {
+    pub file: File,
+    buffer: Vec<u8>,
+    pub closed: bool,
+}
+
+impl Input {
+    pub fn new(file: File) -> Self {
+        Self { file, buffer: Vec::new(), closed: false }
+    }
+
+    /// Stdin, which nothing else may read from.
+    pub fn stdin() -> Self {
+        Self::new(unsafe { File::from_raw_fd(std::io::stdin().as_raw_fd()) })
+    }
+
+    /// Removes the next complete line from the buffer.
+    pub fn pop_line(&mut self) -> Option<String> {
+        let end = self.buffer.iter().position(|&b| b == b'\n')?;
+        let line: Vec<u8> = self.buffer.drain(..=end).collect();
+        Some(String::from_utf8_lossy(&line).trim().to_string())
+    }
+
+    /// Reads whatever is available, blocking if nothing is.
+    pub fn fill(&mut self) -> std::io::Result<()> {
+        let mut chunk = [0; 4096];
+        match self.file.read(&mut chunk)? {
+            0 => self.closed = true,
+            n => self.buffer.extend_from_slice(&chunk[..n]),
+        }
+        Ok(())
+    }
+}
+
+struct Server<'a> {
+    input: Input,
+    output: &'a mut dyn Write,
+}
+
+/// Answers requests until stdin is closed.
+pub fn serve(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    output: &mut dyn Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Stdin is only read here, and unbuffered so polling it stays accurate
+    run(state, event_queue, Server { input: Input::stdin(), output })
+}
+
+/// Answers requests from one daemon client until it hangs up.
+pub fn serve_stream(
+    state: &mut AppState,
+    event_queue: &mut EventQueue<AppState>,
+    stream: UnixStream,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut output = stream.try_clone()?;
+    let input = Input::new(File::from(OwnedFd::from(stream)));
+    run(state, event_queue, Server { input, output: &mut output })
+}
+
+fn run(state: &mut AppState, event_queue: &mut EventQueue<AppState>, mut server: Server) -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+        while let Some(line) = server.input.pop_line() {
+            server.handle(state, event_queue, &line)?;
+        }
+        if server.input.closed {
+            return Ok(());
+        }
+        server.input.fill()?;
+    }
+}
+
+impl Server<'_> {
+    fn handle(&mut self, state: &mut AppState, event_queue: &mut EventQueue<AppState>, line: &str) -> std::io::Result<()> {
+        if line.is_empty() {
+            return Ok(());
+        }
+        let (id, request) = match parse_request(line) {
+            Ok(request) => request,
+            Err((id, err)) => return self.respond(id, Err(err)),
+        };
+        let (method, params) = request;
+        let result = match method.as_str() {
+            "select" => self.select(state, event_queue, &id, &params),
+            "capture" => self.capture(state, event_queue, &id, &params),
+            "pick" => self.pick(state, event_queue, &id),
+            "cancel" => Err(Error::new(FAILED, "nothing to cancel")),
+            _ => Err(Error::new(METHOD_NOT_FOUND, format!("unknown method '{method}'"))),
+        };
+        self.respond(id, result)
+    }
+
+    fn select(&mut self, state: &mut AppState, event_queue: &mut EventQueue<AppState>, id: &Option<Value>, params: &Value) -> Result<Value, Error> {
+        let initial = geometry_param(params, "initial")?;
+        let rect = self.interact(state, event_queue, id, initial)?;
+        let rect = rect.ok_or_else(|| Error::new(CANCELLED, "selection cancelled"))?;
+        Ok(geometry_json(rect))
+    }
+
+    fn capture(&mut self, state: &mut AppState, event_queue: &mut EventQueue<AppState>, id: &Option<Value>, params: &Value) -> Result<Value, Error> {
+        let format = match params.get("format").map(Value::as_str) {
+            None => Format::Png,
+            Some(Some(name)) => match encode::parse_formats(name)?.as_slice() {
+                &[format] => format,
+                _ => return Err(Error::new(INVALID_PARAMS, "capture takes a single format")),
+            },
+            Some(None) => return Err(Error::new(INVALID_PARAMS, "format must be a string")),
+        };
+        let rect = match geometry_param(params, "geometry")? {
+            Some(rect) => rect,
+            None => self.interact(state, event_queue, id, None)?
+                .ok_or_else(|| Error::new(CANCELLED, "selection cancelled"))?,
+        };
+        self.progress(id, "capturing")?;
+        let image = capture::capture_region(state, event_queue, rect)?;
+        self.progress(id, "encoding")?;
+        let metadata = capture::metadata(state, rect);
+        let data = encode::encode(&image, format, &metadata)?;
+        let mut result = geometry_json(rect);
+        result["format"] = json!(format.extension());
+        result["data"] = json!(base64::engine::general_purpose::STANDARD.encode(data));
+        Ok(result)
+    }
+
+    fn pick(&mut self, state: &mut AppState, event_queue: &mut EventQueue<AppState>, id: &Option<Value>) -> Result<Value, Error> {
+        // Colors are read from the screen as it was, not through the dim overlay
+        state.snapshot = Some(capture::capture_snapshot(state, event_queue)?);
+        let (command, two_click) = (state.config.command, state.config.two_click);
+        state.config.command = Command::Pick;
+        state.config.two_click = true;
+        let outcome = self.interact(state, event_queue, id, None);
+        state.config.command = command;
+        state.config.two_click = two_click;
+        let snapshot = state.snapshot.take().unwrap();
+        outcome?;
+
+        let (point, _) = state.points.ok_or_else(|| Error::new(CANCELLED, "pick cancelled"))?;
+        let color = snapshot.sample(point).ok_or_else(|| Error::new(FAILED, "picked a point outside of every output"))?;
+        Ok(json!({ "x": point.0, "y": point.1, "color": contrast::hex(color) }))
+    }
+
+    /// Maps the overlays and waits for the user to confirm or cancel, or for
+    /// a `cancel` request. Returns the confirmed rectangle, if any; picked
+    /// points are left in `state.points`.
+    fn interact(
+        &mut self,
+        state: &mut AppState,
+        event_queue: &mut EventQueue<AppState>,
+        id: &Option<Value>,
+        initial: Option<Rect>,
+    ) -> Result<Option<Rect>, Error> {
+        let qh = event_queue.handle();
+        state.running = true;
+        state.result = None;
+        state.points = None;
+        state.selection_state = initial.map_or(SelectionState::Idle, |rect| SelectionState::Editing { rect });
+        state.prev_selection_state = SelectionState::Idle;
+        state.create_overlays(&qh);
+        event_queue.roundtrip(state)?;
+        state.draw_pending(&qh);
+        self.progress(id, "waiting")?;
+
+        let waited = self.wait(state, event_queue);
+        state.close_overlays();
+        state.pointer_surface = None;
+        state.selection_state = SelectionState::Idle;
+        event_queue.roundtrip(state)?;
+        waited?;
+        Ok(state.result)
+    }
+
+    /// Dispatches Wayland events and reads requests until the interaction ends.
+    fn wait(&mut self, state: &mut AppState, event_queue: &mut EventQueue<AppState>) -> Result<(), Error> {
+        let qh = event_queue.handle();
+        loop {
+            event_queue.dispatch_pending(state)?;
+            state.draw_pending(&qh);
+            while state.running {
+                let Some(line) = self.input.pop_line() else { break };
+                self.handle_busy(state, &line)?;
+            }
+            if !state.running {
+                return Ok(());
+            }
+            if self.input.closed {
+                // Nobody is left to read the result
+                state.cancel();
+                return Ok(());
+            }
+            event_queue.flush()?;
+            let Some(guard) = event_queue.prepare_read() else { continue };
+            let mut fds = [
+                libc::pollfd { fd: guard.connection_fd().as_raw_fd(), events: libc::POLLIN, revents: 0 },
+                libc::pollfd { fd: self.input.file.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+            ];
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(err.into());
+                }
+                continue;
+            }
+            if fds[0].revents != 0 {
+                match guard.read() {
+                    Ok(_) => {}
+                    Err(WaylandError::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+                    Err(err) => return Err(err.into()),
+                }
+            } else {
+                drop(guard);
+            }
+            if fds[1].revents != 0 {
+                self.input.fill()?;
+            }
+        }
+    }
+
+    /// Answers a request that arrived while the user is interacting.
+    fn handle_busy(&mut self, state: &mut AppState, line: &str) -> std::io::Result<()> {
+        if line.is_empty() {
+            return Ok(());
+        }
+        let result = match parse_request(line) {
+            Ok((id, (method, _))) if method == "cancel" => {
+                state.cancel();
+                (id, Ok(Value::Null))
+            }
+            Ok((id, _)) => (id, Err(Error::new(BUSY, "waiting for the user to finish another request"))),
+            Err((id, err)) => (id, Err(err)),
+        };
+        self.respond(result.0, result.1)
+    }
+
+    fn progress(&mut self, id: &Option<Value>, stage: &str) -> std::io::Result<()> {
+        let Some(id) = id else { return Ok(()) };
+        self.send(json!({ "jsonrpc": "2.0", "method": "progress", "params": { "id": id, "stage": stage } }))
+    }
+
+    /// Sends the response to a request; notifications (without an id) get none.
+    fn respond(&mut self, id: Option<Value>, result: Result<Value, Error>) -> std::io::Result<()> {
+        let Some(id) = id else { return Ok(()) };
+        self.send(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": err.code, "message": err.message } }),
+        })
+    }
+
+    fn send(&mut self, message: Value) -> std::io::Result<()> {
+        writeln!(self.output, "{message}")?;
+        self.output.flush()
+    }
+}
+
+type Request = (String, Value); // Method and params
+
+/// Splits a request into its id and contents. Errors carry the id when it
+/// could be read, and a null id otherwise as the spec asks.
+fn parse_request(line: &str) -> Result<(Option<Value>, Request), (Option<Value>, Error)> {
+    let request: Value = serde_json::from_str(line)
+        .map_err(|err| (Some(Value::Null), Error::new(PARSE_ERROR, err.to_string())))?;
+    let id = request.get("id").cloned();
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return Err((Some(id.unwrap_or(Value::Null)), Error::new(INVALID_REQUEST, "missing method")));
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    Ok((id, (method.to_string(), params)))
+}
+
+fn geometry_param(params: &Value, name: &str) -> Result<Option<Rect>, Error> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value.as_str().and_then(Rect::parse).map(Some)
+            .ok_or_else(|| Error::new(INVALID_PARAMS, format!("{name} must be a geometry such as \"10,20 300x200\""))),
+    }
+}
+
+fn geometry_json(rect: Rect) -> Value {
+    json!({ "x": rect.x, "y": rect.y, "width": rect.width, "height": rect.height })
+}
@@ -0,0 +1,219 @@
+//! `--s3`: the capture PUT into an S3-compatible bucket, with the `s3`
+//! feature, and a link to it printed: under `public_url` when the bucket
+//! is served publicly, or else a presigned one that expires.
+//!
+//! ```text
+//! [s3]
+//! bucket = screenshots
+//! endpoint = https://minio.example.com   # Default: AWS, for the region
+//! region = eu-central-1                  # Default: $AWS_REGION or us-east-1
+//! prefix = team/                         # Put before every key
+//! public_url = https://shots.example.com # Links made from it instead of presigned
+//! expires = 86400                        # Seconds a presigned link lasts
+//! ```
+//!
+//! Credentials come from `access_key`, `secret_key` and `session_token`, or
+//! `$AWS_ACCESS_KEY_ID`, `$AWS_SECRET_ACCESS_KEY` and `$AWS_SESSION_TOKEN`.
+
+/// Longest a presigned link may last, a week.
+pub const MAX_EXPIRES: u32 = 604_800;
+
+/// Where and how captures are stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct S3 {
+    pub bucket: String,
+    pub endpoint: Option<String>, // None is AWS in `region`
+    pub region: Option<String>,   // None is $AWS_REGION, or us-east-1
+    pub path_style: Option<bool>, // Bucket in the path rather than the host; default for custom endpoints
+    pub prefix: String,
+    pub public_url: Option<String>,
+    pub expires: u32, // Seconds, for presigned links
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    pub session_token: Option<String>,
+}
+
+impl Default for S3 {
+    fn default() -> Self {
+        Self {
+            bucket: String::new(),
+            endpoint: None,
+            region: None,
+            path_style: None,
+            prefix: String::new(),
+            public_url: None,
+            expires: MAX_EXPIRES,
+            access_key: None,
+            secret_key: None,
+            session_token: None,
+        }
+    }
+}
+
+#[cfg(feature = "s3")]
+mod sign {
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+
+    pub fn sha256_hex(data: &[u8]) -> String {
+        hex(&Sha256::digest(data))
+    }
+
+    pub fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    pub fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    /// Percent-encodes all but unreserved characters, and slashes too
+    /// unless `keep_slash`.
+    pub fn encode(text: &str, keep_slash: bool) -> String {
+        text.bytes().map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            b'/' if keep_slash => "/".into(),
+            _ => format!("%{byte:02X}"),
+        }).collect()
+    }
+}
+
+/// A signed request's parts for one object.
+#[cfg(feature = "s3")]
+struct Request {
+    host: String,
+    path: String, // Encoded
+    base: String, // Scheme and host
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    time: chrono::DateTime<chrono::Utc>,
+}
+
+#[cfg(feature = "s3")]
+impl Request {
+    fn new(s3: &S3, key: &str) -> Result<Self, String> {
+        let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let region = s3.region.clone().or_else(|| env("AWS_REGION")).or_else(|| env("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| "us-east-1".into());
+        let access_key = s3.access_key.clone().or_else(|| env("AWS_ACCESS_KEY_ID"))
+            .ok_or("--s3 needs access_key under [s3] or $AWS_ACCESS_KEY_ID")?;
+        let secret_key = s3.secret_key.clone().or_else(|| env("AWS_SECRET_ACCESS_KEY"))
+            .ok_or("--s3 needs secret_key under [s3] or $AWS_SECRET_ACCESS_KEY")?;
+        let session_token = s3.session_token.clone().or_else(|| env("AWS_SESSION_TOKEN"));
+        let endpoint = s3.endpoint.clone().unwrap_or_else(|| format!("https://s3.{region}.amazonaws.com"));
+        let (scheme, host) = endpoint.trim_end_matches('/').split_once("://")
+            .ok_or_else(|| format!("invalid S3 endpoint '{endpoint}', expected an http(s) URL"))?;
+        let key = sign::encode(key, true);
+        let (host, path) = if s3.path_style.unwrap_or(s3.endpoint.is_some()) {
+            (host.to_string(), format!("/{}/{key}", sign::encode(&s3.bucket, false)))
+        } else {
+            (format!("{}.{host}", s3.bucket), format!("/{key}"))
+        };
+        Ok(Self {
+            base: format!("{scheme}://{host}"),
+            host,
+            path,
+            region,
+            access_key,
+            secret_key,
+            session_token,
+            time: chrono::Utc::now(),
+        })
+    }
+
+    fn scope(&self) -> String {
+        format!("{}/{}/s3/aws4_request", self.time.format("%Y%m%d"), self.region)
+    }
+
+    fn amz_date(&self) -> String {
+        self.time.format("%Y%m%dT%H%M%SZ").to_string()
+    }
+
+    /// The AWS Signature Version 4 of a canonical request.
+    fn signature(&self, canonical_request: &str) -> String {
+        let to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", self.amz_date(), self.scope(), sign::sha256_hex(canonical_request.as_bytes()));
+        let key = [self.time.format("%Y%m%d").to_string(), self.region.clone(), "s3".into(), "aws4_request".into()]
+            .iter()
+            .fold(format!("AWS4{}", self.secret_key).into_bytes(), |key, part| sign::hmac(&key, part));
+        sign::hex(&sign::hmac(&key, &to_sign))
+    }
+
+    /// Uploads `data` with a signed PUT.
+    fn put(&self, data: &[u8], mime_type: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let payload = sign::sha256_hex(data);
+        let mut headers = vec![
+            ("content-type", mime_type.to_string()),
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", payload.clone()),
+            ("x-amz-date", self.amz_date()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{name}:{}\n", value.trim())).collect();
+        let signed_headers = headers.iter().map(|&(name, _)| name).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("PUT\n{}\n\n{canonical_headers}\n{signed_headers}\n{payload}", self.path);
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={signed_headers}, Signature={}",
+            self.access_key,
+            self.scope(),
+            self.signature(&canonical_request),
+        );
+        let agent = ureq::AgentBuilder::new()
+            .timeout(std::time::Duration::from_secs(60))
+            .user_agent(concat!("glimpse/", env!("CARGO_PKG_VERSION")))
+            .build();
+        let mut request = agent.put(&format!("{}{}", self.base, self.path)).set("Authorization", &authorization);
+        for (name, value) in headers.iter().filter(|&&(name, _)| name != "host") {
+            request = request.set(name, value);
+        }
+        match request.send_bytes(data) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(code, response)) => {
+                let answer: String = response.into_string().unwrap_or_default().trim().chars().take(300).collect();
+                Err(format!("{} refused the upload with HTTP {code}: {answer}", self.host).into())
+            }
+            Err(err) => Err(format!("Failed to upload to {}: {err}", self.host).into()),
+        }
+    }
+
+    /// A link to GET the object for `expires` seconds.
+    fn presigned(&self, expires: u32) -> String {
+        let mut query = vec![
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+            ("X-Amz-Credential", format!("{}/{}", self.access_key, self.scope())),
+            ("X-Amz-Date", self.amz_date()),
+            ("X-Amz-Expires", expires.to_string()),
+        ];
+        if let Some(token) = &self.session_token {
+            query.push(("X-Amz-Security-Token", token.clone()));
+        }
+        query.push(("X-Amz-SignedHeaders", "host".into()));
+        let query = query.iter().map(|(name, value)| format!("{name}={}", sign::encode(value, false))).collect::<Vec<_>>().join("&");
+        let canonical_request = format!("GET\n{}\n{query}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD", self.path, self.host);
+        format!("{}{}?{query}&X-Amz-Signature={}", self.base, self.path, self.signature(&canonical_request))
+    }
+}
+
+/// Stores `data` as `key`, under the configured prefix, and returns a link
+/// to it.
+#[cfg(feature = "s3")]
+pub fn send(s3: &S3, data: &[u8], key: &str, mime_type: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let key = format!("{}{key}", s3.prefix);
+    let request = Request::new(s3, &key)?;
+    request.put(data, mime_type)?;
+    Ok(match &s3.public_url {
+        Some(base) => format!("{}/{}", base.trim_end_matches('/'), sign::encode(&key, true)),
+        None => request.presigned(s3.expires),
+    })
+}
+
+/// Stand-in when built without S3 support; `--s3` is refused earlier.
+#[cfg(not(feature = "s3"))]
+pub fn send(_s3: &S3, _data: &[u8], _key: &str, _mime_type: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Err("S3 uploads need Glimpse built with the s3 feature".into())
+}
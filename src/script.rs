@@ -0,0 +1,156 @@
+//! Lua scripts (with the `lua` feature) that customize the selection.
+//!
+//! The script is `--script PATH`, or `init.lua` next to the configuration
+//! file when it exists. It may define these global functions, each taking
+//! the selection as a `{x, y, width, height}` table:
+//!
+//! - `on_selection_change(rect)`: return a table to replace the rectangle,
+//!   e.g. to snap it, or nil to keep it
+//! - `on_confirm(rect, method)`: the same, once the selection is confirmed;
+//!   `method` is `drag`, `click`, `output` or `edit`
+//! - `hud_text(rect)`: return a string to show below the selection
+//!
+//! Errors raised by a function are logged and the selection is left as it was.
+
+use std::path::Path;
+
+use crate::paths;
+
+#[cfg(feature = "lua")]
+pub use lua::Script;
+#[cfg(not(feature = "lua"))]
+pub use stub::Script;
+
+/// Loads `explicit`, or else the default script if there is one. Without
+/// the lua feature only an explicit script is an error.
+pub fn load(explicit: Option<&Path>) -> Result<Option<Script>, Box<dyn std::error::Error>> {
+    if let Some(path) = explicit {
+        return Script::from_file(path).map(Some);
+    }
+    match paths::script_file() {
+        Some(path) if cfg!(feature = "lua") && path.exists() => Script::from_file(&path).map(Some),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(feature = "lua")]
+mod lua {
+    use std::cell::Cell;
+    use std::path::Path;
+
+    use mlua::{Function, Lua, Table, Value};
+
+    use crate::selection::Rect;
+
+    pub struct Script {
+        lua: Lua,
+        last_change: Cell<Option<(Rect, Rect)>>, // Last input and result of on_selection_change
+    }
+
+    impl Script {
+        pub(super) fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+            let source = std::fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+            let lua = Lua::new();
+            lua.load(&source)
+                .set_name(path.display().to_string())
+                .exec()
+                .map_err(|err| format!("{}: {err}", path.display()))?;
+            Ok(Self { lua, last_change: Cell::new(None) })
+        }
+
+        fn function(&self, name: &str) -> Option<Function<'_>> {
+            self.lua.globals().get::<_, Option<Function>>(name).ok().flatten()
+        }
+
+        fn table(&self, rect: Rect) -> mlua::Result<Table<'_>> {
+            let table = self.lua.create_table()?;
+            table.set("x", rect.x)?;
+            table.set("y", rect.y)?;
+            table.set("width", rect.width)?;
+            table.set("height", rect.height)?;
+            Ok(table)
+        }
+
+        /// Calls a function that may return a replacement rectangle.
+        fn adjust(&self, name: &str, rect: Rect, method: Option<&str>) -> Rect {
+            let Some(function) = self.function(name) else { return rect };
+            let result = self.table(rect).and_then(|table| function.call::<_, Value>((table, method)));
+            let adjusted = result.and_then(|value| match value {
+                Value::Nil => Ok(rect),
+                Value::Table(table) => Ok(Rect::new(
+                    table.get("x")?,
+                    table.get("y")?,
+                    table.get::<_, i32>("width")?.max(1),
+                    table.get::<_, i32>("height")?.max(1),
+                )),
+                other => Err(mlua::Error::runtime(format!("expected a table or nil, got {}", other.type_name()))),
+            });
+            adjusted.unwrap_or_else(|err| {
+                log::warn!("Lua {name} failed: {err}");
+                rect
+            })
+        }
+
+        /// Runs `on_selection_change`, only when the selection differs from
+        /// the last call since it is asked for on every redraw.
+        pub fn selection_changed(&self, rect: Rect) -> Rect {
+            if let Some((input, output)) = self.last_change.get() {
+                if input == rect {
+                    return output;
+                }
+            }
+            let adjusted = self.adjust("on_selection_change", rect, None);
+            self.last_change.set(Some((rect, adjusted)));
+            adjusted
+        }
+
+        pub fn confirm(&self, rect: Rect, method: &str) -> Rect {
+            self.adjust("on_confirm", rect, Some(method))
+        }
+
+        pub fn has_hud_text(&self) -> bool {
+            self.function("hud_text").is_some()
+        }
+
+        pub fn hud_text(&self, rect: Rect) -> Option<String> {
+            let function = self.function("hud_text")?;
+            let result = self.table(rect).and_then(|table| function.call::<_, Option<String>>(table));
+            result.unwrap_or_else(|err| {
+                log::warn!("Lua hud_text failed: {err}");
+                None
+            })
+        }
+    }
+}
+
+#[cfg(not(feature = "lua"))]
+mod stub {
+    use std::path::Path;
+
+    use crate::selection::Rect;
+
+    /// Stand-in when built without Lua: no script can ever be loaded.
+    pub enum Script {}
+
+    impl Script {
+        pub(super) fn from_file(_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+            Err("Lua scripts need Glimpse built with the lua feature".into())
+        }
+
+        pub fn selection_changed(&self, _rect: Rect) -> Rect {
+            match *self {}
+        }
+
+        pub fn confirm(&self, _rect: Rect, _method: &str) -> Rect {
+            match *self {}
+        }
+
+        pub fn has_hud_text(&self) -> bool {
+            match *self {}
+        }
+
+        pub fn hud_text(&self, _rect: Rect) -> Option<String> {
+            match *self {}
+        }
+    }
+}
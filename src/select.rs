@@ -0,0 +1,162 @@
+//! The library's entry points: the overlay shown for one selection,
+//! returning the region to the calling program instead of printing it.
+//!
+//! ```no_run
+//! let region = glimpse::select_region(&glimpse::Options::default())?;
+//! if let Some(rect) = region {
+//!     println!("{},{} {}x{}", rect.x, rect.y, rect.width, rect.height);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! With the `tokio` or `async-io` feature, `select_region_async` waits for
+//! the compositor through the runtime instead of blocking the thread.
+
+use std::path::PathBuf;
+
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+use wayland_client::backend::{ReadEventsGuard, WaylandError};
+use wayland_client::{Connection, EventQueue};
+
+use crate::app::{AppState, SelectionState};
+use crate::capture;
+use crate::config::Config;
+use crate::graphics::text::TextRenderer;
+use crate::selection::Rect;
+use crate::snap::Snap;
+use crate::windows;
+
+/// How a selection starts. Everything else, such as the theme, the key
+/// bindings and snapping, comes from the configuration file as for the
+/// `glimpse` command.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub initial: Option<Rect>, // Start with this region selected and editable
+    pub config: Option<PathBuf>, // Instead of $XDG_CONFIG_HOME/glimpse/config
+}
+
+/// Shows the overlay until a region is confirmed, and returns it, or
+/// `None` if the selection was cancelled.
+pub fn select_region(options: &Options) -> Result<Option<Rect>, Box<dyn std::error::Error>> {
+    let (_conn, mut event_queue, mut state) = start(options)?;
+    let qh = event_queue.handle();
+    while state.running {
+        event_queue.blocking_dispatch(&mut state)?;
+        state.draw_pending(&qh);
+    }
+    finish(&mut event_queue, &mut state)
+}
+
+/// Like [`select_region`], but awaits the compositor's events. Setting up
+/// the overlay still takes a few quick roundtrips on the calling thread.
+/// The future isn't `Send`, so await it on the task that wants the region.
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+pub async fn select_region_async(options: &Options) -> Result<Option<Rect>, Box<dyn std::error::Error>> {
+    let (conn, mut event_queue, mut state) = start(options)?;
+    let qh = event_queue.handle();
+    let socket = Socket::new(&conn)?;
+    loop {
+        event_queue.dispatch_pending(&mut state)?;
+        state.draw_pending(&qh);
+        if !state.running {
+            return finish(&mut event_queue, &mut state);
+        }
+        event_queue.flush()?;
+        if let Some(guard) = event_queue.prepare_read() {
+            socket.read(guard).await?;
+        }
+    }
+}
+
+/// Connects, and maps the overlays ready for input.
+fn start(options: &Options) -> Result<(Connection, EventQueue<AppState>, AppState), Box<dyn std::error::Error>> {
+    let config = Config::from_file(options.config.as_deref())?;
+    let windows = match config.snap {
+        Snap::Windows => windows::visible().unwrap_or_else(|err| {
+            log::warn!("Not snapping to windows: {err}");
+            Vec::new()
+        }),
+        _ => Vec::new(),
+    };
+    let conn = Connection::connect_to_env()?;
+    let mut event_queue = conn.new_event_queue();
+    let qh = event_queue.handle();
+    conn.display().get_registry(&qh, ());
+
+    let mut state = AppState::new(config);
+    state.windows = windows;
+    event_queue.roundtrip(&mut state)?;
+    // Second roundtrip collects output geometry and xdg-output details
+    event_queue.roundtrip(&mut state)?;
+    // The overlays can't be mapped without these, and the calling program
+    // should hear that rather than panic
+    state.compositor.as_ref().ok_or("Compositor lacks wl_compositor")?;
+    state.shm.as_ref().ok_or("Compositor lacks wl_shm")?;
+    state.layer_shell.as_ref().ok_or("Compositor lacks zwlr_layer_shell_v1")?;
+    capture::prepare_snapshot(&mut state, &mut event_queue)?;
+    if let Some(rect) = options.initial {
+        state.selection_state = SelectionState::Editing { rect };
+    }
+    state.create_overlays(&qh);
+    if state.config.hud.shows_labels() {
+        state.text = TextRenderer::load(state.config.font.as_deref());
+    }
+    event_queue.roundtrip(&mut state)?;
+    state.draw_pending(&qh);
+    Ok((conn, event_queue, state))
+}
+
+/// The confirmed region once the loop has stopped, or why it stopped.
+fn finish(event_queue: &mut EventQueue<AppState>, state: &mut AppState) -> Result<Option<Rect>, Box<dyn std::error::Error>> {
+    state.close_overlays();
+    event_queue.roundtrip(state)?;
+    match state.error.take() {
+        Some(err) => Err(err.into()),
+        None => Ok(state.result),
+    }
+}
+
+/// The compositor connection's socket, registered with the async runtime.
+#[cfg(feature = "tokio")]
+struct Socket(tokio::io::unix::AsyncFd<std::os::fd::OwnedFd>);
+
+#[cfg(feature = "tokio")]
+impl Socket {
+    fn new(conn: &Connection) -> std::io::Result<Self> {
+        Ok(Self(tokio::io::unix::AsyncFd::new(conn.backend().poll_fd().try_clone_to_owned()?)?))
+    }
+
+    /// Waits for the socket, then reads what the compositor sent.
+    async fn read(&self, guard: ReadEventsGuard) -> Result<(), WaylandError> {
+        let mut ready = self.0.readable().await.map_err(WaylandError::Io)?;
+        match guard.read() {
+            // Nothing after all: forget the readiness so the next wait waits
+            Err(WaylandError::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                ready.clear_ready();
+                Ok(())
+            }
+            result => result.map(drop),
+        }
+    }
+}
+
+/// The compositor connection's socket, registered with the async runtime.
+#[cfg(all(feature = "async-io", not(feature = "tokio")))]
+struct Socket(async_io::Async<std::os::fd::OwnedFd>);
+
+#[cfg(all(feature = "async-io", not(feature = "tokio")))]
+impl Socket {
+    fn new(conn: &Connection) -> std::io::Result<Self> {
+        Ok(Self(async_io::Async::new(conn.backend().poll_fd().try_clone_to_owned()?)?))
+    }
+
+    /// Waits for the socket, then reads what the compositor sent.
+    async fn read(&self, guard: ReadEventsGuard) -> Result<(), WaylandError> {
+        self.0.readable().await.map_err(WaylandError::Io)?;
+        match guard.read() {
+            // async-io polls the socket again on the next wait
+            Err(WaylandError::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+            result => result.map(drop),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Selection geometry helpers.
+
+/// An axis-aligned rectangle in logical coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// The rectangle spanned by two opposite corners, in any order.
+    pub fn from_corners(a: (i32, i32), b: (i32, i32)) -> Self {
+        let x = a.0.min(b.0);
+        let y = a.1.min(b.1);
+        Self::new(x, y, (a.0 - b.0).abs(), (a.1 - b.1).abs())
+    }
+
+    /// Parses `X,Y WxH` (slurp's format) or `X,Y,WxH` (Glimpse's output).
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (position, size) = spec.trim().rsplit_once([' ', ','])?;
+        let (x, y) = position.split_once(',')?;
+        let (width, height) = size.split_once('x')?;
+        let rect = Self::new(x.trim().parse().ok()?, y.trim().parse().ok()?, width.parse().ok()?, height.parse().ok()?);
+        (rect.width > 0 && rect.height > 0).then_some(rect)
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height
+    }
+
+    pub fn translate(&self, dx: i32, dy: i32) -> Self {
+        Self::new(self.x + dx, self.y + dy, self.width, self.height)
+    }
+
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= x || bottom <= y {
+            return None;
+        }
+        Some(Rect::new(x, y, right - x, bottom - y))
+    }
+}
+
+/// A region selected with a number key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preset {
+    Fraction(f64, f64, f64, f64), // X, y, width and height as fractions of the hovered output
+    Fixed(Rect),                  // Global logical coordinates
+}
+
+/// Presets by name, the first nine being the defaults for keys 1 to 9.
+const NAMED_PRESETS: &[(&str, Preset)] = &[
+    ("left-half", Preset::Fraction(0.0, 0.0, 0.5, 1.0)),
+    ("right-half", Preset::Fraction(0.5, 0.0, 0.5, 1.0)),
+    ("top-half", Preset::Fraction(0.0, 0.0, 1.0, 0.5)),
+    ("bottom-half", Preset::Fraction(0.0, 0.5, 1.0, 0.5)),
+    ("top-left", Preset::Fraction(0.0, 0.0, 0.5, 0.5)),
+    ("top-right", Preset::Fraction(0.5, 0.0, 0.5, 0.5)),
+    ("bottom-left", Preset::Fraction(0.0, 0.5, 0.5, 0.5)),
+    ("bottom-right", Preset::Fraction(0.5, 0.5, 0.5, 0.5)),
+    ("center", Preset::Fraction(0.25, 0.25, 0.5, 0.5)),
+    ("output", Preset::Fraction(0.0, 0.0, 1.0, 1.0)),
+];
+
+impl Preset {
+    /// The presets for keys 1 to 9 unless configured otherwise.
+    pub fn defaults() -> [Preset; 9] {
+        std::array::from_fn(|index| NAMED_PRESETS[index].1)
+    }
+
+    /// Parses a name such as `left-half` or `top-right`, or a fixed `X,Y WxH`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match NAMED_PRESETS.iter().find(|&&(name, _)| name == spec) {
+            Some(&(_, preset)) => Ok(preset),
+            None => Rect::parse(spec).map(Preset::Fixed)
+                .ok_or_else(|| format!("invalid preset '{spec}', expected a name such as left-half or X,Y WxH")),
+        }
+    }
+
+    /// The region this preset stands for on `output`.
+    pub fn rect(self, output: Rect) -> Rect {
+        match self {
+            Preset::Fraction(x, y, width, height) => {
+                let scale = |fraction: f64, length: i32| (fraction * length as f64).round() as i32;
+                let (left, top) = (output.x + scale(x, output.width), output.y + scale(y, output.height));
+                let (right, bottom) = (output.x + scale(x + width, output.width), output.y + scale(y + height, output.height));
+                Rect::new(left, top, (right - left).max(1), (bottom - top).max(1))
+            }
+            Preset::Fixed(rect) => rect,
+        }
+    }
+}
+
+/// An edge or corner of the selection that the keyboard can move on its own
+/// while editing, in clockwise order from the top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handle {
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+    Left,
+}
+
+impl Handle {
+    const ALL: [Handle; 8] = [
+        Handle::TopLeft, Handle::Top, Handle::TopRight, Handle::Right,
+        Handle::BottomRight, Handle::Bottom, Handle::BottomLeft, Handle::Left,
+    ];
+
+    /// The handle `steps` places further clockwise from `current`, where
+    /// `None` (the whole selection) sits between the last and the first.
+    pub fn cycle(current: Option<Handle>, steps: i32) -> Option<Handle> {
+        let slots = Self::ALL.len() as i32 + 1;
+        let index = current.map_or(0, |handle| Self::ALL.iter().position(|&h| h == handle).unwrap() as i32 + 1);
+        let index = (index + steps).rem_euclid(slots);
+        (index > 0).then(|| Self::ALL[index as usize - 1])
+    }
+
+    /// Which of the left, top, right and bottom edges the handle moves.
+    fn edges(self) -> (bool, bool, bool, bool) {
+        match self {
+            Handle::TopLeft => (true, true, false, false),
+            Handle::Top => (false, true, false, false),
+            Handle::TopRight => (false, true, true, false),
+            Handle::Right => (false, false, true, false),
+            Handle::BottomRight => (false, false, true, true),
+            Handle::Bottom => (false, false, false, true),
+            Handle::BottomLeft => (true, false, false, true),
+            Handle::Left => (true, false, false, false),
+        }
+    }
+
+    /// Where the handle sits on `rect`.
+    pub fn position(self, rect: Rect) -> (i32, i32) {
+        let (left, top, right, bottom) = self.edges();
+        let x = if left { rect.x } else if right { rect.right() } else { rect.x + rect.width / 2 };
+        let y = if top { rect.y } else if bottom { rect.bottom() } else { rect.y + rect.height / 2 };
+        (x, y)
+    }
+
+    /// Moves the edges of `rect` the handle stands for by `(dx, dy)`,
+    /// keeping at least a 1x1 rectangle.
+    pub fn drag(self, rect: Rect, dx: i32, dy: i32) -> Rect {
+        let (moves_left, moves_top, moves_right, moves_bottom) = self.edges();
+        let (mut left, mut top, mut right, mut bottom) = (rect.x, rect.y, rect.right(), rect.bottom());
+        if moves_left {
+            left = (left + dx).min(right - 1);
+        }
+        if moves_right {
+            right = (right + dx).max(left + 1);
+        }
+        if moves_top {
+            top = (top + dy).min(bottom - 1);
+        }
+        if moves_bottom {
+            bottom = (bottom + dy).max(top + 1);
+        }
+        Rect::new(left, top, right - left, bottom - top)
+    }
+}
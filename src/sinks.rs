@@ -0,0 +1,86 @@
+//! Destinations for captured images.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::os::fd::FromRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::paths;
+use crate::windows;
+
+/// Stands for the focused window's app id in a file name template.
+const APP_PLACEHOLDER: &str = "{app}";
+
+/// Expands a strftime-style template with the current local time, and
+/// `{app}` with the focused window's app id. Slashes make directories, e.g.
+/// `%Y/%m/{app}/shot.png`.
+pub fn expand_filename(template: &str) -> Result<String, String> {
+    let name = format_time(template)?;
+    if !name.contains(APP_PLACEHOLDER) {
+        return Ok(name);
+    }
+    Ok(name.replace(APP_PLACEHOLDER, &focused_app()))
+}
+
+/// Checks a template without looking up the focused window.
+pub fn check_filename(template: &str) -> Result<(), String> {
+    format_time(template).map(drop)
+}
+
+fn format_time(template: &str) -> Result<String, String> {
+    let mut name = String::new();
+    write!(name, "{}", chrono::Local::now().format(template))
+        .map_err(|_| format!("invalid filename template '{template}'"))?;
+    Ok(name)
+}
+
+/// The focused window's app id as a single path component, or `unknown`
+/// when there is none or it can't be found out.
+fn focused_app() -> String {
+    let app_id = match windows::visible() {
+        Ok(windows) => windows.into_iter().find(|window| window.focused).map(|window| window.app_id),
+        Err(err) => {
+            log::warn!("Filing the capture under 'unknown': {err}");
+            None
+        }
+    };
+    match app_id.as_deref().map(|id| id.replace('/', "_")) {
+        Some(id) if !id.is_empty() && id != "." && id != ".." => id,
+        _ => "unknown".into(),
+    }
+}
+
+/// Where results are written: stdout, or a file descriptor inherited from
+/// the caller. The descriptor is closed on exec so hooks and editors don't
+/// hold it open.
+pub fn result_output(fd: Option<i32>) -> Result<Box<dyn std::io::Write>, Box<dyn std::error::Error>> {
+    let Some(fd) = fd else {
+        return Ok(Box::new(std::io::stdout()));
+    };
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(format!("--output-fd {fd}: {}", std::io::Error::last_os_error()).into());
+    }
+    // Nothing else in the process uses this descriptor
+    Ok(Box::new(unsafe { File::from_raw_fd(fd) }))
+}
+
+/// Writes `data` into the screenshots directory, creating it and any
+/// directories in `name` if missing, and returns the resulting path.
+pub fn save_to_screenshots(data: &[u8], name: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = paths::screenshots_dir().ok_or("Cannot determine the pictures directory; is $HOME set?")?;
+    let path = dir.join(name);
+    std::fs::create_dir_all(path.parent().unwrap_or(&dir))?;
+    std::fs::write(&path, data)?;
+    Ok(path)
+}
+
+/// Appends a sequence number to a file name, e.g. `shot.png` becomes
+/// `shot-002.png`.
+pub fn numbered(name: &Path, number: u32) -> PathBuf {
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let mut numbered = name.with_file_name(format!("{stem}-{number:03}"));
+    if let Some(extension) = name.extension() {
+        numbered.set_extension(extension);
+    }
+    numbered
+}
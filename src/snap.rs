@@ -0,0 +1,71 @@
+//! Snapping the moving corner of a drag to a grid, window edges or output
+//! edges. Tab cycles through them while dragging, so candidates from
+//! different sources close to each other can be told apart.
+
+use crate::selection::Rect;
+
+/// How close an edge has to be to pull the corner onto it.
+const SNAP_DISTANCE: i32 = 12;
+
+/// What the corner snaps to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Snap {
+    Off,
+    Grid,
+    Windows,
+    Outputs,
+}
+
+const ORDER: [Snap; 4] = [Snap::Off, Snap::Grid, Snap::Windows, Snap::Outputs];
+
+impl Snap {
+    /// The target `steps` further along, wrapping around.
+    pub fn cycle(self, steps: i32) -> Self {
+        let index = ORDER.iter().position(|&snap| snap == self).unwrap_or(0) as i32;
+        ORDER[(index + steps).rem_euclid(ORDER.len() as i32) as usize]
+    }
+
+    /// Parses a target by its name.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        ORDER.into_iter().find(|snap| snap.name() == name)
+            .ok_or_else(|| format!("unknown snap target '{name}', expected off, grid, windows or outputs"))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Snap::Off => "off",
+            Snap::Grid => "grid",
+            Snap::Windows => "windows",
+            Snap::Outputs => "outputs",
+        }
+    }
+}
+
+/// The grid point nearest to `point`, with lines every `spacing` pixels.
+pub fn to_grid((x, y): (i32, i32), spacing: i32) -> (i32, i32) {
+    let round = |v: i32| (v as f64 / spacing as f64).round() as i32 * spacing;
+    (round(x), round(y))
+}
+
+/// `point` moved onto the nearest edge of `rects` on each axis, if one is
+/// within reach.
+pub fn to_edges((x, y): (i32, i32), rects: impl IntoIterator<Item = Rect>) -> (i32, i32) {
+    let (mut best_x, mut best_y) = (None::<i32>, None::<i32>);
+    let consider = |best: &mut Option<i32>, from: i32, edge: i32| {
+        if (edge - from).abs() <= SNAP_DISTANCE && best.is_none_or(|b| (edge - from).abs() < (b - from).abs()) {
+            *best = Some(edge);
+        }
+    };
+    for rect in rects {
+        // Edges only pull along their length
+        if (rect.y - SNAP_DISTANCE..=rect.bottom() + SNAP_DISTANCE).contains(&y) {
+            consider(&mut best_x, x, rect.x);
+            consider(&mut best_x, x, rect.right());
+        }
+        if (rect.x - SNAP_DISTANCE..=rect.right() + SNAP_DISTANCE).contains(&x) {
+            consider(&mut best_y, y, rect.y);
+            consider(&mut best_y, y, rect.bottom());
+        }
+    }
+    (best_x.unwrap_or(x), best_y.unwrap_or(y))
+}
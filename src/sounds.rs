@@ -0,0 +1,66 @@
+//! Sounds played on selection events, configured in the `[sounds]` section,
+//! for when Glimpse is bound to a hotkey and exits before anything can be
+//! seen. A separate player plays them, left running after Glimpse exits.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::paths;
+
+/// Players tried when none is configured, in order.
+const PLAYERS: &[&str] = &["pw-play", "paplay", "aplay"];
+
+/// Where sounds given by name are looked up.
+const THEME_DIR: &str = "/usr/share/sounds/freedesktop/stereo";
+
+#[derive(Debug, Clone, Default)]
+pub struct Sounds {
+    pub player: Option<String>,   // Run through `sh -c` with the file appended
+    pub confirm: Option<PathBuf>, // A selection was confirmed
+    pub cancel: Option<PathBuf>,  // The selection was cancelled
+    pub save: Option<PathBuf>,    // A capture was written
+}
+
+/// Parses a sound file, or the name of one from the freedesktop theme such
+/// as `camera-shutter`. An empty value plays nothing.
+pub fn parse_sound(value: &str) -> Option<PathBuf> {
+    if value.is_empty() {
+        None
+    } else if value.contains('/') {
+        Some(paths::expand_home(value))
+    } else {
+        Some(Path::new(THEME_DIR).join(format!("{value}.oga")))
+    }
+}
+
+/// First known player found in `$PATH`.
+fn detect<'a>() -> Option<&'a str> {
+    let path = std::env::var_os("PATH")?;
+    PLAYERS.iter()
+        .find(|binary| std::env::split_paths(&path).any(|dir| dir.join(binary).is_file()))
+        .copied()
+}
+
+/// Starts playing `sound` without waiting for it to finish. Like hooks,
+/// sounds are best effort: failures are logged, never fatal.
+pub fn play(sounds: &Sounds, sound: &Option<PathBuf>) {
+    let Some(sound) = sound else { return };
+    let Some(player) = sounds.player.as_deref().or_else(detect) else {
+        log::warn!("No sound player found; set one with player in [sounds] (pw-play, paplay or aplay are detected)");
+        return;
+    };
+    // The shell backgrounds the player and exits at once, so it is never
+    // waited for and keeps nothing of Glimpse's open
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{player} \"$1\" &"))
+        .arg("sh")
+        .arg(sound)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    if let Err(err) = status {
+        log::warn!("Failed to play '{}': {err}", sound.display());
+    }
+}
@@ -0,0 +1,128 @@
+//! `--trace-wayland FILE`: every request and event, next to Glimpse's own
+//! log messages, kept in a file for bug reports.
+//!
+//! wayland-backend logs each request it sends, and the handlers in
+//! `wayland::dispatch` log each event they receive. The logger installed
+//! here writes both into the file while a trace runs, and hands everything
+//! else to env_logger as usual.
+
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use wayland_client::Proxy;
+
+use crate::compositor;
+
+/// Target of the events logged by `event`.
+const EVENTS: &str = "glimpse::trace";
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// env_logger, plus the trace file while there is one.
+struct Logger {
+    inner: env_logger::Logger,
+    file: Mutex<Option<BufWriter<File>>>,
+    started: Instant,
+}
+
+/// Installs the logger, configured by `RUST_LOG` like env_logger's own.
+pub fn init() {
+    let logger = LOGGER.get_or_init(|| Logger {
+        inner: env_logger::Logger::from_default_env(),
+        file: Mutex::new(None),
+        started: Instant::now(),
+    });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(logger.inner.filter());
+    }
+}
+
+/// Stops tracing and finishes the file when dropped.
+pub struct Trace(());
+
+/// Starts tracing into `path`. Needs the logger from `init`.
+pub fn start(path: &Path) -> Result<Trace, Box<dyn std::error::Error>> {
+    let logger = LOGGER.get().ok_or("The logger isn't installed")?;
+    let mut file = BufWriter::new(File::create(path).map_err(|err| format!("{}: {err}", path.display()))?);
+    let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let compositor = compositor::name().unwrap_or_else(|| "an unknown compositor".into());
+    writeln!(file, "# Glimpse {} on {compositor}", env!("CARGO_PKG_VERSION"))?;
+    writeln!(file, "# Arguments: {:?}", std::env::args().skip(1).collect::<Vec<_>>())?;
+    writeln!(file, "# Started at {}.{:06} seconds since the epoch; -> are requests, <- events", started.as_secs(), started.subsec_micros())?;
+    *logger.file.lock().unwrap() = Some(file);
+    // Requests are logged at debug level, whatever RUST_LOG says
+    log::set_max_level(LevelFilter::Trace);
+    Ok(Trace(()))
+}
+
+/// Logs an event `proxy` received, for the trace. Costs nothing without one.
+pub fn event<P: Proxy>(proxy: &P, event: &impl Debug) {
+    log::debug!(target: EVENTS, "{} {event:?}", proxy.id());
+}
+
+impl Logger {
+    fn tracing(&self) -> bool {
+        self.file.lock().unwrap().is_some()
+    }
+
+    fn write(&self, line: std::fmt::Arguments) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let elapsed = self.started.elapsed().as_secs_f64();
+            let _ = writeln!(file, "[{elapsed:12.6}] {line}");
+        }
+    }
+}
+
+/// Whether records for `target` are protocol messages rather than log
+/// messages: the events from `event`, and wayland-backend's debug output.
+fn is_protocol(target: &str, level: Level) -> bool {
+    target == EVENTS || (target.starts_with("wayland_backend") && level == Level::Debug)
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if is_protocol(metadata.target(), metadata.level()) {
+            self.tracing()
+        } else {
+            self.inner.enabled(metadata)
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target() == EVENTS {
+            self.write(format_args!("<- {}", record.args()));
+        } else if is_protocol(record.target(), record.level()) {
+            // Only requests: the backend's lines for events lack their names,
+            // so `event` logs those instead
+            if let Some(request) = record.args().to_string().strip_prefix("Sending ") {
+                self.write(format_args!("-> {request}"));
+            }
+        } else if self.inner.matches(record) {
+            self.inner.log(record);
+            self.write(format_args!("{} {}: {}", record.level(), record.target(), record.args()));
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+impl Drop for Trace {
+    fn drop(&mut self) {
+        if let Some(logger) = LOGGER.get() {
+            if let Some(mut file) = logger.file.lock().unwrap().take() {
+                let _ = file.flush();
+            }
+            log::set_max_level(logger.inner.filter());
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! `--upload`: the capture POSTed to an HTTP endpoint as a multipart form,
+//! with the `upload` feature, and the URL it answers with printed.
+//!
+//! The endpoint is a preset for a host that answers with the bare URL, or
+//! any URL set up in the `[upload]` section:
+//!
+//! ```text
+//! [upload]
+//! url = https://example.com/api/upload
+//! field = image                     # Form field holding the file
+//! form = album=screenshots          # Further fields, repeatable
+//! header = Authorization: Bearer 1234
+//! url_key = data.link               # Where the URL is in a JSON answer
+//! ```
+
+/// A host that takes a file and answers with its URL.
+struct Preset {
+    name: &'static str,
+    url: &'static str,
+    field: &'static str,                           // Form field holding the file
+    form: &'static [(&'static str, &'static str)], // Further fields it needs
+}
+
+const PRESETS: &[Preset] = &[
+    Preset { name: "0x0", url: "https://0x0.st", field: "file", form: &[] },
+    Preset { name: "catbox", url: "https://catbox.moe/user/api.php", field: "fileToUpload", form: &[("reqtype", "fileupload")] },
+];
+
+/// Where and how captures are uploaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upload {
+    pub url: String,
+    pub field: String,               // Form field holding the file
+    pub form: Vec<(String, String)>, // Further form fields
+    pub headers: Vec<(String, String)>,
+    pub url_key: Option<String>, // Dotted path to the URL in a JSON answer; the whole body otherwise
+}
+
+impl Default for Upload {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            field: "file".into(),
+            form: Vec::new(),
+            headers: Vec::new(),
+            url_key: None,
+        }
+    }
+}
+
+impl Upload {
+    /// Points the upload at a preset, which also sets its fields, or at an
+    /// HTTP(S) URL.
+    pub fn set_target(&mut self, target: &str) -> Result<(), String> {
+        if let Some(preset) = PRESETS.iter().find(|preset| preset.name == target) {
+            self.url = preset.url.into();
+            self.field = preset.field.into();
+            self.form = preset.form.iter().map(|&(key, value)| (key.into(), value.into())).collect();
+            return Ok(());
+        }
+        if !(target.starts_with("https://") || target.starts_with("http://")) {
+            let presets: Vec<_> = PRESETS.iter().map(|preset| preset.name).collect();
+            return Err(format!("invalid upload target '{target}', expected an http(s) URL or {}", presets.join(", ")));
+        }
+        self.url = target.into();
+        Ok(())
+    }
+
+    /// Adds a `key=value` form field.
+    pub fn add_field(&mut self, spec: &str) -> Result<(), String> {
+        let (key, value) = spec.split_once('=').ok_or_else(|| format!("invalid form field '{spec}', expected KEY=VALUE"))?;
+        self.form.push((key.trim().into(), value.trim().into()));
+        Ok(())
+    }
+
+    /// Adds a `Name: value` header.
+    pub fn add_header(&mut self, spec: &str) -> Result<(), String> {
+        let (name, value) = spec.split_once(':').ok_or_else(|| format!("invalid header '{spec}', expected 'Name: value'"))?;
+        self.headers.push((name.trim().into(), value.trim().into()));
+        Ok(())
+    }
+}
+
+/// Finds the URL in a host's answer.
+#[cfg_attr(not(feature = "upload"), allow(dead_code))]
+fn find_url(body: &str, url_key: Option<&str>) -> Result<String, String> {
+    let url = match url_key {
+        None => body.trim().to_string(),
+        Some(path) => {
+            let json: serde_json::Value = serde_json::from_str(body).map_err(|err| format!("the upload host's answer isn't JSON: {err}"))?;
+            let value = path.split('.').try_fold(&json, |value, key| match key.parse::<usize>() {
+                Ok(index) if value.is_array() => value.get(index),
+                _ => value.get(key),
+            });
+            value.and_then(|value| value.as_str()).ok_or_else(|| format!("no URL at '{path}' in the upload host's answer"))?.to_string()
+        }
+    };
+    if !url.starts_with("http") {
+        let answer: String = body.trim().chars().take(200).collect();
+        return Err(format!("the upload host answered '{answer}' instead of a URL"));
+    }
+    Ok(url)
+}
+
+/// Uploads `data`, named `filename` with type `mime_type`, and returns its URL.
+#[cfg(feature = "upload")]
+pub fn send(upload: &Upload, data: &[u8], filename: &str, mime_type: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let boundary = format!("glimpse-{}-{nanos:x}", std::process::id());
+    let mut body = Vec::with_capacity(data.len() + 1024);
+    for (key, value) in &upload.form {
+        body.extend(format!("--{boundary}\r\nContent-Disposition: form-data; name=\"{key}\"\r\n\r\n{value}\r\n").bytes());
+    }
+    body.extend(format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{filename}\"\r\nContent-Type: {mime_type}\r\n\r\n",
+        upload.field,
+    ).bytes());
+    body.extend_from_slice(data);
+    body.extend(format!("\r\n--{boundary}--\r\n").bytes());
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(60))
+        .user_agent(concat!("glimpse/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let mut request = agent.post(&upload.url).set("Content-Type", &format!("multipart/form-data; boundary={boundary}"));
+    for (name, value) in &upload.headers {
+        request = request.set(name, value);
+    }
+    let response = match request.send_bytes(&body) {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, response)) => {
+            let answer: String = response.into_string().unwrap_or_default().trim().chars().take(200).collect();
+            return Err(format!("{} refused the upload with HTTP {code}: {answer}", upload.url).into());
+        }
+        Err(err) => return Err(format!("Failed to upload to {}: {err}", upload.url).into()),
+    };
+    Ok(find_url(&response.into_string()?, upload.url_key.as_deref())?)
+}
+
+/// Stand-in when built without an HTTP client; `--upload` is refused earlier.
+#[cfg(not(feature = "upload"))]
+pub fn send(_upload: &Upload, _data: &[u8], _filename: &str, _mime_type: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Err("uploading needs Glimpse built with the upload feature".into())
+}
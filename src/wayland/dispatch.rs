@@ -0,0 +1,667 @@
+//! Dispatch implementations for every Wayland interface the app binds.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use wayland_client::{
+    Connection, Dispatch, Proxy, QueueHandle, WEnum
+};
+use wayland_client::protocol::{
+    wl_registry, wl_callback, wl_compositor, wl_subcompositor, wl_subsurface, wl_region, wl_shm,
+    wl_shm_pool, wl_surface, wl_buffer, wl_seat, wl_pointer, wl_keyboard, wl_output
+};
+use wayland_protocols::wp::cursor_shape::v1::client::{
+    wp_cursor_shape_device_v1::{Shape, WpCursorShapeDeviceV1}, wp_cursor_shape_manager_v1::WpCursorShapeManagerV1
+};
+use wayland_protocols::wp::pointer_constraints::zv1::client::{
+    zwp_confined_pointer_v1::ZwpConfinedPointerV1, zwp_locked_pointer_v1::ZwpLockedPointerV1,
+    zwp_pointer_constraints_v1::ZwpPointerConstraintsV1
+};
+use wayland_protocols::wp::relative_pointer::zv1::client::{
+    zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1, zwp_relative_pointer_v1::{self, ZwpRelativePointerV1}
+};
+use wayland_protocols::wp::pointer_gestures::zv1::client::{
+    zwp_pointer_gesture_pinch_v1::{self, ZwpPointerGesturePinchV1}, zwp_pointer_gesture_swipe_v1::{self, ZwpPointerGestureSwipeV1},
+    zwp_pointer_gestures_v1::ZwpPointerGesturesV1
+};
+use wayland_protocols::wp::idle_inhibit::zv1::client::{
+    zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1
+};
+use wayland_protocols::wp::single_pixel_buffer::v1::client::wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1;
+use wayland_protocols::wp::viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter};
+use wayland_protocols::xdg::xdg_output::zv1::client::{
+    zxdg_output_manager_v1::ZxdgOutputManagerV1, zxdg_output_v1::{self, ZxdgOutputV1}
+};
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_shell_v1::ZwlrLayerShellV1;
+use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::{
+    self, ZwlrLayerSurfaceV1
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1}, zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1}, zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1}
+};
+use wayland_protocols::ext::image_capture_source::v1::client::{
+    ext_image_capture_source_v1::ExtImageCaptureSourceV1,
+    ext_output_image_capture_source_manager_v1::ExtOutputImageCaptureSourceManagerV1
+};
+use wayland_protocols::ext::image_copy_capture::v1::client::{
+    ext_image_copy_capture_frame_v1::{self, ExtImageCopyCaptureFrameV1},
+    ext_image_copy_capture_manager_v1::ExtImageCopyCaptureManagerV1,
+    ext_image_copy_capture_session_v1::{self, ExtImageCopyCaptureSessionV1}
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1}, zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1
+};
+
+use crate::app::AppState;
+use crate::capture::{self, Backend, BufferInfo, FrameState};
+use crate::config::Command;
+use crate::keys;
+use crate::graphics::buffer::{allocate_overlay_buffers, overlay_buffer_size, set_frozen};
+use crate::graphics::cursor::{self, Cursor};
+use crate::output::Output;
+use crate::recording::InputEvent;
+use crate::trace;
+
+impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+             _: &Connection,
+             qh: &QueueHandle<Self>,
+    ) {
+        trace::event(registry, &event);
+        match event {
+            wl_registry::Event::Global { name, interface, version } => match interface.as_str() {
+                "wl_compositor" => state.compositor = Some(registry.bind(name, version.min(6), qh, ())),
+                "wl_subcompositor" => state.subcompositor = Some(registry.bind(name, 1, qh, ())),
+                "wl_shm" => state.shm = Some(registry.bind(name, version, qh, ())),
+                "zwlr_layer_shell_v1" => state.layer_shell = Some(registry.bind(name, version, qh, ())),
+                "wl_seat" => state.seat = Some(registry.bind(name, version, qh, ())),
+                "wl_output" => {
+                    let wl_output = registry.bind(name, version.min(4), qh, ());
+                    state.add_overlay(&wl_output, qh);
+                    state.outputs.push(Output::new(name, wl_output));
+                    state.bind_xdg_outputs(qh);
+                }
+                "wp_cursor_shape_manager_v1" => state.cursor_shape_manager = Some(registry.bind(name, 1, qh, ())),
+                "zwp_idle_inhibit_manager_v1" => state.idle_inhibit_manager = Some(registry.bind(name, 1, qh, ())),
+                "zwlr_screencopy_manager_v1" => state.screencopy_manager = Some(registry.bind(name, version.min(3), qh, ())),
+                "ext_output_image_capture_source_manager_v1" => {
+                    state.image_capture_source_manager = Some(registry.bind(name, 1, qh, ()));
+                }
+                "ext_image_copy_capture_manager_v1" => state.image_copy_capture_manager = Some(registry.bind(name, 1, qh, ())),
+                "zwlr_data_control_manager_v1" => state.data_control_manager = Some(registry.bind(name, 1, qh, ())),
+                "zwp_pointer_constraints_v1" => state.pointer_constraints = Some(registry.bind(name, 1, qh, ())),
+                "zwp_relative_pointer_manager_v1" => {
+                    state.relative_pointer_manager = Some(registry.bind(name, 1, qh, ()));
+                    state.bind_relative_pointer(qh);
+                }
+                "zwp_pointer_gestures_v1" => {
+                    state.pointer_gestures = Some(registry.bind(name, 1, qh, ()));
+                    state.bind_gestures(qh);
+                }
+                "wp_viewporter" => state.viewporter = Some(registry.bind(name, 1, qh, ())),
+                "wp_single_pixel_buffer_manager_v1" => state.single_pixel_buffer_manager = Some(registry.bind(name, 1, qh, ())),
+                "zxdg_output_manager_v1" => {
+                    state.xdg_output_manager = Some(registry.bind(name, version.min(3), qh, ()));
+                    state.bind_xdg_outputs(qh);
+                }
+                _ => {}
+            },
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(idx) = state.outputs.iter().position(|o| o.registry_name == name) {
+                    let output = state.outputs.remove(idx);
+                    if let Some(idx) = state.overlays.iter().position(|o| o.output.as_ref() == Some(&output.wl_output)) {
+                        state.overlays.remove(idx).destroy();
+                    }
+                    if let Some(xdg_output) = output.xdg_output {
+                        xdg_output.destroy();
+                    }
+                    if output.wl_output.version() >= 3 {
+                        output.wl_output.release();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        seat: &wl_seat::WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+             _: &Connection,
+             qh: &QueueHandle<Self>,
+    ) {
+        trace::event(seat, &event);
+        if let wl_seat::Event::Capabilities { capabilities: WEnum::Value(caps) } = event {
+            if !caps.contains(wl_seat::Capability::Pointer) {
+                state.remove_pointer();
+            } else if state.pointer.is_none() {
+                state.pointer = Some(seat.get_pointer(qh, ()));
+                state.bind_relative_pointer(qh);
+                state.bind_gestures(qh);
+                state.request_redraw();
+            }
+            if !caps.contains(wl_seat::Capability::Keyboard) {
+                state.remove_keyboard();
+            } else if state.keyboard.is_none() {
+                state.keyboard = Some(seat.get_keyboard(qh, ()));
+                state.request_redraw();
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_pointer::WlPointer, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        pointer: &wl_pointer::WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+             _: &Connection,
+             qh: &QueueHandle<Self>,
+    ) {
+        trace::event(pointer, &event);
+        match event {
+            wl_pointer::Event::Enter { serial, surface, surface_x, surface_y } => {
+                let (x, y) = state.to_global(&surface, (surface_x as i32, surface_y as i32));
+                state.input(InputEvent::Motion(x, y), qh);
+                // Render the crosshair for the scale of the output we entered
+                let scale = state.surface_scale(&surface);
+                state.pointer_surface = Some(surface);
+                if state.config.command == Command::Output {
+                    // Highlight the output that was entered
+                    state.request_redraw();
+                }
+                if state.config.custom_cursor && state.cursor.is_none() {
+                    let cursor_size = state.config.cursor_size.or_else(cursor::theme_size);
+                    state.cursor = state.compositor.as_ref().map(|compositor| Cursor::new(compositor, cursor_size, qh));
+                }
+                if let (Some(cursor), Some(shm)) = (state.cursor.as_mut(), state.shm.as_ref()) {
+                    if let Err(err) = cursor.set_scale(scale, shm, qh) {
+                        log::warn!("Failed to render cursor at scale {scale}: {err}");
+                    }
+                    let (hx, hy) = cursor.hotspot();
+                    pointer.set_cursor(serial, Some(&cursor.surface), hx, hy);
+                } else if let Some(manager) = &state.cursor_shape_manager {
+                    let device = state.cursor_shape_device.get_or_insert_with(|| manager.get_pointer(pointer, qh, ()));
+                    device.set_shape(serial, Shape::Crosshair);
+                }
+            }
+            wl_pointer::Event::Leave { surface, .. } if state.pointer_surface.as_ref() == Some(&surface) => {
+                state.pointer_surface = None;
+            }
+            wl_pointer::Event::Motion { surface_x, surface_y, .. } => {
+                // Motion is relative to the surface the pointer entered (or the
+                // one holding the implicit grab while dragging across outputs)
+                let local = (surface_x as i32, surface_y as i32);
+                let (x, y) = match &state.pointer_surface {
+                    Some(surface) => state.to_global(surface, local),
+                    None => local,
+                };
+                state.input(InputEvent::Motion(x, y), qh);
+            }
+            wl_pointer::Event::Button { button, state: btn_state, .. } => {
+                let pressed = btn_state == WEnum::Value(wl_pointer::ButtonState::Pressed);
+                state.input(InputEvent::Button { button, pressed }, qh);
+            }
+            wl_pointer::Event::Axis { axis: WEnum::Value(axis), value, .. } => match axis {
+                wl_pointer::Axis::HorizontalScroll => state.input(InputEvent::Axis(value, 0.0), qh),
+                wl_pointer::Axis::VerticalScroll => state.input(InputEvent::Axis(0.0, value), qh),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        keyboard: &wl_keyboard::WlKeyboard,
+        event: wl_keyboard::Event,
+        _: &(),
+             _: &Connection,
+             qh: &QueueHandle<Self>,
+    ) {
+        trace::event(keyboard, &event);
+        match event {
+            wl_keyboard::Event::Key { key, state: key_state, .. } => {
+                let pressed = key_state == WEnum::Value(wl_keyboard::KeyState::Pressed);
+                state.input(InputEvent::Key { key, pressed }, qh);
+            }
+            // Keys let go elsewhere send no release
+            wl_keyboard::Event::Leave { .. } => state.set_peek(None),
+            wl_keyboard::Event::Modifiers { mods_depressed, mods_latched, .. } => {
+                state.input(InputEvent::Modifiers(keys::modifiers_from_mask(mods_depressed | mods_latched)), qh);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        wl_output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        _: &(),
+             _: &Connection,
+             _: &QueueHandle<Self>,
+    ) {
+        trace::event(wl_output, &event);
+        let Some(output) = state.outputs.iter_mut().find(|o| &o.wl_output == wl_output) else { return };
+        match event {
+            wl_output::Event::Geometry { x, y, physical_width, physical_height, transform, .. } => {
+                output.position = (x, y);
+                output.physical_size_mm = (physical_width, physical_height);
+                if let WEnum::Value(transform) = transform {
+                    output.transform = transform;
+                }
+            }
+            wl_output::Event::Mode { flags: WEnum::Value(flags), width, height, refresh }
+                if flags.contains(wl_output::Mode::Current) =>
+            {
+                output.mode_size = (width, height);
+                output.refresh_mhz = refresh;
+            }
+            wl_output::Event::Scale { factor } => output.scale = factor,
+            wl_output::Event::Name { name } => output.name = Some(name),
+            wl_output::Event::Description { description } => output.description = Some(description),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZxdgOutputV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        xdg_output: &ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        _: &(),
+             _: &Connection,
+             _: &QueueHandle<Self>,
+    ) {
+        trace::event(xdg_output, &event);
+        let Some(output) = state.outputs.iter_mut().find(|o| o.xdg_output.as_ref() == Some(xdg_output)) else { return };
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => output.logical_position = Some((x, y)),
+            zxdg_output_v1::Event::LogicalSize { width, height } => output.logical_size = Some((width, height)),
+            // wl_output v4 already provides the name; only use xdg-output's as a fallback
+            zxdg_output_v1::Event::Name { name } if output.name.is_none() => output.name = Some(name),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        surf: &ZwlrLayerSurfaceV1,
+        event: <ZwlrLayerSurfaceV1 as wayland_client::Proxy>::Event,
+        _: &(),
+             _: &Connection,
+             qh: &QueueHandle<Self>,
+    ) {
+        trace::event(surf, &event);
+        let Some(idx) = state.overlays.iter().position(|o| &o.layer_surface == surf) else { return };
+        if let zwlr_layer_surface_v1::Event::Configure { serial, width, height } = event {
+            if overlay_buffer_size(width, height).is_none() {
+                surf.ack_configure(serial);
+                // Ask once for the output's own size; a new configure follows
+                let overlay = &state.overlays[idx];
+                let output_size = state.outputs.iter()
+                    .find(|output| overlay.output.as_ref() == Some(&output.wl_output))
+                    .map(|output| output.logical_geometry())
+                    .map(|(_, _, w, h)| (w as u32, h as u32))
+                    .filter(|&(w, h)| overlay_buffer_size(w, h).is_some());
+                match output_size {
+                    Some((w, h)) if !overlay.size_requested => {
+                        log::warn!("Compositor configured an overlay as {width}x{height}; asking for {w}x{h}");
+                        surf.set_size(w, h);
+                        overlay.surface.commit();
+                        state.overlays[idx].size_requested = true;
+                    }
+                    _ => {
+                        let message = format!("compositor configured an overlay as {width}x{height}, which can't be drawn");
+                        state.overlays.remove(idx).destroy();
+                        if state.overlays.is_empty() {
+                            state.error = Some(message);
+                            state.running = false;
+                        } else {
+                            log::error!("{message}; closing it");
+                        }
+                    }
+                }
+                return;
+            }
+            let overlay = &mut state.overlays[idx];
+            let size_changed = overlay.width != width || overlay.height != height;
+            overlay.width = width;
+            overlay.height = height;
+            surf.ack_configure(serial);
+            if size_changed {
+                // Allocate the background and buffers only if size changed
+                allocate_overlay_buffers(overlay, qh);
+                if let Some(frozen) = state.frozen_pixels(&state.overlays[idx]) {
+                    set_frozen(&mut state.overlays[idx], frozen, state.config.blur);
+                }
+            }
+            state.overlays[idx].needs_redraw = true; // Always redraw after configure
+        } else if let zwlr_layer_surface_v1::Event::Closed = event {
+            // The output went away or the compositor dismissed us; keep going on the others
+            state.overlays.remove(idx).destroy();
+            if state.overlays.is_empty() {
+                state.running = false;
+            }
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, usize> for AppState {
+    fn event(
+        state: &mut Self,
+        frame: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        idx: &usize,
+             _: &Connection,
+             qh: &QueueHandle<Self>,
+    ) {
+        trace::event(frame, &event);
+        let Some(capture) = state.captures.get_mut(*idx) else { return };
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer { format: WEnum::Value(format), width, height, stride } => {
+                if capture::prefer_format(capture.info.map(|info| info.format), format, state.config.deep_color) {
+                    capture.info = Some(BufferInfo { format, width, height, stride });
+                }
+                // Before v3 there is no buffer_done; the single shm offer is final
+                if frame.version() < 3 {
+                    capture.start_copy(state.shm.as_ref().unwrap(), qh);
+                }
+            }
+            zwlr_screencopy_frame_v1::Event::BufferDone => capture.start_copy(state.shm.as_ref().unwrap(), qh),
+            zwlr_screencopy_frame_v1::Event::Flags { flags: WEnum::Value(flags) } => {
+                capture.y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert);
+            }
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => capture.state = FrameState::Ready,
+            zwlr_screencopy_frame_v1::Event::Failed => capture.state = FrameState::Failed,
+            _ => {}
+        }
+    }
+}
+
+/// Buffer constraints of an ext-image-copy-capture session, for the
+/// capture at the index in the user data.
+impl Dispatch<ExtImageCopyCaptureSessionV1, usize> for AppState {
+    fn event(
+        state: &mut Self,
+        session: &ExtImageCopyCaptureSessionV1,
+        event: ext_image_copy_capture_session_v1::Event,
+        idx: &usize,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        trace::event(session, &event);
+        let Some(capture) = state.captures.get_mut(*idx) else { return };
+        let Backend::Ext { size, format, .. } = &mut capture.backend else { return };
+        match event {
+            ext_image_copy_capture_session_v1::Event::BufferSize { width, height } => *size = (width, height),
+            ext_image_copy_capture_session_v1::Event::ShmFormat { format: WEnum::Value(offered) }
+                if capture::prefer_format(*format, offered, state.config.deep_color) =>
+            {
+                *format = Some(offered);
+            }
+            ext_image_copy_capture_session_v1::Event::Done => capture.session_done(state.shm.as_ref().unwrap(), qh),
+            ext_image_copy_capture_session_v1::Event::Stopped if capture.state == FrameState::Pending => {
+                capture.state = FrameState::Failed;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureFrameV1, usize> for AppState {
+    fn event(
+        state: &mut Self,
+        frame: &ExtImageCopyCaptureFrameV1,
+        event: ext_image_copy_capture_frame_v1::Event,
+        idx: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        trace::event(frame, &event);
+        let Some(capture) = state.captures.get_mut(*idx) else { return };
+        match event {
+            ext_image_copy_capture_frame_v1::Event::Transform { transform: WEnum::Value(transform) } => {
+                capture.transform = transform;
+            }
+            ext_image_copy_capture_frame_v1::Event::Ready => capture.state = FrameState::Ready,
+            ext_image_copy_capture_frame_v1::Event::Failed { reason } => {
+                log::error!("Screen capture failed: {reason:?}");
+                capture.state = FrameState::Failed;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        device: &ZwlrDataControlDeviceV1,
+        event: zwlr_data_control_device_v1::Event,
+        _: &(),
+             _: &Connection,
+             _: &QueueHandle<Self>,
+    ) {
+        trace::event(device, &event);
+        if let zwlr_data_control_device_v1::Event::Selection { id } = event {
+            if let Some(previous) = std::mem::replace(&mut state.clipboard_offer, id) {
+                previous.destroy();
+            }
+        }
+    }
+
+    wayland_client::event_created_child!(AppState, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, Mutex::new(Vec::new())),
+    ]);
+}
+
+/// Collects the MIME types of an offer, kept as its user data.
+impl Dispatch<ZwlrDataControlOfferV1, Mutex<Vec<String>>> for AppState {
+    fn event(
+        _: &mut Self,
+        offer: &ZwlrDataControlOfferV1,
+        event: zwlr_data_control_offer_v1::Event,
+        mime_types: &Mutex<Vec<String>>,
+             _: &Connection,
+             _: &QueueHandle<Self>,
+    ) {
+        trace::event(offer, &event);
+        if let zwlr_data_control_offer_v1::Event::Offer { mime_type } = event {
+            mime_types.lock().unwrap().push(mime_type);
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlSourceV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        source: &ZwlrDataControlSourceV1,
+        event: zwlr_data_control_source_v1::Event,
+        _: &(),
+             _: &Connection,
+             _: &QueueHandle<Self>,
+    ) {
+        trace::event(source, &event);
+        match event {
+            zwlr_data_control_source_v1::Event::Send { mime_type, fd } => {
+                if let Some(clipboard) = &state.clipboard {
+                    clipboard.send(&mime_type, fd);
+                }
+            }
+            zwlr_data_control_source_v1::Event::Cancelled => {
+                source.destroy();
+                state.clipboard = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Pointer deltas, for slowing the pointer down while Alt is held in a drag.
+impl Dispatch<ZwpRelativePointerV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        relative_pointer: &ZwpRelativePointerV1,
+        event: zwp_relative_pointer_v1::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        trace::event(relative_pointer, &event);
+        if let zwp_relative_pointer_v1::Event::RelativeMotion { dx, dy, .. } = event {
+            state.input(InputEvent::Relative(dx, dy), qh);
+        }
+    }
+}
+
+/// Touchpad pinches, scaling the rectangle being edited around its center.
+impl Dispatch<ZwpPointerGesturePinchV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        pinch: &ZwpPointerGesturePinchV1,
+        event: zwp_pointer_gesture_pinch_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        trace::event(pinch, &event);
+        match event {
+            zwp_pointer_gesture_pinch_v1::Event::Begin { .. } => state.begin_gesture(),
+            zwp_pointer_gesture_pinch_v1::Event::Update { scale, .. } => state.update_gesture(Some(scale), (0.0, 0.0)),
+            zwp_pointer_gesture_pinch_v1::Event::End { cancelled, .. } => state.end_gesture(cancelled != 0),
+            _ => {}
+        }
+    }
+}
+
+/// Touchpad swipes, moving the rectangle being edited.
+impl Dispatch<ZwpPointerGestureSwipeV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        swipe: &ZwpPointerGestureSwipeV1,
+        event: zwp_pointer_gesture_swipe_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        trace::event(swipe, &event);
+        match event {
+            zwp_pointer_gesture_swipe_v1::Event::Begin { .. } => state.begin_gesture(),
+            zwp_pointer_gesture_swipe_v1::Event::Update { dx, dy, .. } => state.update_gesture(None, (dx, dy)),
+            zwp_pointer_gesture_swipe_v1::Event::End { cancelled, .. } => state.end_gesture(cancelled != 0),
+            _ => {}
+        }
+    }
+}
+
+/// Preferred scale and transform of our surfaces, from wl_surface v6. The
+/// cursor follows both; overlays draw upright, so only their scale is kept.
+impl Dispatch<wl_surface::WlSurface, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        surface: &wl_surface::WlSurface,
+        event: wl_surface::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        trace::event(surface, &event);
+        let Some(shm) = state.shm.as_ref() else { return };
+        let cursor = state.cursor.as_mut().filter(|cursor| &cursor.surface == surface);
+        match event {
+            wl_surface::Event::PreferredBufferScale { factor } => {
+                if let Some(cursor) = cursor {
+                    if let Err(err) = cursor.set_scale(factor, shm, qh) {
+                        log::warn!("Failed to render cursor at scale {factor}: {err}");
+                    }
+                } else if let Some(overlay) = state.overlays.iter_mut().find(|o| &o.surface == surface) {
+                    overlay.preferred_scale = Some(factor);
+                }
+            }
+            wl_surface::Event::PreferredBufferTransform { transform: WEnum::Value(transform) } => {
+                if let Some(cursor) = cursor {
+                    if let Err(err) = cursor.set_transform(transform, shm, qh) {
+                        log::warn!("Failed to render cursor for transform {transform:?}: {err}");
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Buffers whose user data tracks whether the compositor still holds them.
+impl Dispatch<wl_buffer::WlBuffer, AtomicBool> for AppState {
+    fn event(
+        _: &mut Self,
+        buffer: &wl_buffer::WlBuffer,
+        event: wl_buffer::Event,
+        busy: &AtomicBool,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        trace::event(buffer, &event);
+        if let wl_buffer::Event::Release = event {
+            busy.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Frame callbacks, requested by overlays that are animating.
+impl Dispatch<wl_callback::WlCallback, wl_surface::WlSurface> for AppState {
+    fn event(
+        state: &mut Self,
+        callback: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        surface: &wl_surface::WlSurface,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        trace::event(callback, &event);
+        if let wl_callback::Event::Done { .. } = event {
+            if let Some(overlay) = state.overlays.iter_mut().find(|o| &o.surface == surface) {
+                overlay.needs_redraw = true;
+            }
+        }
+    }
+}
+
+// No-op handlers for interfaces we don't need to react to
+macro_rules! noop_dispatch {
+    ($($iface:ty),*) => {
+        $(impl Dispatch<$iface, ()> for AppState {
+            fn event(_: &mut Self, proxy: &$iface, event: <$iface as wayland_client::Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+                trace::event(proxy, &event);
+            }
+        })*
+    };
+}
+noop_dispatch!(
+    wl_compositor::WlCompositor, wl_shm::WlShm, wl_shm_pool::WlShmPool,
+    wl_buffer::WlBuffer, ZwlrLayerShellV1, ZxdgOutputManagerV1,
+    WpCursorShapeManagerV1, WpCursorShapeDeviceV1, ZwpIdleInhibitManagerV1, ZwpIdleInhibitorV1,
+    ZwlrScreencopyManagerV1, ZwlrDataControlManagerV1, wl_subcompositor::WlSubcompositor,
+    wl_subsurface::WlSubsurface, wl_region::WlRegion, WpViewporter, WpViewport, WpSinglePixelBufferManagerV1,
+    ExtOutputImageCaptureSourceManagerV1, ExtImageCaptureSourceV1, ExtImageCopyCaptureManagerV1,
+    ZwpPointerConstraintsV1, ZwpConfinedPointerV1, ZwpLockedPointerV1, ZwpRelativePointerManagerV1,
+    ZwpPointerGesturesV1
+);
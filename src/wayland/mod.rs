@@ -0,0 +1,3 @@
+//! Wayland protocol glue.
+
+mod dispatch;
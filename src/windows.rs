@@ -0,0 +1,162 @@
+//! Toplevel windows and where they are, read over the compositor's IPC since
+//! Wayland does not tell clients about other clients' windows.
+
+use std::io::Write;
+use std::process::Command;
+
+use serde_json::{json, Value};
+
+use crate::compositor;
+use crate::output::{self, Output};
+use crate::selection::Rect;
+
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub app_id: String,
+    pub title: String,
+    pub rect: Rect, // Global logical coordinates, without decorations
+    pub focused: bool,
+}
+
+impl Window {
+    /// `app_id — title`, or whichever of the two is set.
+    pub fn label(&self) -> String {
+        match (self.app_id.is_empty(), self.title.is_empty()) {
+            (false, false) => format!("{} — {}", self.app_id, self.title),
+            (false, true) => self.app_id.clone(),
+            _ => self.title.clone(),
+        }
+    }
+}
+
+/// Windows currently shown on any output, topmost first.
+pub fn visible() -> Result<Vec<Window>, String> {
+    match compositor::name().as_deref() {
+        Some("Hyprland") => hyprland(),
+        Some("sway") => sway(),
+        Some(other) => Err(format!("listing windows is not supported on {other}, only on Hyprland and sway")),
+        None => Err("listing windows needs Hyprland or sway".into()),
+    }
+}
+
+/// Prints every window with its geometry and the output showing most of
+/// it, topmost first, one per line or as a JSON array.
+pub fn list(out: &mut dyn Write, windows: &[Window], outputs: &[Output], json: bool) -> std::io::Result<()> {
+    let output_name = |window: &Window| output::containing(outputs, &window.rect).and_then(|output| output.name.as_deref());
+    if json {
+        let list: Vec<_> = windows.iter()
+            .map(|window| {
+                let Rect { x, y, width, height } = window.rect;
+                json!({
+                    "app_id": window.app_id,
+                    "title": window.title,
+                    "x": x, "y": y, "width": width, "height": height,
+                    "output": output_name(window),
+                    "focused": window.focused,
+                })
+            })
+            .collect();
+        return writeln!(out, "{}", Value::Array(list));
+    }
+    for window in windows {
+        let Rect { x, y, width, height } = window.rect;
+        writeln!(
+            out,
+            "{} {:<20}  {:<10}  {}",
+            if window.focused { '*' } else { ' ' },
+            format!("{x},{y} {width}x{height}"),
+            output_name(window).unwrap_or("-"),
+            window.label(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Runs an IPC command and parses its JSON output.
+fn query(program: &str, args: &[&str]) -> Result<Value, String> {
+    let output = Command::new(program).args(args).output()
+        .map_err(|err| format!("failed to run {program}: {err}"))?;
+    if !output.status.success() {
+        return Err(format!("{program} {} failed: {}", args.join(" "), output.status));
+    }
+    serde_json::from_slice(&output.stdout).map_err(|err| format!("unexpected output from {program}: {err}"))
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+fn pair(value: &Value) -> Option<(i32, i32)> {
+    Some((value[0].as_i64()? as i32, value[1].as_i64()? as i32))
+}
+
+fn hyprland() -> Result<Vec<Window>, String> {
+    let monitors = query("hyprctl", &["monitors", "-j"])?;
+    let monitors = monitors.as_array().map(Vec::as_slice).unwrap_or_default();
+    // Windows on hidden workspaces keep their geometry, so only those on a
+    // workspace shown by some monitor count
+    let shown: Vec<i64> = monitors.iter()
+        .flat_map(|monitor| [&monitor["activeWorkspace"]["id"], &monitor["specialWorkspace"]["id"]])
+        .filter_map(Value::as_i64)
+        .filter(|&id| id != 0)
+        .collect();
+
+    let clients = query("hyprctl", &["clients", "-j"])?;
+    let mut clients: Vec<&Value> = clients.as_array().map(Vec::as_slice).unwrap_or_default().iter()
+        .filter(|client| client["mapped"].as_bool() == Some(true) && client["hidden"].as_bool() != Some(true))
+        .filter(|client| client["workspace"]["id"].as_i64().is_some_and(|id| shown.contains(&id)))
+        .collect();
+    // Floating windows are above tiled ones, and the more recently focused
+    // above the rest
+    clients.sort_by_key(|client| {
+        (!client["floating"].as_bool().unwrap_or(false), client["focusHistoryID"].as_i64().unwrap_or(i64::MAX))
+    });
+    Ok(clients.into_iter()
+        .filter_map(|client| {
+            let (x, y) = pair(&client["at"])?;
+            let (width, height) = pair(&client["size"])?;
+            Some(Window {
+                app_id: text(&client["class"]),
+                title: text(&client["title"]),
+                rect: Rect::new(x, y, width, height),
+                focused: client["focusHistoryID"].as_i64() == Some(0),
+            })
+        })
+        .collect())
+}
+
+fn sway() -> Result<Vec<Window>, String> {
+    let tree = query("swaymsg", &["-t", "get_tree", "--raw"])?;
+    let mut windows = Vec::new();
+    collect_sway(&tree, &mut windows);
+    Ok(windows)
+}
+
+/// Appends the visible views under a sway tree node, floating ones before
+/// the tiled ones they cover.
+fn collect_sway(node: &Value, windows: &mut Vec<Window>) {
+    if node["pid"].is_number() {
+        if node["visible"].as_bool() == Some(true) {
+            let rect = &node["rect"];
+            let window = &node["window_rect"];
+            let coordinate = |value: &Value| value.as_i64().unwrap_or_default() as i32;
+            let app_id = node["app_id"].as_str().or(node["window_properties"]["class"].as_str());
+            windows.push(Window {
+                app_id: app_id.unwrap_or_default().to_string(),
+                title: text(&node["name"]),
+                rect: Rect::new(
+                    coordinate(&rect["x"]) + coordinate(&window["x"]),
+                    coordinate(&rect["y"]) + coordinate(&window["y"]),
+                    coordinate(&window["width"]),
+                    coordinate(&window["height"]),
+                ),
+                focused: node["focused"].as_bool() == Some(true),
+            });
+        }
+        return;
+    }
+    let children = |key: &str| node[key].as_array().map(Vec::as_slice).unwrap_or_default();
+    for child in children("floating_nodes").iter().chain(children("nodes")) {
+        collect_sway(child, windows);
+    }
+}